@@ -0,0 +1,292 @@
+use super::{IBroker, UnknownActions, LINE};
+use crate::error::Error;
+use crate::schema::{Action, Column, Country, Currency, Type};
+
+use anyhow::{Context, Result};
+use polars::prelude::*;
+use std::path::Path;
+
+/// Withholding tax of the "Juros Sobre Capital Próprio", B3 reports the net value.
+const JCP_TAX_RATE: f64 = 0.15;
+const JCP: &str = "Juros Sobre Capital Próprio";
+
+/// Columns of the "Negociação" export.
+const TRADE_COLUMNS: [&str; 6] = [
+    "Data do Negócio",
+    "Tipo de Movimentação",
+    "Código de Negociação",
+    "Quantidade",
+    "Preço",
+    "Valor",
+];
+
+/// Columns of the "Movimentação" export.
+const EVENT_COLUMNS: [&str; 5] = [
+    "Data",
+    "Movimentação",
+    "Produto",
+    "Quantidade",
+    "Preço unitário",
+];
+
+/// Reads the "Negociação" and "Movimentação" exports of the B3 investor portal, saved as csv.
+///
+/// The settlement transfers of the "Movimentação" are ignored, the trades come from the
+/// "Negociação". Shares received in a "Desdobramento" are bought at zero cost, keeping the cost
+/// of the position, and a "Bonificação" is bought at the unit cost declared by the company.
+pub struct B3 {
+    unknown: UnknownActions,
+    account: Option<String>,
+}
+
+impl Default for B3 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl B3 {
+    pub fn new() -> Self {
+        Self {
+            unknown: UnknownActions::default(),
+            account: None,
+        }
+    }
+
+    /// Map unknown actions to `Action::Ignore` instead of failing.
+    pub fn with_lenient(mut self, lenient: bool) -> Self {
+        self.unknown = UnknownActions::new(lenient);
+        self
+    }
+
+    /// Name of the account in the `Account` column, defaults to the broker name.
+    pub fn with_account(mut self, account: impl Into<String>) -> Self {
+        self.account = Some(account.into());
+        self
+    }
+
+    fn map_trade(s: &str) -> Option<Action> {
+        match s {
+            "Compra" => Some(Action::Buy),
+            "Venda" => Some(Action::Sell),
+            _ => None,
+        }
+    }
+
+    fn map_event(s: &str) -> Option<Action> {
+        Some(match s {
+            "Rendimento" | "Dividendo" | JCP => Action::Dividend,
+            "Desdobramento" | "Bonificação em Ativos" => Action::Buy,
+            // Position updates after an event and settlements of the trades.
+            "Atualização" | "Transferência - Liquidação" | "Transferência" => Action::Ignore,
+            _ => return None,
+        })
+    }
+
+    /// Parses "1.234,56", "R$ 1.234,56" or "1234.56", a "-" is an empty value.
+    fn cast_to_float(column: &str) -> Expr {
+        let value = col(column)
+            .str()
+            .replace_all(lit(r"[^\d,.]"), lit(""), false);
+        when(value.clone().str().contains_literal(lit(",")))
+            .then(
+                value
+                    .clone()
+                    .str()
+                    .replace_all(lit("."), lit(""), true)
+                    .str()
+                    .replace_all(lit(","), lit("."), true),
+            )
+            .otherwise(value)
+            .cast(DataType::Float64)
+    }
+
+    fn to_date(column: &str) -> Expr {
+        col(column)
+            .str()
+            .to_datetime(
+                None,
+                None,
+                StrptimeOptions {
+                    format: Some("%d/%m/%Y".into()),
+                    ..StrptimeOptions::default()
+                },
+                lit("raise"),
+            )
+            .cast(DataType::Date)
+            .alias(Column::Date.into())
+    }
+
+    /// Units and real estate funds are traded with the suffix 11, stocks with 3 to 8.
+    fn asset_type() -> Expr {
+        when(col(Column::Ticker.into()).str().ends_with(lit("11")))
+            .then(lit(Type::Fii.as_str()))
+            .otherwise(lit(Type::Stock.as_str()))
+            .alias(Column::Type.into())
+    }
+
+    fn read(file: &Path) -> Result<DataFrame> {
+        let content = std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read B3 export {:?}", file))?;
+        let separator = if content.lines().next().unwrap_or_default().contains(';') {
+            b';'
+        } else {
+            b','
+        };
+        Ok(LazyCsvReader::new(file)
+            .has_header(true)
+            .with_separator(separator)
+            .with_infer_schema_length(Some(0))
+            .finish()?
+            .with_row_index(LINE, Some(2))
+            .collect()?)
+    }
+
+    fn load_trades(&self, file: &Path, df: DataFrame) -> Result<LazyFrame> {
+        super::ensure_columns(file, &df.get_column_names(), &TRADE_COLUMNS)?;
+        let action = self
+            .unknown
+            .map_column(file, &df, "Tipo de Movimentação", Self::map_trade)?;
+
+        Ok(df.lazy().select([
+            Self::to_date("Data do Negócio"),
+            action.alias(Column::Action.into()),
+            // The fractional market adds a F to the ticker.
+            col("Código de Negociação")
+                .str()
+                .replace(lit(r"^(\w{4}\d{1,2})F$"), lit("$1"), false)
+                .alias(Column::Ticker.into()),
+            Self::cast_to_float("Quantidade").alias(Column::Qty.into()),
+            Self::cast_to_float("Preço").alias(Column::Price.into()),
+            Self::cast_to_float("Valor").alias(Column::Amount.into()),
+        ]))
+    }
+
+    fn load_events(&self, file: &Path, df: DataFrame) -> Result<LazyFrame> {
+        super::ensure_columns(file, &df.get_column_names(), &EVENT_COLUMNS)?;
+        let action = self
+            .unknown
+            .map_column(file, &df, "Movimentação", Self::map_event)?;
+        let amount = if df.get_column_names().contains(&"Valor da Operação") {
+            Self::cast_to_float("Valor da Operação")
+        } else {
+            lit(NULL).cast(DataType::Float64)
+        };
+
+        let qty = Self::cast_to_float("Quantidade").fill_null(lit(0.0));
+        let price = when(col("Movimentação").eq(lit("Desdobramento")))
+            .then(lit(0.0))
+            .otherwise(Self::cast_to_float("Preço unitário").fill_null(lit(0.0)));
+        let events = df.lazy().select([
+            Self::to_date("Data"),
+            action.alias(Column::Action.into()),
+            col("Produto")
+                .str()
+                .extract(lit(r"^(\S+)"), 1)
+                .alias(Column::Ticker.into()),
+            qty.clone().alias(Column::Qty.into()),
+            price.clone().alias(Column::Price.into()),
+            amount.fill_null(qty * price).alias(Column::Amount.into()),
+            col("Movimentação").eq(lit(JCP)).alias(JCP),
+        ]);
+
+        // The JCP is reported net, it's split in the gross dividend and the withheld tax.
+        let gross = (col(Column::Amount.into()) / lit(1.0 - JCP_TAX_RATE)).round(2);
+        let dividends = events.clone().with_column(
+            when(col(JCP))
+                .then(gross.clone())
+                .otherwise(col(Column::Amount.into()))
+                .alias(Column::Amount.into()),
+        );
+        let tax = (gross - col(Column::Amount.into())).round(2);
+        let taxes = events.filter(col(JCP)).with_columns([
+            lit(Action::Tax.as_str()).alias(Column::Action.into()),
+            lit(1.0).alias(Column::Qty.into()),
+            tax.clone().alias(Column::Price.into()),
+            tax.alias(Column::Amount.into()),
+        ]);
+        Ok(concat([dividends, taxes], Default::default())?.drop([JCP]))
+    }
+}
+
+impl IBroker for B3 {
+    fn name(&self) -> &'static str {
+        "B3"
+    }
+
+    fn account(&self) -> &str {
+        self.account.as_deref().unwrap_or(self.name())
+    }
+
+    fn load_from_csv(&self, csv_file: &Path) -> Result<DataFrame> {
+        let df = Self::read(csv_file)?;
+        let df = if df.get_column_names().contains(&"Movimentação") {
+            self.load_events(csv_file, df)?
+        } else {
+            self.load_trades(csv_file, df)?
+        }
+        .with_columns([
+            lit(0.0).alias(Column::Tax.into()),
+            lit(0.0).alias(Column::Commission.into()),
+            lit(Country::Brazil.as_str()).alias(Column::Country.into()),
+            lit(Currency::BRL.as_str()).alias(Column::Currency.into()),
+            Self::asset_type(),
+        ]);
+
+        Ok(self.sanitize(df).collect()?)
+    }
+
+    fn load_from_api(&self, _path: Option<&Path>) -> Result<DataFrame> {
+        anyhow::bail!("B3 has no api")
+    }
+
+    fn unknown_actions(&self) -> Vec<Error> {
+        self.unknown.take()
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+    use super::*;
+    use crate::utils;
+    use std::fs::File;
+
+    #[test]
+    fn load_dir_success() {
+        let input_dir = Path::new("resources/tests/input/b3");
+        let reference_output = Path::new("resources/tests/b3_dir_success.csv");
+        let output = Path::new("target/b3_dir_result.csv");
+
+        let mut df = B3::default().load_from_dir(input_dir).unwrap();
+
+        let mut file = File::create(output).expect("could not create file");
+        CsvWriter::new(&mut file)
+            .include_header(true)
+            .with_separator(b',')
+            .finish(&mut df)
+            .unwrap();
+
+        assert!(
+            utils::test::fs::compare_files(reference_output, output).unwrap(),
+            "Run the command to check the diff: meld {} {}",
+            reference_output.as_os_str().to_str().unwrap(),
+            output.as_os_str().to_str().unwrap()
+        );
+    }
+
+    #[test]
+    fn load_unknown_event_fails() {
+        let file = temp_file::with_contents(
+            "Entrada/Saída;Data;Movimentação;Produto;Instituição;Quantidade;Preço unitário;Valor da Operação\n\
+             Credito;20/04/2023;Dividendo;PETR4 - PETROBRAS;XP;55;1,00;55,00\n\
+             Credito;21/04/2023;Leilão de Fração;PETR4 - PETROBRAS;XP;-;-;12,00\n"
+                .as_bytes(),
+        );
+        let error = B3::default().load_from_csv(file.path()).unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::UnknownAction { line: 3, .. })
+        ));
+    }
+}
@@ -0,0 +1,408 @@
+use super::{IBroker, UnknownActions, LINE};
+use crate::error::Error;
+use crate::schema::{self, Action, Column, Type};
+use crate::utils;
+
+use anyhow::{Context, Result};
+use polars::prelude::*;
+use regex::RegexSet;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+const ISIN: &str = "ISIN";
+
+/// Degiro exports the same layout in every language, so the columns are addressed by position.
+mod transactions {
+    pub const DATE: &str = "column_1";
+    pub const ISIN: &str = "column_4";
+    pub const QTY: &str = "column_7";
+    pub const PRICE: &str = "column_8";
+    pub const CURRENCY: &str = "column_9";
+    pub const LOCAL_VALUE: &str = "column_10";
+    pub const EXCHANGE_RATE: &str = "column_14";
+    pub const FEES: &str = "column_15";
+    pub const FEES_CURRENCY: &str = "column_16";
+}
+
+mod account {
+    pub const DATE: &str = "column_1";
+    pub const ISIN: &str = "column_5";
+    pub const DESCRIPTION: &str = "column_6";
+    pub const CURRENCY: &str = "column_8";
+    pub const CHANGE: &str = "column_9";
+}
+
+/// Description patterns in English, Dutch and Portuguese, the first match wins.
+const ACTIONS: &[(&str, Action)] = &[
+    (
+        r"(?i)^(dividend tax|dividendbelasting|imposto sobre dividendo)",
+        Action::Tax,
+    ),
+    (r"(?i)^(dividend|dividendo)$", Action::Dividend),
+    (
+        r"(?i)(connection fee|aansluitingskosten|custo de conectividade)",
+        Action::Fee,
+    ),
+    // Trades and their fees are loaded from the transactions export.
+    (
+        r"(?i)^(buy|sell|koop|verkoop|compra|venda) ",
+        Action::Ignore,
+    ),
+    (
+        r"(?i)(transaction and/or third party|transactiekosten|comiss[õo]es de transa[çc][ãa]o)",
+        Action::Ignore,
+    ),
+    (
+        r"(?i)(currency conversion|fx (debit|credit)|valuta (debitering|creditering)|(d[ée]bito|cr[ée]dito) de divisa)",
+        Action::Ignore,
+    ),
+    (
+        r"(?i)(withdrawal|opname|terugstorting|levantamento)",
+        Action::Withdraw,
+    ),
+    (r"(?i)(deposit|storting|dep[óo]sito)", Action::Deposit),
+    (r"(?i)^(interest|rente|juros)", Action::Interest),
+];
+
+#[derive(Default)]
+pub struct Degiro {
+    tickers: HashMap<String, String>,
+    unknown: UnknownActions,
+    account: Option<String>,
+}
+
+impl Degiro {
+    /// Degiro only exports the ISIN, `tickers` maps it to the symbol used by the scraper.
+    pub fn new(tickers: HashMap<String, String>) -> Self {
+        Self {
+            tickers,
+            unknown: UnknownActions::default(),
+            account: None,
+        }
+    }
+
+    /// Map unknown descriptions to `Action::Ignore` instead of failing.
+    pub fn with_lenient(mut self, lenient: bool) -> Self {
+        self.unknown = UnknownActions::new(lenient);
+        self
+    }
+
+    /// Name of the account in the `Account` column, defaults to the broker name.
+    pub fn with_account(mut self, account: impl Into<String>) -> Self {
+        self.account = Some(account.into());
+        self
+    }
+
+    pub fn tickers_from_file(file: &PathBuf) -> Result<HashMap<String, String>> {
+        let file_content = std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read Degiro tickers {:?}", file))?;
+        serde_json::from_str(&file_content)
+            .with_context(|| format!("Failed to deserialize Degiro tickers {:?}", file))
+    }
+
+    fn map_action(s: &str) -> Option<Action> {
+        static PATTERNS: OnceLock<RegexSet> = OnceLock::new();
+        let patterns = PATTERNS.get_or_init(|| {
+            RegexSet::new(ACTIONS.iter().map(|(pattern, _)| pattern)).expect("Invalid pattern")
+        });
+        patterns
+            .matches(s)
+            .into_iter()
+            .next()
+            .map(|index| ACTIONS[index].1)
+    }
+
+    fn cast_to_float(column: &str) -> Expr {
+        col(column)
+            .str()
+            .replace_all(lit(","), lit("."), true)
+            .cast(DataType::Float64)
+    }
+
+    fn cast_abs_to_float(column: &str) -> Expr {
+        col(column)
+            .str()
+            .replace(lit("^-"), lit(""), false)
+            .str()
+            .replace_all(lit(","), lit("."), true)
+            .cast(DataType::Float64)
+    }
+
+    fn str_to_date(column: &str) -> Expr {
+        col(column)
+            .str()
+            .to_datetime(
+                None,
+                None,
+                StrptimeOptions {
+                    format: Some("%d-%m-%Y".to_owned()),
+                    ..StrptimeOptions::default()
+                },
+                lit("raise"),
+            )
+            .cast(DataType::Date)
+    }
+
+    fn country() -> Expr {
+        utils::polars::map_str_column(ISIN, |isin| match isin {
+            Some(isin) if isin.len() > 2 => schema::Country::from_isin(isin).into(),
+            _ => schema::Country::Unknown.into(),
+        })
+    }
+
+    /// Replaces the ISIN by the ticker when it is known, `CASH` when the row has no product.
+    fn with_ticker(&self, frame: LazyFrame) -> Result<LazyFrame> {
+        let (isin, ticker): (Vec<_>, Vec<_>) = self
+            .tickers
+            .iter()
+            .map(|(isin, ticker)| (isin.as_str(), ticker.as_str()))
+            .unzip();
+        let tickers = df!(
+            ISIN => isin,
+            Column::Ticker.into() => ticker,
+        )?
+        .lazy();
+
+        Ok(frame
+            .join(
+                tickers,
+                [col(ISIN)],
+                [col(ISIN)],
+                JoinArgs::new(JoinType::Left),
+            )
+            .with_column(
+                col(Column::Ticker.into())
+                    .fill_null(col(ISIN))
+                    .fill_null(lit("CASH"))
+                    .alias(Column::Ticker.into()),
+            ))
+    }
+
+    fn read_csv(csv_file: &Path) -> Result<LazyFrame> {
+        Ok(LazyCsvReader::new(csv_file)
+            .has_header(false)
+            .with_skip_rows(1)
+            .with_infer_schema_length(Some(0))
+            .finish()?)
+    }
+
+    fn load_transactions(&self, csv_file: &Path) -> Result<LazyFrame> {
+        let df = Self::read_csv(csv_file)?
+            .select([
+                Self::str_to_date(transactions::DATE).alias(Column::Date.into()),
+                col(transactions::ISIN).alias(ISIN),
+                when(col(transactions::QTY).str().starts_with(lit("-")))
+                    .then(lit(Action::Sell.as_str()))
+                    .otherwise(lit(Action::Buy.as_str()))
+                    .alias(Column::Action.into()),
+                Self::cast_abs_to_float(transactions::QTY).alias(Column::Qty.into()),
+                Self::cast_to_float(transactions::PRICE).alias(Column::Price.into()),
+                Self::cast_abs_to_float(transactions::LOCAL_VALUE).alias(Column::Amount.into()),
+                // The fees are charged in the account currency, convert them to the local currency.
+                when(col(transactions::FEES_CURRENCY).eq(col(transactions::CURRENCY)))
+                    .then(Self::cast_abs_to_float(transactions::FEES))
+                    .otherwise(
+                        Self::cast_abs_to_float(transactions::FEES)
+                            * Self::cast_to_float(transactions::EXCHANGE_RATE).fill_null(lit(1)),
+                    )
+                    .fill_null(lit(0.0))
+                    .alias(Column::Commission.into()),
+                col(transactions::CURRENCY).alias(Column::Currency.into()),
+            ])
+            .with_columns([
+                lit(0.0).alias(Column::Tax.into()),
+                Self::country().alias(Column::Country.into()),
+                lit(Type::Stock.to_string()).alias(Column::Type.into()),
+            ]);
+
+        self.with_ticker(df)
+    }
+
+    fn load_account(&self, csv_file: &Path) -> Result<LazyFrame> {
+        let df = Self::read_csv(csv_file)?
+            .with_row_index(LINE, Some(2))
+            .filter(col(account::CHANGE).is_not_null())
+            .collect()?;
+        let action =
+            self.unknown
+                .map_column(csv_file, &df, account::DESCRIPTION, Self::map_action)?;
+
+        let df = df
+            .lazy()
+            .select([
+                Self::str_to_date(account::DATE).alias(Column::Date.into()),
+                col(account::ISIN).alias(ISIN),
+                action.alias(Column::Action.into()),
+                Self::cast_to_float(account::CHANGE).alias(Column::Amount.into()),
+                col(account::CURRENCY).alias(Column::Currency.into()),
+            ])
+            .filter(
+                col(Column::Action.into())
+                    .neq(lit(Action::Ignore.as_str()))
+                    .and(col(Column::Amount.into()).neq(lit(0))),
+            )
+            .with_column(
+                // Taxes, fees and withdrawals are negative in the statement.
+                when(
+                    col(Column::Action.into())
+                        .is_in(lit(Series::new(
+                            "negative",
+                            [Action::Tax, Action::Fee, Action::Withdraw].map(Action::as_str),
+                        )))
+                        .and(col(Column::Amount.into()).lt(lit(0))),
+                )
+                .then(col(Column::Amount.into()) * lit(-1))
+                .otherwise(col(Column::Amount.into()))
+                .alias(Column::Amount.into()),
+            )
+            .with_columns([
+                lit(1.0).alias(Column::Qty.into()),
+                col(Column::Amount.into()).alias(Column::Price.into()),
+                lit(0.0).alias(Column::Tax.into()),
+                lit(0.0).alias(Column::Commission.into()),
+                Self::country().alias(Column::Country.into()),
+                lit(Type::Stock.to_string()).alias(Column::Type.into()),
+            ]);
+
+        self.with_ticker(df)
+    }
+}
+
+impl IBroker for Degiro {
+    fn name(&self) -> &'static str {
+        "Degiro"
+    }
+
+    fn account(&self) -> &str {
+        self.account.as_deref().unwrap_or(self.name())
+    }
+
+    fn load_from_csv(&self, csv_file: &Path) -> Result<DataFrame> {
+        // Both exports start with the date, but the ISIN is at a different position.
+        let header = std::fs::read_to_string(csv_file)
+            .with_context(|| format!("{:?}", csv_file))?
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_owned();
+        let df = match header.split(',').position(|x| x.trim_matches('"') == ISIN) {
+            Some(3) => self.load_transactions(csv_file)?,
+            Some(4) => self.load_account(csv_file)?,
+            _ => anyhow::bail!("Unknown Degiro export {:?}", csv_file),
+        };
+
+        Ok(self.sanitize(df).collect()?)
+    }
+
+    fn load_from_api(&self, _path: Option<&Path>) -> Result<DataFrame> {
+        anyhow::bail!("Degiro has no api")
+    }
+
+    fn unknown_actions(&self) -> Vec<Error> {
+        self.unknown.take()
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+
+    use super::*;
+    use crate::utils;
+    use std::fs::File;
+    use std::path::Path;
+
+    #[test]
+    fn load_dir_success() {
+        let input_dir = Path::new("resources/tests/input/degiro");
+        let reference_output = Path::new("resources/tests/degiro_dir_success.csv");
+        let output = Path::new("target/degiro_dir_result.csv");
+
+        let tickers = HashMap::from([
+            ("US0378331005".to_owned(), "AAPL".to_owned()),
+            ("IE00B3RBWM25".to_owned(), "VWRL".to_owned()),
+        ]);
+        let mut df = Degiro::new(tickers).load_from_dir(input_dir).unwrap();
+
+        let mut file = File::create(output).expect("could not create file");
+        CsvWriter::new(&mut file)
+            .include_header(true)
+            .with_separator(b',')
+            .finish(&mut df)
+            .unwrap();
+
+        assert!(
+            utils::test::fs::compare_files(reference_output, output).unwrap(),
+            "Run the command to check the diff: meld {} {}",
+            reference_output.as_os_str().to_str().unwrap(),
+            output.as_os_str().to_str().unwrap()
+        );
+    }
+
+    #[test]
+    fn map_action_languages() {
+        for (description, action) in [
+            ("Dividend", Action::Dividend),
+            ("Dividendo", Action::Dividend),
+            ("Dividend Tax", Action::Tax),
+            ("Dividendbelasting", Action::Tax),
+            ("Imposto sobre dividendo", Action::Tax),
+            (
+                "DEGIRO Exchange Connection Fee 2023 (New York Stock Exchange - NYSE)",
+                Action::Fee,
+            ),
+            (
+                "DEGIRO Aansluitingskosten 2023 (Euronext Amsterdam - EAM)",
+                Action::Fee,
+            ),
+            (
+                "Custo de Conectividade DEGIRO 2023 (Nasdaq - NDQ)",
+                Action::Fee,
+            ),
+            ("iDEAL storting", Action::Deposit),
+            ("Depósito", Action::Deposit),
+            ("Levantamento", Action::Withdraw),
+            ("Valuta Debitering", Action::Ignore),
+            ("Koop 10 Apple Inc@150 USD (US0378331005)", Action::Ignore),
+        ] {
+            assert_eq!(
+                Degiro::map_action(description).map(Action::as_str),
+                Some(action.as_str()),
+                "{description}"
+            );
+        }
+    }
+
+    #[test]
+    fn country_from_isin() {
+        let df =
+            df!(ISIN => &[Some("NL0010273215"), Some("DE0007164600"), Some("CH0038863350"), None])
+                .unwrap()
+                .lazy()
+                .select([Degiro::country()])
+                .collect()
+                .unwrap();
+
+        assert_eq!(
+            utils::polars::column_str(&df, ISIN).unwrap(),
+            [
+                schema::Country::EU,
+                schema::Country::EU,
+                schema::Country::Unknown,
+                schema::Country::Unknown
+            ]
+            .map(schema::Country::as_str)
+        );
+    }
+
+    #[test]
+    fn tickers_from_invalid_file_fails() {
+        let file = temp_file::with_contents(b"{\"US0378331005\": ");
+        let error = Degiro::tickers_from_file(&file.path().to_path_buf()).unwrap_err();
+        assert!(error.to_string().contains("Failed to deserialize"));
+
+        let missing = PathBuf::from("target/missing_degiro_tickers.json");
+        let error = Degiro::tickers_from_file(&missing).unwrap_err();
+        assert!(error.to_string().contains("Failed to read"));
+    }
+}
@@ -0,0 +1,384 @@
+use super::{IBroker, UnknownActions, LINE};
+use crate::error::Error;
+use crate::schema::{Action, Column, Country, Currency, Type};
+
+use anyhow::{Context, Result};
+use polars::prelude::*;
+use regex::Regex;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Name of the source column for each column of the schema.
+#[derive(Debug, serde::Deserialize)]
+pub struct ColumnMapping {
+    pub date: String,
+    pub action: String,
+    pub amount: String,
+    pub ticker: Option<String>,
+    pub qty: Option<String>,
+    pub price: Option<String>,
+    pub tax: Option<String>,
+    pub commission: Option<String>,
+    pub currency: Option<String>,
+    pub country: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ActionPattern {
+    pub pattern: String,
+    pub action: String,
+}
+
+/// Extracts the ticker from another column, i.e. `"APPLE INC (AAPL)"` with `"\\((.*)\\)"`.
+#[derive(Debug, serde::Deserialize)]
+pub struct TickerPattern {
+    pub column: String,
+    pub pattern: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct Mapping {
+    pub columns: ColumnMapping,
+    pub date_format: String,
+    #[serde(default = "Mapping::default_separator")]
+    pub separator: char,
+    #[serde(default = "Mapping::default_decimal_separator")]
+    pub decimal_separator: char,
+    pub thousands_separator: Option<char>,
+    /// Patterns matched against the action column, the first match wins.
+    pub actions: Vec<ActionPattern>,
+    pub currency: String,
+    #[serde(default)]
+    pub country: Option<String>,
+    pub ticker: Option<TickerPattern>,
+}
+
+impl Mapping {
+    fn default_separator() -> char {
+        ','
+    }
+
+    fn default_decimal_separator() -> char {
+        '.'
+    }
+
+    /// Reads a toml mapping when the file has the `toml` extension, and a json one otherwise.
+    pub fn from_file(file: &Path) -> Result<Self> {
+        let file_content = std::fs::read_to_string(file)
+            .with_context(|| format!("Failed to read mapping file {:?}", file))?;
+        if file.extension().is_some_and(|x| x == "toml") {
+            toml::from_str(&file_content)
+                .with_context(|| format!("Failed to deserialize mapping file {:?}", file))
+        } else {
+            serde_json::from_str(&file_content)
+                .with_context(|| format!("Failed to deserialize mapping file {:?}", file))
+        }
+    }
+}
+
+pub struct Generic {
+    mapping: Mapping,
+    actions: Vec<(Regex, Action)>,
+    currency: Currency,
+    country: Country,
+    unknown: UnknownActions,
+    account: Option<String>,
+}
+
+impl Generic {
+    pub fn new(mapping: Mapping) -> Result<Self> {
+        let actions = mapping
+            .actions
+            .iter()
+            .map(|x| {
+                Ok((
+                    Regex::new(&x.pattern)
+                        .with_context(|| format!("Invalid pattern {}", x.pattern))?,
+                    Action::from_str(&x.action)
+                        .with_context(|| format!("Unknown action {}", x.action))?,
+                ))
+            })
+            .collect::<Result<_>>()?;
+        let currency = Currency::from_str(&mapping.currency)
+            .with_context(|| format!("Can't parse currency {}", mapping.currency))?;
+        let country = match &mapping.country {
+            Some(country) => {
+                Country::from_str(country).map_err(|_| Error::UnknownCountry(country.clone()))?
+            }
+            None => currency.into(),
+        };
+
+        Ok(Self {
+            mapping,
+            actions,
+            currency,
+            country,
+            unknown: UnknownActions::default(),
+            account: None,
+        })
+    }
+
+    /// Map unknown actions to `Action::Ignore` instead of failing.
+    pub fn with_lenient(mut self, lenient: bool) -> Self {
+        self.unknown = UnknownActions::new(lenient);
+        self
+    }
+
+    /// Name of the account in the `Account` column, defaults to the broker name.
+    pub fn with_account(mut self, account: impl Into<String>) -> Self {
+        self.account = Some(account.into());
+        self
+    }
+
+    pub fn from_file(file: &Path) -> Result<Self> {
+        Self::new(Mapping::from_file(file)?)
+    }
+
+    fn map_action(&self, action: &str) -> Option<Action> {
+        self.actions
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(action))
+            .map(|(_, action)| *action)
+    }
+
+    fn required_columns(&self) -> Vec<&str> {
+        let columns = &self.mapping.columns;
+        [&columns.date, &columns.action, &columns.amount]
+            .into_iter()
+            .chain(
+                [
+                    &columns.ticker,
+                    &columns.qty,
+                    &columns.price,
+                    &columns.tax,
+                    &columns.commission,
+                    &columns.currency,
+                    &columns.country,
+                ]
+                .into_iter()
+                .flatten(),
+            )
+            .chain(self.mapping.ticker.iter().map(|ticker| &ticker.column))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Normalizes the separators and drops the sign, the schema holds absolute values.
+    fn cast_to_float(&self, column: &str) -> Expr {
+        let mut expr = col(column);
+        if let Some(thousands) = self.mapping.thousands_separator {
+            expr = expr
+                .str()
+                .replace_all(lit(thousands.to_string()), lit(""), true);
+        }
+        if self.mapping.decimal_separator != '.' {
+            expr = expr.str().replace_all(
+                lit(self.mapping.decimal_separator.to_string()),
+                lit("."),
+                true,
+            );
+        }
+        expr.str()
+            .replace_all(lit(r"[^\d.]"), lit(""), false)
+            .cast(DataType::Float64)
+    }
+
+    fn optional_float(&self, column: &Option<String>, default: Expr) -> Expr {
+        match column {
+            Some(column) => self.cast_to_float(column).fill_null(default),
+            None => default,
+        }
+    }
+
+    fn ticker(&self) -> Expr {
+        let extracted = match &self.mapping.ticker {
+            Some(ticker) => col(&ticker.column)
+                .str()
+                .extract(lit(ticker.pattern.as_str()), 1),
+            None => lit(NULL).cast(DataType::String),
+        };
+        let ticker = match &self.mapping.columns.ticker {
+            Some(column) => when(col(column).str().len_chars().gt(lit(0)))
+                .then(col(column))
+                .otherwise(extracted),
+            None => extracted,
+        };
+        ticker.fill_null(lit("CASH"))
+    }
+
+    fn optional_str(column: &Option<String>, default: &'static str) -> Expr {
+        match column {
+            Some(column) => col(column).fill_null(lit(default)),
+            None => lit(default),
+        }
+    }
+}
+
+impl IBroker for Generic {
+    fn name(&self) -> &'static str {
+        "Generic"
+    }
+
+    fn account(&self) -> &str {
+        self.account.as_deref().unwrap_or(self.name())
+    }
+
+    fn load_from_csv(&self, csv_file: &Path) -> Result<DataFrame> {
+        let columns = &self.mapping.columns;
+        let df = LazyCsvReader::new(csv_file)
+            .has_header(true)
+            .with_separator(self.mapping.separator as u8)
+            .with_infer_schema_length(Some(0))
+            .finish()?
+            .with_row_index(LINE, Some(2))
+            .collect()?;
+        super::ensure_columns(csv_file, &df.get_column_names(), &self.required_columns())?;
+        let action = self
+            .unknown
+            .map_column(csv_file, &df, &columns.action, |action| {
+                self.map_action(action)
+            })?;
+
+        let df = df
+            .lazy()
+            .select([
+                col(&columns.date)
+                    .str()
+                    .to_datetime(
+                        None,
+                        None,
+                        StrptimeOptions {
+                            format: Some(self.mapping.date_format.clone()),
+                            ..StrptimeOptions::default()
+                        },
+                        lit("raise"),
+                    )
+                    .cast(DataType::Date)
+                    .alias(Column::Date.into()),
+                action.alias(Column::Action.into()),
+                self.ticker().alias(Column::Ticker.into()),
+                self.optional_float(&columns.qty, lit(1.0))
+                    .alias(Column::Qty.into()),
+                self.cast_to_float(&columns.amount)
+                    .alias(Column::Amount.into()),
+                self.optional_float(&columns.price, lit(NULL).cast(DataType::Float64))
+                    .alias(Column::Price.into()),
+                self.optional_float(&columns.tax, lit(0.0))
+                    .alias(Column::Tax.into()),
+                self.optional_float(&columns.commission, lit(0.0))
+                    .alias(Column::Commission.into()),
+                Self::optional_str(&columns.country, self.country.as_str())
+                    .alias(Column::Country.into()),
+                Self::optional_str(&columns.currency, self.currency.as_str())
+                    .alias(Column::Currency.into()),
+                lit(Type::Stock.to_string()).alias(Column::Type.into()),
+            ])
+            .with_column(
+                col(Column::Price.into())
+                    .fill_null(col(Column::Amount.into()) / col(Column::Qty.into()))
+                    .alias(Column::Price.into()),
+            );
+
+        Ok(self.sanitize(df).collect()?)
+    }
+
+    fn load_from_api(&self, _path: Option<&Path>) -> Result<DataFrame> {
+        anyhow::bail!("The generic broker has no api")
+    }
+
+    fn unknown_actions(&self) -> Vec<Error> {
+        self.unknown.take()
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+
+    use super::*;
+    use crate::utils;
+    use std::fs::File;
+    use std::path::Path;
+
+    #[test]
+    fn load_dir_success() {
+        let input_dir = Path::new("resources/tests/input/generic");
+        let reference_output = Path::new("resources/tests/generic_dir_success.csv");
+        let output = Path::new("target/generic_dir_result.csv");
+
+        let mut df = Generic::from_file(&input_dir.join("mapping.json"))
+            .unwrap()
+            .load_from_dir(input_dir)
+            .unwrap();
+
+        let mut file = File::create(output).expect("could not create file");
+        CsvWriter::new(&mut file)
+            .include_header(true)
+            .with_separator(b',')
+            .finish(&mut df)
+            .unwrap();
+
+        assert!(
+            utils::test::fs::compare_files(reference_output, output).unwrap(),
+            "Run the command to check the diff: meld {} {}",
+            reference_output.as_os_str().to_str().unwrap(),
+            output.as_os_str().to_str().unwrap()
+        );
+    }
+
+    #[test]
+    fn load_toml_mapping_success() {
+        let input_dir = Path::new("resources/tests/input/generic");
+        let json = Generic::from_file(&input_dir.join("mapping.json"))
+            .unwrap()
+            .load_from_dir(input_dir)
+            .unwrap();
+        let toml = Generic::from_file(Path::new("resources/tests/input/generic_mapping.toml"))
+            .unwrap()
+            .load_from_dir(input_dir)
+            .unwrap();
+
+        assert_eq!(json, toml);
+    }
+
+    fn unknown_action_input() -> (Generic, temp_file::TempFile) {
+        let mapping =
+            Mapping::from_file(Path::new("resources/tests/input/generic/mapping.json")).unwrap();
+        let csv = "Data;Operação;Ativo;Descrição;Quantidade;Preço;Valor;Taxas;Moeda\n\
+                   05/01/2023;Compra;AAPL;APPLE INC;10;\"125,50\";\"1.255,00\";\"1,00\";USD\n\
+                   06/01/2023;Aluguel;AAPL;APPLE INC;;;\"3,00\";;USD\n";
+        (
+            Generic::new(mapping).unwrap(),
+            temp_file::with_contents(csv.as_bytes()),
+        )
+    }
+
+    #[test]
+    fn load_unknown_action_fails() {
+        let (broker, file) = unknown_action_input();
+        let error = broker.load_from_csv(file.path()).unwrap_err();
+
+        match error.downcast_ref::<Error>() {
+            Some(Error::UnknownAction { action, line, .. }) => {
+                assert_eq!(action, "Aluguel");
+                assert_eq!(*line, 3);
+            }
+            _ => panic!("Unexpected error {error}"),
+        }
+    }
+
+    #[test]
+    fn load_unknown_action_lenient() {
+        let (broker, file) = unknown_action_input();
+        let broker = broker.with_lenient(true);
+        let df = broker.load_from_csv(file.path()).unwrap();
+
+        assert_eq!(
+            utils::polars::column_str(&df, Column::Action.as_str()).unwrap(),
+            ["Buy", "Ignore"]
+        );
+        let unknown = broker.unknown_actions();
+        assert_eq!(unknown.len(), 1);
+        assert!(matches!(&unknown[0], Error::UnknownAction { line: 3, .. }));
+        assert!(broker.unknown_actions().is_empty());
+    }
+}
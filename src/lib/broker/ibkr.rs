@@ -0,0 +1,727 @@
+use super::{IBroker, UnknownActions, LINE};
+use crate::error::Error;
+use crate::schema::{Action, Column, Country, Currency, Type};
+
+use anyhow::{Context, Result};
+use polars::prelude::*;
+use std::path::Path;
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct FlexQueryResponse {
+    #[serde(rename = "FlexStatements", default)]
+    statements: FlexStatements,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct FlexStatements {
+    #[serde(rename = "FlexStatement", default)]
+    statement: Vec<FlexStatement>,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct FlexStatement {
+    #[serde(rename = "AccountInformation")]
+    account: Option<AccountInformation>,
+    #[serde(rename = "Trades", default)]
+    trades: Trades,
+    #[serde(rename = "CashTransactions", default)]
+    cash_transactions: CashTransactions,
+    #[serde(rename = "CorporateActions", default)]
+    corporate_actions: CorporateActions,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct AccountInformation {
+    #[serde(rename = "@currency")]
+    currency: String,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct Trades {
+    #[serde(rename = "Trade", default)]
+    trade: Vec<Trade>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Trade {
+    #[serde(rename = "@currency")]
+    currency: String,
+    #[serde(rename = "@fxRateToBase", default)]
+    fx_rate_to_base: Option<f64>,
+    #[serde(rename = "@assetCategory", default)]
+    asset_category: String,
+    #[serde(rename = "@symbol")]
+    symbol: String,
+    #[serde(rename = "@isin", default)]
+    isin: String,
+    #[serde(rename = "@tradeDate")]
+    trade_date: String,
+    #[serde(rename = "@quantity")]
+    quantity: f64,
+    #[serde(rename = "@tradePrice")]
+    trade_price: f64,
+    #[serde(rename = "@tradeMoney")]
+    trade_money: f64,
+    #[serde(rename = "@taxes", default)]
+    taxes: f64,
+    #[serde(rename = "@ibCommission", default)]
+    commission: f64,
+    #[serde(rename = "@ibCommissionCurrency", default)]
+    commission_currency: String,
+    #[serde(rename = "@buySell")]
+    buy_sell: String,
+    #[serde(rename = "@levelOfDetail", default)]
+    level_of_detail: String,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct CashTransactions {
+    #[serde(rename = "CashTransaction", default)]
+    cash_transaction: Vec<CashTransaction>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct CashTransaction {
+    #[serde(rename = "@currency")]
+    currency: String,
+    #[serde(rename = "@symbol", default)]
+    symbol: String,
+    #[serde(rename = "@isin", default)]
+    isin: String,
+    #[serde(rename = "@dateTime")]
+    date_time: String,
+    #[serde(rename = "@amount")]
+    amount: f64,
+    #[serde(rename = "@type")]
+    kind: String,
+    #[serde(rename = "@levelOfDetail", default)]
+    level_of_detail: String,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct CorporateActions {
+    #[serde(rename = "CorporateAction", default)]
+    corporate_action: Vec<CorporateAction>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct CorporateAction {
+    #[serde(rename = "@currency")]
+    currency: String,
+    #[serde(rename = "@symbol")]
+    symbol: String,
+    #[serde(rename = "@isin", default)]
+    isin: String,
+    #[serde(rename = "@dateTime")]
+    date_time: String,
+    #[serde(rename = "@description", default)]
+    description: String,
+    #[serde(rename = "@quantity", default)]
+    quantity: f64,
+    #[serde(rename = "@proceeds", default)]
+    proceeds: f64,
+    #[serde(rename = "@value", default)]
+    value: f64,
+    #[serde(rename = "@type")]
+    kind: String,
+    #[serde(rename = "@levelOfDetail", default)]
+    level_of_detail: String,
+}
+
+impl CorporateAction {
+    /// The fractional shares of a corporate action paid in cash come in their own row.
+    fn is_cash_in_lieu(&self) -> bool {
+        self.description.to_uppercase().contains("CASH IN LIEU")
+    }
+}
+
+/// Action of the corporate action rows paying fractional shares in cash.
+const CASH_IN_LIEU: &str = "Cash In Lieu";
+
+/// Accumulates the statement rows in the columns of the sanitized schema, with the action as in
+/// the statement and the line of its element.
+#[derive(Default)]
+struct Orders {
+    line: Vec<u32>,
+    date: Vec<chrono::NaiveDate>,
+    action: Vec<String>,
+    ticker: Vec<String>,
+    qty: Vec<f64>,
+    price: Vec<f64>,
+    amount: Vec<f64>,
+    tax: Vec<f64>,
+    commission: Vec<f64>,
+    country: Vec<&'static str>,
+    currency: Vec<&'static str>,
+    new_ticker: Vec<Option<String>>,
+}
+
+impl Orders {
+    #[allow(clippy::too_many_arguments)]
+    fn push(
+        &mut self,
+        line: usize,
+        date: chrono::NaiveDate,
+        action: &str,
+        ticker: &str,
+        qty: f64,
+        price: f64,
+        amount: f64,
+        tax: f64,
+        commission: f64,
+        country: Country,
+        currency: Currency,
+        new_ticker: Option<&str>,
+    ) {
+        self.line.push(line as u32);
+        self.date.push(date);
+        self.action.push(action.to_owned());
+        self.ticker.push(ticker.to_owned());
+        self.qty.push(qty);
+        self.price.push(price);
+        self.amount.push(amount);
+        self.tax.push(tax);
+        self.commission.push(commission);
+        self.country.push(country.as_str());
+        self.currency.push(currency.as_str());
+        self.new_ticker.push(new_ticker.map(str::to_owned));
+    }
+
+    fn finish(self) -> Result<DataFrame> {
+        let len = self.date.len();
+        Ok(df!(
+            LINE => self.line,
+            Column::Date.into() => self.date,
+            Column::Action.into() => self.action,
+            Column::Ticker.into() => self.ticker,
+            Column::Qty.into() => self.qty,
+            Column::Price.into() => self.price,
+            Column::Amount.into() => self.amount,
+            Column::Tax.into() => self.tax,
+            Column::Commission.into() => self.commission,
+            Column::Country.into() => self.country,
+            Column::Currency.into() => self.currency,
+            Column::Type.into() => vec![Type::Stock.as_str(); len],
+            Column::NewTicker.into() => self.new_ticker,
+        )?)
+    }
+}
+
+pub struct InteractiveBrokers {
+    currency: Currency,
+    unknown: UnknownActions,
+    account: Option<String>,
+}
+
+impl Default for InteractiveBrokers {
+    fn default() -> Self {
+        InteractiveBrokers::new(Currency::USD)
+    }
+}
+
+impl InteractiveBrokers {
+    /// The `currency` is the account base currency, used when the statement has no `AccountInformation`.
+    pub fn new(currency: Currency) -> Self {
+        InteractiveBrokers {
+            currency,
+            unknown: UnknownActions::default(),
+            account: None,
+        }
+    }
+
+    /// Map unknown actions to `Action::Ignore` instead of failing.
+    pub fn with_lenient(mut self, lenient: bool) -> Self {
+        self.unknown = UnknownActions::new(lenient);
+        self
+    }
+
+    /// Name of the account in the `Account` column, defaults to the broker name.
+    pub fn with_account(mut self, account: impl Into<String>) -> Self {
+        self.account = Some(account.into());
+        self
+    }
+
+    /// Maps the `buySell` of the trades and the `type` of the cash transactions and corporate
+    /// actions, the withdrawals are the deposits with a negative amount.
+    fn map_action(s: &str) -> Option<Action> {
+        Some(match s {
+            "BUY" => Action::Buy,
+            "SELL" => Action::Sell,
+            "Dividends" | "Payment In Lieu Of Dividends" => Action::Dividend,
+            "Withholding Tax" => Action::Tax,
+            "Deposits/Withdrawals" => Action::Deposit,
+            "Broker Interest Received" | "Bond Interest Received" => Action::Interest,
+            "Broker Interest Paid" | "Other Fees" | "Commission Adjustments" => Action::Fee,
+            "FS" | "RS" => Action::Split,
+            "TC" => Action::Rename,
+            "SO" => Action::SpinOff,
+            "TO" | "TM" => Action::Merger,
+            CASH_IN_LIEU => Action::CashInLieu,
+            _ => return None,
+        })
+    }
+
+    /// Line of each `<tag ...>` element in the document, in the order they're deserialized.
+    fn element_lines(content: &str, tag: &str) -> Vec<usize> {
+        let start = format!("<{tag}");
+        let mut lines = Vec::new();
+        for (index, line) in content.lines().enumerate() {
+            for (position, _) in line.match_indices(&start) {
+                let next = line[position + start.len()..].chars().next();
+                if next.is_none_or(|x| x.is_whitespace() || x == '/' || x == '>') {
+                    lines.push(index + 1);
+                }
+            }
+        }
+        lines
+    }
+
+    /// Flex dates come either as `20230105` or `2023-01-05`, optionally followed by the time.
+    fn parse_date(file: &Path, line: usize, date: &str) -> Result<chrono::NaiveDate> {
+        let digits: String = date.chars().filter(char::is_ascii_digit).take(8).collect();
+        chrono::NaiveDate::parse_from_str(&digits, "%Y%m%d").map_err(|_| {
+            Error::UnparsableDate {
+                date: date.to_owned(),
+                file: file.to_path_buf(),
+                line,
+            }
+            .into()
+        })
+    }
+
+    /// Ratio of new shares per share of a description like `SPLIT 4 FOR 1` for the `keyword` SPLIT.
+    fn ratio(description: &str, keyword: &str) -> Option<f64> {
+        let description = description.to_uppercase();
+        let position = description.find(keyword)? + keyword.len();
+        let mut words = description[position..].split_whitespace();
+        let new: f64 = words.next()?.parse().ok()?;
+        if words.next()? != "FOR" {
+            return None;
+        }
+        let old: f64 = words.next()?.parse().ok()?;
+        Some(new / old)
+    }
+
+    fn parse_currency(currency: &str) -> Result<Currency> {
+        currency
+            .parse()
+            .with_context(|| format!("Can't parse currency {currency}"))
+    }
+
+    /// Tickers without a known ISIN country are assumed to be listed in the country of their currency.
+    fn country(isin: &str, currency: Currency) -> Country {
+        match isin.len() {
+            12.. => match Country::from_isin(isin) {
+                Country::Unknown => currency.into(),
+                country => country,
+            },
+            _ => currency.into(),
+        }
+    }
+
+    fn is_summary(level_of_detail: &str) -> bool {
+        !level_of_detail.is_empty() && level_of_detail != "EXECUTION" && level_of_detail != "DETAIL"
+    }
+
+    fn load_trades(
+        file: &Path,
+        orders: &mut Orders,
+        trades: &Trades,
+        base_currency: Currency,
+        lines: &mut impl Iterator<Item = usize>,
+    ) -> Result<()> {
+        for trade in &trades.trade {
+            let line = lines.next().unwrap_or_default();
+            // Forex conversions and derivatives are not part of the portfolio.
+            if trade.asset_category != "STK" || Self::is_summary(&trade.level_of_detail) {
+                continue;
+            }
+            let currency = Self::parse_currency(&trade.currency)?;
+
+            // The commission is charged in the account base currency when it differs from the trade currency.
+            let commission = if trade.commission_currency.is_empty()
+                || trade.commission_currency == trade.currency
+            {
+                trade.commission.abs()
+            } else {
+                let commission_currency = Self::parse_currency(&trade.commission_currency)?;
+                anyhow::ensure!(
+                    commission_currency == base_currency,
+                    "Can't convert commission from {commission_currency} to {currency}"
+                );
+                let rate = trade
+                    .fx_rate_to_base
+                    .with_context(|| format!("Missing fxRateToBase for {}", trade.symbol))?;
+                trade.commission.abs() / rate
+            };
+
+            orders.push(
+                line,
+                Self::parse_date(file, line, &trade.trade_date)?,
+                &trade.buy_sell,
+                &trade.symbol,
+                trade.quantity.abs(),
+                trade.trade_price,
+                trade.trade_money.abs(),
+                trade.taxes.abs(),
+                commission,
+                Self::country(&trade.isin, currency),
+                currency,
+                None,
+            );
+        }
+        Ok(())
+    }
+
+    fn load_cash_transactions(
+        file: &Path,
+        orders: &mut Orders,
+        cash: &CashTransactions,
+        lines: &mut impl Iterator<Item = usize>,
+    ) -> Result<()> {
+        for transaction in &cash.cash_transaction {
+            let line = lines.next().unwrap_or_default();
+            if Self::is_summary(&transaction.level_of_detail) {
+                continue;
+            }
+            let currency = Self::parse_currency(&transaction.currency)?;
+            let (ticker, country) = if transaction.symbol.is_empty() {
+                ("CASH".to_owned(), Country::Unknown)
+            } else {
+                (
+                    transaction.symbol.clone(),
+                    Self::country(&transaction.isin, currency),
+                )
+            };
+
+            orders.push(
+                line,
+                Self::parse_date(file, line, &transaction.date_time)?,
+                &transaction.kind,
+                &ticker,
+                1.0,
+                transaction.amount,
+                transaction.amount,
+                0.0,
+                0.0,
+                country,
+                currency,
+                None,
+            );
+        }
+        Ok(())
+    }
+
+    /// The legs of a corporate action are consecutive rows with the same type and date, the
+    /// shares removed with a negative quantity and the ones received with a positive one.
+    fn load_corporate_actions(
+        file: &Path,
+        orders: &mut Orders,
+        actions: &CorporateActions,
+        lines: &mut impl Iterator<Item = usize>,
+    ) -> Result<()> {
+        let mut rows = Vec::new();
+        for action in &actions.corporate_action {
+            let line = lines.next().unwrap_or_default();
+            if !Self::is_summary(&action.level_of_detail) {
+                rows.push((line, action));
+            }
+        }
+
+        let legs = rows.chunk_by(|(_, a), (_, b)| {
+            a.kind == b.kind
+                && a.date_time == b.date_time
+                && !a.is_cash_in_lieu()
+                && !b.is_cash_in_lieu()
+        });
+        for legs in legs {
+            let (line, first) = legs[0];
+            let kind = first.kind.as_str();
+            let removed = legs.iter().map(|(_, x)| *x).find(|x| x.quantity < 0.0);
+            let received = legs.iter().map(|(_, x)| *x).find(|x| x.quantity > 0.0);
+            let missing =
+                |leg: &str| anyhow::anyhow!("{kind} without the {leg} shares in line {line}");
+
+            let (kind, ticker, qty, price, amount, new_ticker) = if first.is_cash_in_lieu() {
+                let qty = first.quantity.abs();
+                let price = first.proceeds.abs() / qty;
+                (
+                    CASH_IN_LIEU,
+                    first.symbol.as_str(),
+                    qty,
+                    price,
+                    first.proceeds.abs(),
+                    None,
+                )
+            } else {
+                match kind {
+                    "FS" | "RS" => {
+                        let ratio = match (Self::ratio(&first.description, "SPLIT"), removed) {
+                            (Some(ratio), _) => ratio,
+                            (None, Some(removed)) => {
+                                received.map_or(0.0, |x| x.quantity) / -removed.quantity
+                            }
+                            (None, None) => return Err(missing("removed")),
+                        };
+                        let ticker = removed.unwrap_or(first).symbol.as_str();
+                        (kind, ticker, ratio, 0.0, 0.0, None)
+                    }
+                    "TC" => {
+                        let removed = removed.ok_or_else(|| missing("removed"))?;
+                        let received = received.ok_or_else(|| missing("received"))?;
+                        let qty = received.quantity / -removed.quantity;
+                        let new_ticker = Some(received.symbol.as_str());
+                        (kind, removed.symbol.as_str(), qty, 0.0, 0.0, new_ticker)
+                    }
+                    // Only the received shares are listed, the parent ticker is in the description
+                    // and none of its basis is carried, as the statement doesn't tell it.
+                    "SO" => {
+                        let received = received.ok_or_else(|| missing("received"))?;
+                        let parent = received.description.split('(').next().unwrap_or_default();
+                        let qty = Self::ratio(&received.description, "SPINOFF")
+                            .with_context(|| format!("{kind} without ratio in line {line}"))?;
+                        let new_ticker = Some(received.symbol.as_str());
+                        (kind, parent.trim(), qty, 0.0, 0.0, new_ticker)
+                    }
+                    // The basis is allocated to the cash and the new shares by their value.
+                    "TO" | "TM" => {
+                        let removed = removed.ok_or_else(|| missing("removed"))?;
+                        let cash = removed.proceeds.abs();
+                        let (qty, value) = received.map_or((0.0, 0.0), |x| {
+                            (x.quantity / -removed.quantity, x.value.abs())
+                        });
+                        let price = if cash + value > 0.0 {
+                            cash / (cash + value)
+                        } else {
+                            1.0
+                        };
+                        let new_ticker = received.map(|x| x.symbol.as_str());
+                        (kind, removed.symbol.as_str(), qty, price, cash, new_ticker)
+                    }
+                    _ => (
+                        kind,
+                        first.symbol.as_str(),
+                        first.quantity.abs(),
+                        0.0,
+                        0.0,
+                        None,
+                    ),
+                }
+            };
+
+            let currency = Self::parse_currency(&first.currency)?;
+            orders.push(
+                line,
+                Self::parse_date(file, line, &first.date_time)?,
+                kind,
+                ticker,
+                qty,
+                price,
+                amount,
+                0.0,
+                0.0,
+                Self::country(&first.isin, currency),
+                currency,
+                new_ticker,
+            );
+        }
+        Ok(())
+    }
+}
+
+impl IBroker for InteractiveBrokers {
+    fn name(&self) -> &'static str {
+        "InteractiveBrokers"
+    }
+
+    fn account(&self) -> &str {
+        self.account.as_deref().unwrap_or(self.name())
+    }
+
+    fn file_extension(&self) -> &'static str {
+        "xml"
+    }
+
+    fn load_from_csv(&self, xml_file: &Path) -> Result<DataFrame> {
+        let content =
+            std::fs::read_to_string(xml_file).with_context(|| format!("{:?}", xml_file))?;
+        let response: FlexQueryResponse = quick_xml::de::from_str(&content)
+            .with_context(|| format!("Failed to parse Flex Query {:?}", xml_file))?;
+
+        let mut orders = Orders::default();
+        let mut trade_lines = Self::element_lines(&content, "Trade").into_iter();
+        let mut cash_lines = Self::element_lines(&content, "CashTransaction").into_iter();
+        let mut action_lines = Self::element_lines(&content, "CorporateAction").into_iter();
+        for statement in response.statements.statement {
+            let base_currency = match statement.account {
+                Some(account) => Self::parse_currency(&account.currency)?,
+                None => self.currency,
+            };
+            Self::load_trades(
+                xml_file,
+                &mut orders,
+                &statement.trades,
+                base_currency,
+                &mut trade_lines,
+            )?;
+            Self::load_cash_transactions(
+                xml_file,
+                &mut orders,
+                &statement.cash_transactions,
+                &mut cash_lines,
+            )?;
+            Self::load_corporate_actions(
+                xml_file,
+                &mut orders,
+                &statement.corporate_actions,
+                &mut action_lines,
+            )?;
+        }
+
+        let orders = orders.finish()?;
+        let action = self.unknown.map_column(
+            xml_file,
+            &orders,
+            Column::Action.as_str(),
+            Self::map_action,
+        )?;
+        let is = |action: Action| col(Column::Action.as_str()).eq(lit(action.as_str()));
+        // Taxes, fees and withdrawals are negative in the statement but positive in the schema.
+        let sign = |column: Column| {
+            when(is(Action::Tax).or(is(Action::Fee)).or(is(Action::Withdraw)))
+                .then(-col(column.as_str()))
+                .otherwise(col(column.as_str()))
+                .alias(column.as_str())
+        };
+        let orders = orders
+            .lazy()
+            .with_column(action.alias(Column::Action.as_str()))
+            .with_column(
+                when(is(Action::Deposit).and(col(Column::Amount.as_str()).lt(lit(0.0))))
+                    .then(lit(Action::Withdraw.as_str()))
+                    .otherwise(col(Column::Action.as_str()))
+                    .alias(Column::Action.as_str()),
+            )
+            .with_columns([sign(Column::Price), sign(Column::Amount)]);
+
+        Ok(self.sanitize(orders).collect()?)
+    }
+
+    fn load_from_api(&self, _path: Option<&Path>) -> Result<DataFrame> {
+        anyhow::bail!("The Flex Web Service isn't supported, export a Flex Query instead")
+    }
+
+    fn unknown_actions(&self) -> Vec<Error> {
+        self.unknown.take()
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+
+    use super::*;
+    use crate::utils;
+    use std::fs::File;
+    use std::path::Path;
+
+    #[test]
+    fn load_xml_success() {
+        let input_xml = Path::new("resources/tests/input/ibkr/2023.xml");
+        let reference_output = Path::new("resources/tests/ibkr_success.csv");
+        let output = Path::new("target/ibkr_result.csv");
+
+        let mut df = InteractiveBrokers::default()
+            .load_from_csv(input_xml)
+            .unwrap();
+
+        let mut file = File::create(output).expect("could not create file");
+        CsvWriter::new(&mut file)
+            .include_header(true)
+            .with_separator(b',')
+            .finish(&mut df)
+            .unwrap();
+
+        assert!(
+            utils::test::fs::compare_files(reference_output, output).unwrap(),
+            "Run the command to check the diff: meld {} {}",
+            reference_output.as_os_str().to_str().unwrap(),
+            output.as_os_str().to_str().unwrap()
+        );
+    }
+
+    fn unknown_action_input() -> temp_file::TempFile {
+        temp_file::with_contents(
+            b"<FlexQueryResponse>\n\
+              <FlexStatements count=\"1\">\n\
+              <FlexStatement>\n\
+              <Trades>\n\
+              <Trade currency=\"USD\" assetCategory=\"STK\" symbol=\"AAPL\" tradeDate=\"20230105\" quantity=\"10\" tradePrice=\"125.5\" tradeMoney=\"1255\" buySell=\"BUY\" />\n\
+              </Trades>\n\
+              <CashTransactions>\n\
+              <CashTransaction currency=\"USD\" symbol=\"AAPL\" dateTime=\"20230518\" amount=\"-2.1\" type=\"Stock Loan Fee\" />\n\
+              </CashTransactions>\n\
+              </FlexStatement>\n\
+              </FlexStatements>\n\
+              </FlexQueryResponse>\n",
+        )
+    }
+
+    #[test]
+    fn load_unknown_action_fails() {
+        let file = unknown_action_input();
+        let error = InteractiveBrokers::default()
+            .load_from_csv(file.path())
+            .unwrap_err();
+
+        match error.downcast_ref::<Error>() {
+            Some(Error::UnknownAction { action, line, .. }) => {
+                assert_eq!(action, "Stock Loan Fee");
+                assert_eq!(*line, 8);
+            }
+            _ => panic!("Unexpected error {error}"),
+        }
+    }
+
+    #[test]
+    fn load_unknown_action_lenient() {
+        let file = unknown_action_input();
+        let broker = InteractiveBrokers::default().with_lenient(true);
+        let df = broker.load_from_csv(file.path()).unwrap();
+
+        assert_eq!(
+            utils::polars::column_str(&df, Column::Action.as_str()).unwrap(),
+            ["Buy", "Ignore"]
+        );
+        let unknown = broker.unknown_actions();
+        assert_eq!(unknown.len(), 1);
+        assert!(matches!(&unknown[0], Error::UnknownAction { line: 8, .. }));
+    }
+
+    #[test]
+    fn load_unknown_corporate_action_lenient() {
+        let file = temp_file::with_contents(
+            b"<FlexQueryResponse>\n\
+              <FlexStatements count=\"1\">\n\
+              <FlexStatement>\n\
+              <CorporateActions>\n\
+              <CorporateAction currency=\"USD\" symbol=\"AAPL\" dateTime=\"20230901\" quantity=\"2\" type=\"DW\" />\n\
+              </CorporateActions>\n\
+              </FlexStatement>\n\
+              </FlexStatements>\n\
+              </FlexQueryResponse>\n",
+        );
+        let broker = InteractiveBrokers::default().with_lenient(true);
+        let df = broker.load_from_csv(file.path()).unwrap();
+
+        assert_eq!(
+            utils::polars::column_str(&df, Column::Action.as_str()).unwrap(),
+            ["Ignore"]
+        );
+        let unknown = broker.unknown_actions();
+        assert_eq!(unknown.len(), 1);
+        assert!(matches!(
+            &unknown[0],
+            Error::UnknownAction { action, line: 5, .. } if action == "DW"
+        ));
+    }
+}
@@ -1,45 +1,195 @@
+pub mod b3;
+pub mod degiro;
+pub mod generic;
+pub mod ibkr;
 pub mod schwab;
 pub mod trading212;
 
+pub use b3::B3;
+pub use degiro::Degiro;
+pub use generic::Generic;
+pub use ibkr::InteractiveBrokers;
 use polars::lazy::frame::LazyFrame;
 pub use schwab::Schwab;
 pub use trading212::Trading212;
 
-use crate::schema::Column::*;
+use crate::error::Error;
+use crate::schema::{Action, Column::*};
+use crate::utils;
 use anyhow::Result;
 use glob::glob;
 use polars::prelude::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::path::Path;
 
+/// Column with the line of each row in the statement, used to report unknown actions.
+const LINE: &str = "Line";
+
+/// Actions a broker can't map. They fail the load by default, the lenient mode maps them to
+/// `Action::Ignore` and keeps them for the report at the end of the run.
+#[derive(Default)]
+pub struct UnknownActions {
+    lenient: bool,
+    found: RefCell<Vec<Error>>,
+}
+
+impl UnknownActions {
+    pub fn new(lenient: bool) -> Self {
+        Self {
+            lenient,
+            ..Default::default()
+        }
+    }
+
+    fn handle(&self, file: &Path, line: usize, action: &str) -> Result<Action, Error> {
+        let error = Error::UnknownAction {
+            action: action.to_owned(),
+            file: file.to_path_buf(),
+            line,
+        };
+        if !self.lenient {
+            return Err(error);
+        }
+        log::warn!("{error}");
+        self.found.borrow_mut().push(error);
+        Ok(Action::Ignore)
+    }
+
+    /// Maps the `column` of a statement read with its `LINE` column. The unknown actions are
+    /// handled here, so the mapping expression itself can't fail.
+    fn map_column(
+        &self,
+        file: &Path,
+        frame: &DataFrame,
+        column: &str,
+        map: impl Fn(&str) -> Option<Action>,
+    ) -> Result<Expr> {
+        let lines = frame.column(LINE)?.cast(&DataType::UInt64)?;
+        let mut actions = HashMap::new();
+        for (line, action) in lines.u64()?.into_iter().zip(frame.column(column)?.str()?) {
+            let action = action.unwrap_or_default();
+            let mapped = match map(action) {
+                Some(mapped) => mapped,
+                None => self.handle(file, line.unwrap_or_default() as usize, action)?,
+            };
+            actions.insert(action.to_owned(), mapped);
+        }
+
+        Ok(utils::polars::map_str_column(column, move |row| {
+            actions
+                .get(row.unwrap_or_default())
+                .copied()
+                .unwrap_or(Action::Ignore)
+                .as_str()
+        }))
+    }
+
+    /// Takes the unknown actions found so far.
+    pub fn take(&self) -> Vec<Error> {
+        self.found.take()
+    }
+}
+
+/// Fails on the first row of a statement read with its `LINE` column where the `date` expression,
+/// which must leave null the dates it can't parse, has no date for the `column`.
+fn ensure_dates(file: &Path, frame: &DataFrame, column: &str, date: Expr) -> Result<()> {
+    let unparsable = frame
+        .clone()
+        .lazy()
+        .filter(date.is_null().and(col(column).is_not_null()))
+        .select([col(LINE).cast(DataType::UInt64), col(column)])
+        .collect()?;
+    let lines = unparsable.column(LINE)?.u64()?;
+    let dates = unparsable.column(column)?.str()?;
+    if let Some((Some(line), Some(date))) = lines.into_iter().zip(dates).next() {
+        return Err(Error::UnparsableDate {
+            date: date.to_owned(),
+            file: file.to_path_buf(),
+            line: line as usize,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+fn ensure_columns(file: &Path, available: &[&str], required: &[&str]) -> Result<(), Error> {
+    match required.iter().find(|column| !available.contains(column)) {
+        Some(column) => Err(Error::MissingColumn {
+            column: column.to_string(),
+            file: file.to_path_buf(),
+        }),
+        None => Ok(()),
+    }
+}
+
 pub trait IBroker {
+    /// Name of the broker, filled in the `Broker` column.
+    fn name(&self) -> &'static str;
+
+    /// Name of the account, filled in the `Account` column. Defaults to the broker name.
+    fn account(&self) -> &str {
+        self.name()
+    }
+
     fn load_from_csv(&self, file: &Path) -> Result<DataFrame>;
 
+    /// Extension of the statement files loaded by `load_from_dir`.
+    fn file_extension(&self) -> &'static str {
+        "csv"
+    }
+
     fn load_from_dir(&self, dir: &Path) -> Result<DataFrame> {
-        let files = glob(dir.join("*.csv").as_os_str().to_str().unwrap())?;
+        let pattern = dir.join(format!("*.{}", self.file_extension()));
+        let files = glob(pattern.as_os_str().to_str().unwrap())?;
         let mut frame = LazyFrame::default();
         for file in files {
             let new = self.load_from_csv(file?.as_path())?.lazy();
             frame = concat([frame, new], Default::default())?;
         }
         Ok(frame
-            .unique(None, UniqueKeepStrategy::First)
+            .unique_stable(None, UniqueKeepStrategy::First)
             .sort(
                 [Date.as_str(), Ticker.as_str(), Price.as_str()],
-                Default::default(),
+                SortMultipleOptions::new().with_maintain_order(true),
             )
             .collect()?)
     }
 
-    fn sanitize(frame: impl IntoLazy) -> LazyFrame {
+    fn sanitize(&self, frame: impl IntoLazy) -> LazyFrame {
         let columns = [
             Date, Action, Ticker, Qty, Price, Amount, Tax, Commission, Country, Currency, Type,
+            Account, Broker, NewTicker,
         ]
         .map(|x| col(x.as_str()));
-        frame.lazy().select(columns).sort(
-            [Date.as_str()],
-            SortMultipleOptions::new().with_order_descending(false),
-        )
+        let frame = frame.lazy();
+        let new_ticker = if frame
+            .schema()
+            .is_ok_and(|schema| schema.contains(NewTicker.as_str()))
+        {
+            col(NewTicker.as_str())
+        } else {
+            lit(NULL).cast(DataType::String)
+        };
+        frame
+            .with_columns([
+                new_ticker.alias(NewTicker.as_str()),
+                lit(self.account().to_owned()).alias(Account.as_str()),
+                lit(self.name()).alias(Broker.as_str()),
+            ])
+            .select(columns)
+            .sort(
+                [Date.as_str()],
+                SortMultipleOptions::new()
+                    .with_order_descending(false)
+                    .with_maintain_order(true),
+            )
     }
 
     fn load_from_api(&self, path: Option<&Path>) -> Result<DataFrame>;
+
+    /// The actions ignored in lenient mode since the last call.
+    fn unknown_actions(&self) -> Vec<Error> {
+        Vec::new()
+    }
 }
@@ -1,30 +1,46 @@
-use super::IBroker;
+use super::{IBroker, UnknownActions, LINE};
+use crate::error::Error;
 use crate::schema::{self, Action, Column, Country, Currency, Type};
-use crate::utils;
 
 use anyhow::Result;
 use polars::prelude::*;
 use std::path::Path;
 pub struct Schwab {
     currency: Currency,
+    unknown: UnknownActions,
+    account: Option<String>,
 }
 
 impl Default for Schwab {
     fn default() -> Self {
-        Schwab {
-            currency: Currency::USD,
-        }
+        Schwab::new(Currency::USD)
     }
 }
 
 impl Schwab {
     pub fn new(currency: Currency) -> Self {
-        Schwab { currency }
+        Schwab {
+            currency,
+            unknown: UnknownActions::default(),
+            account: None,
+        }
+    }
+
+    /// Map unknown actions to `Action::Ignore` instead of failing.
+    pub fn with_lenient(mut self, lenient: bool) -> Self {
+        self.unknown = UnknownActions::new(lenient);
+        self
+    }
+
+    /// Name of the account in the `Account` column, defaults to the broker name.
+    pub fn with_account(mut self, account: impl Into<String>) -> Self {
+        self.account = Some(account.into());
+        self
     }
 
-    fn map_action(s: &str) -> Action {
+    fn map_action(s: &str) -> Option<Action> {
         let collect: Vec<_> = s.split_whitespace().take(4).collect();
-        match &collect[..] {
+        Some(match &collect[..] {
             ["Buy"] => Action::Buy,
             ["Sell"] => Action::Sell,
             ["Split"] => Action::Ignore,
@@ -38,8 +54,26 @@ impl Schwab {
             ["Withdrawal"] => Action::Withdraw,
             ["Long", "Term", "Cap", "Gain"] => Action::Dividend,
             ["Internal", "Transfer"] => Action::Ignore,
-            _ => panic!("Unknown {s}"),
-        }
+            ["Cash", "In", "Lieu"] => Action::CashInLieu,
+            _ => return None,
+        })
+    }
+
+    /// The dates it can't parse are null.
+    fn to_date(column: &str) -> Expr {
+        col(column)
+            .str()
+            .to_datetime(
+                None,
+                None,
+                StrptimeOptions {
+                    format: Some("%m/%d/%Y".to_owned()),
+                    strict: false,
+                    ..StrptimeOptions::default()
+                },
+                lit("raise"),
+            )
+            .cast(DataType::Date)
     }
 
     fn cast_cash_to_float(column: &str) -> Expr {
@@ -52,10 +86,28 @@ impl Schwab {
 }
 
 impl IBroker for Schwab {
+    fn name(&self) -> &'static str {
+        "Schwab"
+    }
+
+    fn account(&self) -> &str {
+        self.account.as_deref().unwrap_or(self.name())
+    }
+
     fn load_from_csv(&self, csv_file: &Path) -> Result<DataFrame> {
         let df = LazyCsvReader::new(csv_file)
             .has_header(true)
             .finish()?
+            .with_row_index(LINE, Some(2))
+            .collect()?;
+        super::ensure_columns(
+            csv_file,
+            &df.get_column_names(),
+            &["Date", "Action", "Symbol", "Description", "Amount"],
+        )?;
+
+        let df = df
+            .lazy()
             .filter(
                 col("Description")
                     .str()
@@ -68,23 +120,23 @@ impl IBroker for Schwab {
                     .otherwise(col("Symbol"))
                     .alias("Symbol"),
             )
+            .collect()?;
+        let action = self
+            .unknown
+            .map_column(csv_file, &df, "Action", Self::map_action)?;
+        super::ensure_dates(
+            csv_file,
+            &df,
+            Column::Date.into(),
+            Self::to_date(Column::Date.into()),
+        )?;
+
+        let is_cash_in_lieu = col(Column::Action.into()).eq(lit(Action::CashInLieu.as_str()));
+        let df = df
+            .lazy()
             .select([
-                col(Column::Date.into())
-                    .str()
-                    .to_datetime(
-                        None,
-                        None,
-                        StrptimeOptions {
-                            format: Some("%m/%d/%Y".to_owned()),
-                            ..StrptimeOptions::default()
-                        },
-                        lit("raise"),
-                    )
-                    .cast(DataType::Date),
-                utils::polars::map_str_column("Action", |row| {
-                    Self::map_action(row.unwrap_or("Unknown")).into()
-                })
-                .alias(Column::Action.into()),
+                Self::to_date(Column::Date.into()),
+                action.alias(Column::Action.into()),
                 when(col("Symbol").eq(lit("")))
                     .then(
                         when(col("Description").str().contains(lit(r"\(.+\)"), false))
@@ -95,7 +147,6 @@ impl IBroker for Schwab {
                     .alias(Column::Ticker.into()),
                 col("Quantity")
                     .cast(DataType::Float64)
-                    .fill_null(lit(1))
                     .alias(Column::Qty.into()),
                 Schwab::cast_cash_to_float("Amount").alias(Column::Amount.into()),
                 lit(0.0).alias(Column::Tax.into()),
@@ -118,6 +169,17 @@ impl IBroker for Schwab {
                     .fill_null(col(Column::Amount.into()))
                     .alias(Column::Price.into()),
             )
+            // The cash in lieu has no quantity, it's cash only and the shares are kept.
+            .with_columns([
+                when(is_cash_in_lieu.clone())
+                    .then(col(Column::Qty.into()).fill_null(lit(0.0)))
+                    .otherwise(col(Column::Qty.into()).fill_null(lit(1.0)))
+                    .alias(Column::Qty.into()),
+                when(is_cash_in_lieu.and(col(Column::Qty.into()).gt(lit(0.0))))
+                    .then(col(Column::Amount.into()) / col(Column::Qty.into()))
+                    .otherwise(col(Column::Price.into()))
+                    .alias(Column::Price.into()),
+            ])
             .with_columns([
                 col(Column::Action.into()).str().replace(
                     lit(r".*Tax.*"),
@@ -127,11 +189,15 @@ impl IBroker for Schwab {
                 lit(self.currency.as_str()).alias(Column::Currency.into()),
             ]);
 
-        Ok(Self::sanitize(df).collect()?)
+        Ok(self.sanitize(df).collect()?)
     }
 
     fn load_from_api(&self, _path: Option<&Path>) -> Result<DataFrame> {
-        todo!("Look for an api");
+        anyhow::bail!("Schwab has no api")
+    }
+
+    fn unknown_actions(&self) -> Vec<Error> {
+        self.unknown.take()
     }
 }
 
@@ -165,4 +231,81 @@ mod unittest {
             output.as_os_str().to_str().unwrap()
         );
     }
+
+    #[test]
+    fn load_cash_in_lieu_keeps_shares() {
+        let input_csv = Path::new("resources/tests/input/schwab/2019.csv");
+        let df = Schwab::default()
+            .load_from_csv(input_csv)
+            .unwrap()
+            .lazy()
+            .filter(col(Column::Action.as_str()).eq(lit(Action::CashInLieu.as_str())))
+            .select([
+                col(Column::Ticker.as_str()),
+                col(Column::Qty.as_str()),
+                col(Column::Amount.as_str()),
+            ])
+            .collect()
+            .unwrap();
+
+        let expected = df!(
+            Column::Ticker.as_str() => &["TSLA"],
+            Column::Qty.as_str() => &[0.0],
+            Column::Amount.as_str() => &[42.12],
+        )
+        .unwrap();
+        assert_eq!(expected, df);
+    }
+
+    fn unknown_action_input() -> temp_file::TempFile {
+        temp_file::with_contents(
+            b"\"Date\",\"Action\",\"Symbol\",\"Description\",\"Quantity\",\"Price\",\"Fees & Comm\",\"Amount\"\n\
+              \"12/29/2019\",\"Cash Dividend\",\"KWEB\",\"KRANESHARES CSI CHINA INTERNET ETF\",\"\",\"\",\"\",\"$2.68\"\n\
+              \"12/30/2019\",\"Stock Lending Income\",\"KWEB\",\"KRANESHARES CSI CHINA INTERNET ETF\",\"\",\"\",\"\",\"$0.10\"\n",
+        )
+    }
+
+    #[test]
+    fn load_unknown_action_fails() {
+        let file = unknown_action_input();
+        let error = Schwab::default().load_from_csv(file.path()).unwrap_err();
+
+        match error.downcast_ref::<Error>() {
+            Some(Error::UnknownAction { action, line, .. }) => {
+                assert_eq!(action, "Stock Lending Income");
+                assert_eq!(*line, 3);
+            }
+            _ => panic!("Unexpected error {error}"),
+        }
+    }
+
+    #[test]
+    fn load_unknown_action_lenient() {
+        let file = unknown_action_input();
+        let broker = Schwab::default().with_lenient(true);
+        broker.load_from_csv(file.path()).unwrap();
+
+        let unknown = broker.unknown_actions();
+        assert_eq!(unknown.len(), 1);
+        assert!(matches!(&unknown[0], Error::UnknownAction { line: 3, .. }));
+        assert!(broker.unknown_actions().is_empty());
+    }
+
+    #[test]
+    fn load_unparsable_date_fails() {
+        let file = temp_file::with_contents(
+            b"\"Date\",\"Action\",\"Symbol\",\"Description\",\"Quantity\",\"Price\",\"Fees & Comm\",\"Amount\"\n\
+              \"12/29/2019\",\"Cash Dividend\",\"KWEB\",\"KRANESHARES CSI CHINA INTERNET ETF\",\"\",\"\",\"\",\"$2.68\"\n\
+              \"2019-12-30\",\"Cash Dividend\",\"KWEB\",\"KRANESHARES CSI CHINA INTERNET ETF\",\"\",\"\",\"\",\"$0.10\"\n",
+        );
+        let error = Schwab::default().load_from_csv(file.path()).unwrap_err();
+
+        match error.downcast_ref::<Error>() {
+            Some(Error::UnparsableDate { date, line, .. }) => {
+                assert_eq!(date, "2019-12-30");
+                assert_eq!(*line, 3);
+            }
+            _ => panic!("Unexpected error {error}"),
+        }
+    }
 }
@@ -1,4 +1,5 @@
-use super::IBroker;
+use super::{IBroker, UnknownActions, LINE};
+use crate::error::Error;
 use crate::schema::{self, Action, Column, Currency, Type};
 use crate::utils;
 
@@ -28,6 +29,8 @@ impl ApiConfig {
 pub struct Trading212 {
     currency: Currency,
     config: Option<ApiConfig>,
+    unknown: UnknownActions,
+    account: Option<String>,
 }
 
 impl Default for Trading212 {
@@ -38,19 +41,36 @@ impl Default for Trading212 {
 
 impl Trading212 {
     pub fn new(currency: Currency, config: Option<ApiConfig>) -> Self {
-        Self { currency, config }
+        Self {
+            currency,
+            config,
+            unknown: UnknownActions::default(),
+            account: None,
+        }
+    }
+
+    /// Map unknown actions to `Action::Ignore` instead of failing.
+    pub fn with_lenient(mut self, lenient: bool) -> Self {
+        self.unknown = UnknownActions::new(lenient);
+        self
+    }
+
+    /// Name of the account in the `Account` column, defaults to the broker name.
+    pub fn with_account(mut self, account: impl Into<String>) -> Self {
+        self.account = Some(account.into());
+        self
     }
 
-    fn map_action(s: &str) -> Action {
+    fn map_action(s: &str) -> Option<Action> {
         let collect: Vec<_> = s.split_whitespace().take(2).collect();
         match &collect[..] {
-            ["Deposit"] => Action::Deposit,
-            ["Withdrawal"] => Action::Withdraw,
-            [_, "buy"] => Action::Buy,
-            [_, "sell"] => Action::Sell,
-            ["Dividend", _] => Action::Dividend,
-            ["Interest", _] => Action::Interest,
-            _ => panic!("Unknown {s}"),
+            ["Deposit"] => Some(Action::Deposit),
+            ["Withdrawal"] => Some(Action::Withdraw),
+            [_, "buy"] => Some(Action::Buy),
+            [_, "sell"] => Some(Action::Sell),
+            ["Dividend", _] => Some(Action::Dividend),
+            ["Interest", _] => Some(Action::Interest),
+            _ => None,
         }
     }
 
@@ -89,18 +109,40 @@ impl OptCol {
 }
 
 impl IBroker for Trading212 {
+    fn name(&self) -> &'static str {
+        "Trading212"
+    }
+
+    fn account(&self) -> &str {
+        self.account.as_deref().unwrap_or(self.name())
+    }
+
     fn load_from_csv(&self, csv_file: &Path) -> Result<DataFrame> {
         // Workarrow: Remove rows with the string 'Not available'.
         let content = std::fs::read_to_string(csv_file).context(format!("{:?}", csv_file))?;
         let content = content.replace("Not available", "");
         let file = temp_file::with_contents(content.as_bytes());
-        let csv_file = file.path();
 
-        let df = LazyCsvReader::new(csv_file)
+        let df = LazyCsvReader::new(file.path())
             .has_header(true)
             .finish()?
-            .collect()?;
-        std::fs::remove_file(csv_file)?;
+            .collect()?
+            .with_row_index(LINE, Some(2))?;
+        std::fs::remove_file(file.path())?;
+        super::ensure_columns(
+            csv_file,
+            &df.get_column_names(),
+            &["Action", "Time", "Total"],
+        )?;
+        let action = self
+            .unknown
+            .map_column(csv_file, &df, "Action", Self::map_action)?;
+        super::ensure_dates(
+            csv_file,
+            &df,
+            "Time",
+            utils::polars::str_to_date_or_null("Time"),
+        )?;
 
         //TODO: check if there's a batter way of handling optional columns.
         let columns = df.get_column_names();
@@ -127,11 +169,8 @@ impl IBroker for Trading212 {
         let out = lazy_df
             .select([
                 // Rename columns to the standard data schema.
-                utils::polars::str_to_date("Time").alias(Column::Date.into()),
-                utils::polars::map_str_column("Action", |row| {
-                    Self::map_action(row.unwrap_or("Unknown")).into()
-                })
-                .alias(Column::Action.into()),
+                utils::polars::str_to_date_or_null("Time").alias(Column::Date.into()),
+                action.alias(Column::Action.into()),
                 col("Ticker")
                     .fill_null(lit("CASH"))
                     .alias(Column::Ticker.into()),
@@ -174,7 +213,7 @@ impl IBroker for Trading212 {
                 (col(Column::Amount.into()) / col(Column::Qty.into())).alias(Column::Price.into()),
             );
 
-        Ok(Self::sanitize(out).collect()?)
+        Ok(self.sanitize(out).collect()?)
     }
 
     fn load_from_api(&self, path: Option<&Path>) -> Result<DataFrame> {
@@ -245,6 +284,10 @@ impl IBroker for Trading212 {
             .unique(None, UniqueKeepStrategy::First)
             .collect()?)
     }
+
+    fn unknown_actions(&self) -> Vec<Error> {
+        self.unknown.take()
+    }
 }
 
 #[cfg(test)]
@@ -300,4 +343,83 @@ mod unittest {
             output.as_os_str().to_str().unwrap()
         );
     }
+
+    #[test]
+    fn load_csv_with_account() {
+        let input_csv = Path::new("resources/tests/input/trading212/2022.csv");
+        let df = Trading212::default()
+            .with_account("ISA")
+            .load_from_csv(input_csv)
+            .unwrap()
+            .lazy()
+            .select([
+                col(Column::Account.as_str()).unique(),
+                col(Column::Broker.as_str()).unique(),
+            ])
+            .collect()
+            .unwrap();
+
+        let expected = df!(
+            Column::Account.as_str() => &["ISA"],
+            Column::Broker.as_str() => &["Trading212"],
+        )
+        .unwrap();
+        assert_eq!(expected, df);
+    }
+
+    fn unknown_action_input() -> temp_file::TempFile {
+        temp_file::with_contents(
+            b"Action,Time,ISIN,Ticker,Name,No. of shares,Price / share,Currency (Price / share),Exchange rate,Result,Currency (Result),Total,Currency (Total),Withholding tax,Currency (Withholding tax),Stamp duty reserve tax,Currency (Stamp duty reserve tax),Notes,ID,Currency conversion fee,Currency (Currency conversion fee)\n\
+              Deposit,2022-08-30 09:17:59,,,,,,,,,,500.00,\"GBP\",,,,,,e72297ed-fc44-4d10-9aaa-1c234aa5cda7,,\n\
+              Lending interest,2022-09-01 09:00:00,,,,,,,,,,0.12,\"GBP\",,,,,,d1f8a3c2-5b6e-4f7a-8c9d-0e1f2a3b4c5d,,\n",
+        )
+    }
+
+    #[test]
+    fn load_unknown_action_fails() {
+        let file = unknown_action_input();
+        let error = Trading212::default()
+            .load_from_csv(file.path())
+            .unwrap_err();
+
+        match error.downcast_ref::<Error>() {
+            Some(Error::UnknownAction { action, line, .. }) => {
+                assert_eq!(action, "Lending interest");
+                assert_eq!(*line, 3);
+            }
+            _ => panic!("Unexpected error {error}"),
+        }
+    }
+
+    #[test]
+    fn load_unknown_action_lenient() {
+        let file = unknown_action_input();
+        let broker = Trading212::default().with_lenient(true);
+        broker.load_from_csv(file.path()).unwrap();
+
+        let unknown = broker.unknown_actions();
+        assert_eq!(unknown.len(), 1);
+        assert!(matches!(&unknown[0], Error::UnknownAction { line: 3, .. }));
+        assert!(broker.unknown_actions().is_empty());
+    }
+
+    #[test]
+    fn load_unparsable_date_fails() {
+        let file = temp_file::with_contents(
+            b"Action,Time,Total\n\
+              Deposit,2022-08-30 09:17:59,500.00\n\
+              Deposit,30/08/2022 09:18,100.00\n",
+        );
+        let error = Trading212::default()
+            .load_from_csv(file.path())
+            .unwrap_err();
+
+        match error.downcast_ref::<Error>() {
+            Some(Error::UnparsableDate { date, line, .. }) => {
+                assert_eq!(date, "30/08/2022 09:18");
+                assert_eq!(*line, 3);
+            }
+            _ => panic!("Unexpected error {error}"),
+        }
+    }
 }
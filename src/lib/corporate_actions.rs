@@ -0,0 +1,236 @@
+use crate::error::Error;
+use crate::schema::{Action, Column, Country, Currency, Type};
+use crate::utils;
+use anyhow::{Context, Result};
+use polars::prelude::*;
+use std::path::Path;
+use std::str::FromStr;
+
+const REQUIRED: [Column; 4] = [Column::Date, Column::Action, Column::Ticker, Column::Qty];
+
+/// Corporate actions declared by the user in a csv file with the columns Date, Action, Ticker,
+/// Qty and optionally NewTicker and Price, with the same meaning as in the orders. A reverse split
+/// is a split with a ratio below 1, i.e. 0.1 for 1-for-10.
+///
+/// The file wins over the scraper: the scraped splits of a ticker with splits in the file are
+/// ignored, so a split with ratio 1 discards a wrong one. The splits reported by a broker win in
+/// the same way.
+#[derive(Default)]
+pub struct CorporateActions {
+    data: Option<DataFrame>,
+}
+
+impl CorporateActions {
+    pub fn from_file(file: &Path) -> Result<Self> {
+        let frame = LazyCsvReader::new(file)
+            .has_header(true)
+            .with_infer_schema_length(Some(0))
+            .finish()?
+            .collect()
+            .with_context(|| format!("Failed to read corporate actions {:?}", file))?;
+
+        let columns = frame.get_column_names();
+        if let Some(column) = REQUIRED.iter().find(|x| !columns.contains(&x.as_str())) {
+            return Err(Error::MissingColumn {
+                column: column.as_str().to_owned(),
+                file: file.to_path_buf(),
+            }
+            .into());
+        }
+        let optional = |column: Column| {
+            if columns.contains(&column.as_str()) {
+                col(column.as_str())
+            } else {
+                lit(NULL).cast(DataType::String).alias(column.as_str())
+            }
+        };
+
+        let data = frame
+            .clone()
+            .lazy()
+            .select([
+                utils::polars::str_to_date(Column::Date.as_str()),
+                col(Column::Action.as_str()),
+                col(Column::Ticker.as_str()),
+                col(Column::Qty.as_str()).cast(DataType::Float64),
+                optional(Column::Price)
+                    .cast(DataType::Float64)
+                    .fill_null(lit(0.0)),
+                optional(Column::NewTicker),
+            ])
+            .collect()?;
+
+        let actions = utils::polars::column_str(&data, Column::Action.as_str())?;
+        let new_tickers = data.column(Column::NewTicker.as_str())?.str()?;
+        for (i, (action, new_ticker)) in actions.into_iter().zip(new_tickers).enumerate() {
+            let line = i + 2;
+            match Action::from_str(action) {
+                Ok(Action::Split) => (),
+                Ok(Action::Rename | Action::SpinOff) => anyhow::ensure!(
+                    new_ticker.is_some_and(|x| !x.is_empty()),
+                    "{action} without NewTicker in {file:?} line {line}"
+                ),
+                _ => {
+                    return Err(Error::UnknownAction {
+                        action: action.to_owned(),
+                        file: file.to_path_buf(),
+                        line,
+                    }
+                    .into())
+                }
+            }
+        }
+
+        Ok(Self { data: Some(data) })
+    }
+
+    /// Adds the corporate actions to the orders, with the country and currency of their ticker.
+    pub fn merge_into(&self, orders: LazyFrame) -> Result<LazyFrame> {
+        let Some(data) = &self.data else {
+            return Ok(orders);
+        };
+
+        let listing = orders
+            .clone()
+            .group_by([col(Column::Ticker.as_str())])
+            .agg([
+                col(Column::Country.as_str()).first(),
+                col(Column::Currency.as_str()).last(),
+            ]);
+        let actions = data
+            .clone()
+            .lazy()
+            .join(
+                listing,
+                [col(Column::Ticker.as_str())],
+                [col(Column::Ticker.as_str())],
+                JoinArgs::new(JoinType::Left),
+            )
+            .select([
+                col(Column::Date.as_str()),
+                col(Column::Action.as_str()),
+                col(Column::Ticker.as_str()),
+                col(Column::Qty.as_str()),
+                col(Column::Price.as_str()),
+                lit(0.0).alias(Column::Amount.as_str()),
+                lit(0.0).alias(Column::Tax.as_str()),
+                lit(0.0).alias(Column::Commission.as_str()),
+                col(Column::Country.as_str()).fill_null(lit(Country::NA.as_str())),
+                col(Column::Currency.as_str()).fill_null(lit(Currency::USD.as_str())),
+                lit(Type::Stock.as_str()).alias(Column::Type.as_str()),
+                lit(NULL)
+                    .cast(DataType::String)
+                    .alias(Column::Account.as_str()),
+                lit(NULL)
+                    .cast(DataType::String)
+                    .alias(Column::Broker.as_str()),
+                col(Column::NewTicker.as_str()),
+            ]);
+
+        Ok(concat([orders, actions], Default::default())?
+            .sort([Column::Date.as_str()], Default::default()))
+    }
+
+    /// The scraped splits of the tickers without splits in the `orders`, which have the splits of
+    /// the file once merged and the ones reported by the brokers.
+    pub fn filter_splits(orders: LazyFrame, splits: DataFrame) -> Result<DataFrame> {
+        let tickers = orders
+            .filter(utils::polars::filter::split())
+            .select([col(Column::Ticker.as_str())])
+            .collect()?;
+        Ok(splits
+            .lazy()
+            .filter(
+                col(Column::Ticker.as_str())
+                    .is_in(lit(tickers.column(Column::Ticker.as_str())?.clone()))
+                    .not(),
+            )
+            .collect()?)
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+    use super::*;
+
+    fn orders() -> LazyFrame {
+        df!(
+            Column::Date.into() => &["2022-01-03", "2022-01-04"],
+            Column::Action.into() => &["Buy", "Buy"],
+            Column::Ticker.into() => &["FB", "TSLA"],
+            Column::Qty.into() => &[10.0, 3.0],
+            Column::Price.into() => &[300.0, 1200.0],
+            Column::Amount.into() => &[3000.0, 3600.0],
+            Column::Tax.into() => &[0.0, 0.0],
+            Column::Commission.into() => &[0.0, 0.0],
+            Column::Country.into() => &["Usa", "Usa"],
+            Column::Currency.into() => &["USD", "USD"],
+            Column::Type.into() => &["Stock", "Stock"],
+            Column::Account.into() => &["Schwab", "Schwab"],
+            Column::Broker.into() => &["Schwab", "Schwab"],
+            Column::NewTicker.into() => &[None::<&str>, None],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Column::Date.into()))
+    }
+
+    #[test]
+    fn merge_corporate_actions_file() {
+        let file = temp_file::with_contents(
+            b"Date,Action,Ticker,Qty,NewTicker\n\
+              2022-06-09,Rename,FB,1,META\n\
+              2022-08-25,Split,TSLA,3,\n",
+        );
+        let actions = CorporateActions::from_file(file.path()).unwrap();
+
+        let result = actions
+            .merge_into(orders())
+            .unwrap()
+            .select([
+                col(Column::Date.as_str()).cast(DataType::String),
+                col(Column::Action.as_str()),
+                col(Column::Ticker.as_str()),
+                col(Column::Qty.as_str()),
+                col(Column::Country.as_str()),
+                col(Column::NewTicker.as_str()),
+            ])
+            .collect()
+            .unwrap();
+
+        let expected = df!(
+            Column::Date.into() => &["2022-01-03", "2022-01-04", "2022-06-09", "2022-08-25"],
+            Column::Action.into() => &["Buy", "Buy", "Rename", "Split"],
+            Column::Ticker.into() => &["FB", "TSLA", "FB", "TSLA"],
+            Column::Qty.into() => &[10.0, 3.0, 1.0, 3.0],
+            Column::Country.into() => &["Usa"; 4],
+            Column::NewTicker.into() => &[None, None, Some("META"), None],
+        )
+        .unwrap();
+        assert_eq!(expected, result);
+
+        // The split of TSLA in the file wins over the scraped one.
+        let splits = df!(
+            Column::Date.into() => &["2020-08-31", "2022-08-25", "2020-08-31"],
+            Column::Ticker.into() => &["AAPL", "TSLA", "TSLA"],
+            Column::Qty.into() => &[4.0, 3.0, 5.0],
+        )
+        .unwrap();
+        let splits = CorporateActions::filter_splits(result.clone().lazy(), splits).unwrap();
+        assert_eq!(
+            utils::polars::column_str(&splits, Column::Ticker.as_str()).unwrap(),
+            ["AAPL"]
+        );
+    }
+
+    #[test]
+    fn corporate_actions_file_rejects_other_actions() {
+        let file = temp_file::with_contents(b"Date,Action,Ticker,Qty\n2022-06-09,Buy,FB,1\n");
+        let error = CorporateActions::from_file(file.path()).err().unwrap();
+
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::UnknownAction { line: 2, .. })
+        ));
+    }
+}
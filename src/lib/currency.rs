@@ -5,6 +5,33 @@ use anyhow::{ensure, Context, Result};
 use polars::prelude::*;
 use IntoLazy;
 
+const EXCHANGE_RATE: &str = "exchange_rate";
+
+/// Selects which exchange rate is used to convert the rows of a table.
+#[derive(Debug, Clone, Copy)]
+pub enum ExchangeRate {
+    /// The rate around the given date (today if None) is applied to every row.
+    Present(Option<chrono::NaiveDate>),
+    /// Each row is converted with the rate of its own `Date`, falling back to the previous trading day.
+    Historical,
+}
+
+pub fn convert(
+    table: impl IntoLazy,
+    by_col: &str,
+    columns: &[Expr],
+    currency: schema::Currency,
+    scraper: &mut impl IScraper,
+    rate: ExchangeRate,
+) -> Result<LazyFrame> {
+    match rate {
+        ExchangeRate::Present(present_date) => {
+            normalize(table, by_col, columns, currency, scraper, present_date)
+        }
+        ExchangeRate::Historical => normalize_historical(table, by_col, columns, currency, scraper),
+    }
+}
+
 pub fn normalize(
     table: impl IntoLazy,
     by_col: &str,
@@ -44,7 +71,6 @@ pub fn normalize(
         None,
     ))?;
 
-    const EXCHANGE_RATE: &str = "exchange_rate";
     let exchange_rate = data
         .quotes
         .lazy()
@@ -109,6 +135,120 @@ pub fn normalize(
     Ok(res)
 }
 
+/// Converts every row using the exchange rate of the row's `Date`, rows without a rate on the
+/// same day use the previous trading day, and rows older than the series use its first rate.
+pub fn normalize_historical(
+    table: impl IntoLazy,
+    by_col: &str,
+    columns: &[Expr],
+    currency: schema::Currency,
+    scraper: &mut impl IScraper,
+) -> Result<LazyFrame> {
+    let table = table.lazy();
+
+    let data_frame = table.clone().collect()?;
+    ensure!(
+        data_frame.shape().0 > 0,
+        "Argument table must not be empty!"
+    );
+
+    let mut currencies = utils::polars::column_str(&data_frame, by_col)?;
+    currencies.sort();
+    currencies.dedup();
+
+    if currencies.len() == 1 && currencies[0] == currency.as_str() {
+        return Ok(table);
+    }
+
+    for ticker_currency in currencies {
+        let ticker_currency = ticker_currency
+            .parse()
+            .with_context(|| format!("Can't parse {ticker_currency}"))?;
+        if ticker_currency != currency {
+            scraper.with_currency(ticker_currency, currency);
+        }
+    }
+
+    // Look a few days back so the first rows can fallback to the previous trading day.
+    let data = scraper.load_blocking(scraper::SearchPeriod::new(
+        Some(utils::polars::first_date(&data_frame) - chrono::Duration::days(7)),
+        Some(utils::polars::latest_date(&data_frame) + chrono::Duration::days(1)),
+        None,
+    ))?;
+
+    const ROW_INDEX: &str = "row_index";
+    const RATE_BEFORE: &str = "exchange_rate_before";
+    const RATE_AFTER: &str = "exchange_rate_after";
+    let exchange_rate = data
+        .quotes
+        .lazy()
+        // Find the origin currency, i.e "USD/GBP" -> "USD"
+        .select([
+            col(schema::Column::Date.as_str()),
+            utils::polars::map_str_column(schema::Column::Ticker.as_str(), |row| {
+                row.with_context(|| format!("Failed to unwrap {row:?}"))
+                    .unwrap()
+                    .split_once('/')
+                    .with_context(|| format!("Failed to split {row:?}"))
+                    .unwrap()
+                    .0
+            })
+            .alias(by_col),
+            col(schema::Column::Price.as_str()).alias(EXCHANGE_RATE),
+        ])
+        .sort([schema::Column::Date.as_str()], Default::default());
+
+    let asof = |strategy: AsofStrategy| {
+        JoinArgs::new(JoinType::AsOf(AsOfOptions {
+            strategy,
+            left_by: Some(vec![by_col.into()]),
+            right_by: Some(vec![by_col.into()]),
+            ..Default::default()
+        }))
+    };
+
+    let convert: Vec<_> = columns
+        .iter()
+        .map(|column| column.clone() * col(EXCHANGE_RATE))
+        .collect();
+
+    let on = [col(schema::Column::Date.as_str())];
+    let res = table
+        .with_row_index(ROW_INDEX, None)
+        .sort([schema::Column::Date.as_str()], Default::default())
+        .join(
+            exchange_rate
+                .clone()
+                .with_column(col(EXCHANGE_RATE).alias(RATE_BEFORE))
+                .select([col("*").exclude([EXCHANGE_RATE])]),
+            on.clone(),
+            on.clone(),
+            asof(AsofStrategy::Backward),
+        )
+        .join(
+            exchange_rate
+                .with_column(col(EXCHANGE_RATE).alias(RATE_AFTER))
+                .select([col("*").exclude([EXCHANGE_RATE])]),
+            on.clone(),
+            on,
+            asof(AsofStrategy::Forward),
+        )
+        .collect()?
+        .lazy()
+        .with_column(
+            col(RATE_BEFORE)
+                .fill_null(col(RATE_AFTER))
+                .fill_null(lit(1.0)) // If not available 1.
+                .alias(EXCHANGE_RATE),
+        )
+        .with_columns(convert)
+        .with_column(lit(currency.as_str()).alias(by_col))
+        .sort([ROW_INDEX], Default::default())
+        .select([col("*").exclude([ROW_INDEX, EXCHANGE_RATE, RATE_BEFORE, RATE_AFTER])]);
+
+    Ok(res)
+}
+
 #[cfg(test)]
 mod unittest {
     use super::*;
@@ -254,4 +394,44 @@ mod unittest {
 
         assert_eq!(orders, normalized);
     }
+
+    #[test]
+    fn normalize_historical_uses_rate_of_each_date() {
+        let orders = df! (
+            Date.into() => &["2024-01-01", "2024-01-02", "2024-01-04", "2024-01-05", "2024-01-06", "2024-01-03"],
+            Amount.into() => &[100.0, 100.0, 100.0, 100.0, 100.0, 200.0],
+            Currency.into() => &[USD, USD, USD, BRL, GBP, USD].map(|x| x.as_str()),
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Date.into()).alias(Date.into()));
+
+        let mut scraper = utils::test::mock::Scraper::new()
+            .with_history("USD/GBP", &[("2024-01-02", 0.80), ("2024-01-05", 0.75)])
+            .with_history("BRL/GBP", &[("2024-01-03", 0.16)]);
+        let normalized = normalize_historical(
+            orders,
+            schema::Column::Currency.as_str(),
+            &[col(Amount.as_str())],
+            GBP,
+            &mut scraper,
+        )
+        .unwrap()
+        .with_column(dtype_col(&DataType::Float64).round(2))
+        .collect()
+        .unwrap();
+
+        let expected = df! (
+            Date.into() => &["2024-01-01", "2024-01-02", "2024-01-04", "2024-01-05", "2024-01-06", "2024-01-03"],
+            Amount.into() => &[80.0, 80.0, 80.0, 16.0, 100.0, 160.0],
+            Currency.into() => &[GBP;6].map(|x| x.as_str()),
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Date.into()).alias(Date.into()))
+        .collect()
+        .unwrap();
+
+        assert_eq!(expected, normalized);
+    }
 }
@@ -31,15 +31,15 @@ impl Dividends {
         mut self,
         scraper: &mut impl IScraper,
         currency: Currency,
-        present_date: Option<chrono::NaiveDate>,
+        rate: currency::ExchangeRate,
     ) -> Result<Self> {
-        self.data = currency::normalize(
+        self.data = currency::convert(
             self.data.clone(),
             Column::Currency.as_str(),
             &[col(Column::Amount.as_str()), col(Column::Price.as_str())],
             currency,
             scraper,
-            present_date,
+            rate,
         )?;
 
         Ok(self)
@@ -111,7 +111,7 @@ mod unittest {
             Column::Dividends.into() => &[2.75, 3.26],
         )
         .unwrap()
-        .sort(&[Column::Ticker.as_str()], Default::default())
+        .sort([Column::Ticker.as_str()], Default::default())
         .unwrap();
         assert_eq!(expected, result);
     }
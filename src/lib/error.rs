@@ -0,0 +1,31 @@
+use std::path::PathBuf;
+
+/// Errors the caller may want to handle, everything else is reported through `anyhow`.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Unknown action {action:?} in {file:?} line {line}")]
+    UnknownAction {
+        action: String,
+        file: PathBuf,
+        line: usize,
+    },
+    #[error("Can't parse date {date:?} in {file:?} line {line}")]
+    UnparsableDate {
+        date: String,
+        file: PathBuf,
+        line: usize,
+    },
+    #[error("Missing column {column:?} in {file:?}")]
+    MissingColumn { column: String, file: PathBuf },
+    #[error("Unknown country {0:?}")]
+    UnknownCountry(String),
+    #[error("No prices of {ticker:?} in {dir:?}")]
+    MissingPrices { ticker: String, dir: PathBuf },
+    #[error("Prices of {ticker:?} in {dir:?} miss the dates from {start} to {end}")]
+    MissingDates {
+        ticker: String,
+        start: chrono::NaiveDate,
+        end: chrono::NaiveDate,
+        dir: PathBuf,
+    },
+}
@@ -72,8 +72,18 @@ impl GoogleSheet {
         })
     }
 
+    /// Upload to the `tab` instead of the configured one, i.e. one tab per account.
+    pub fn with_tab(mut self, tab: impl Into<String>) -> Self {
+        self.config.spreadsheet_tab = tab.into();
+        self
+    }
+
     fn authenticate(config: &GoogleSheetConfig) -> Result<Client> {
         let credentials = JsonOAuth::from_file(&config.credentials_file);
+        let re = Regex::new(
+            r"^http://localhost/\?state=(?<state>[\w\d-]+)&code=(?<code>[\w\d/-]+)?&scope.*",
+        )
+        .unwrap();
 
         loop {
             if let Ok(file_content) =
@@ -121,11 +131,6 @@ impl GoogleSheet {
                 .read_line(&mut url)
                 .expect("Failed to read line");
 
-            let re = Regex::new(
-                r"^http://localhost/\?state=(?<state>[\w\d-]+)&code=(?<code>[\w\d/-]+)?&scope.*",
-            )
-            .unwrap();
-
             let Some(caps) = re.captures(&url) else {
                 panic!("no match!: \n{}", url);
             };
@@ -226,6 +231,7 @@ mod unittest {
     use super::*;
 
     #[test]
+    #[allow(clippy::identity_op)]
     fn column_name_success() {
         assert!(&GoogleSheet::column_name(26 * 1 + 0) == "Z");
         assert!(&GoogleSheet::column_name(26 * 1 + 1) == "AA");
@@ -0,0 +1,177 @@
+use crate::dividends::Dividends;
+use crate::perpetual_inventory::AverageCost;
+use crate::schema::{Action, Column};
+use crate::utils;
+use anyhow::Result;
+use polars::prelude::*;
+
+const SPLIT_RATIO: &str = "SplitRatio";
+
+/// The valuation of each position for every trading day it was held.
+pub struct History {
+    orders: LazyFrame,
+}
+
+impl History {
+    pub fn from_orders(orders: impl IntoLazy) -> Self {
+        Self {
+            orders: orders.lazy(),
+        }
+    }
+
+    fn asof_by_ticker() -> JoinArgs {
+        JoinArgs::new(JoinType::AsOf(AsOfOptions {
+            strategy: AsofStrategy::Backward,
+            left_by: Some(vec![Column::Ticker.as_str().into()]),
+            right_by: Some(vec![Column::Ticker.as_str().into()]),
+            ..Default::default()
+        }))
+    }
+
+    /// Joins the position of each ticker at the date of each quote, the values are in the
+    /// currency of the ticker.
+    pub fn collect(self, quotes: &DataFrame) -> Result<DataFrame> {
+        let split_ratio = || {
+            when(col(Column::Action.as_str()).eq(lit(Action::Split.as_str())))
+                .then(col(Column::Qty.as_str()))
+                .otherwise(lit(1.0))
+        };
+
+        let positions = AverageCost::from_orders(self.orders.clone())
+            .with_cumulative()?
+            .collect()?
+            .lazy()
+            // Quotes are adjusted by the splits, so the positions are adjusted by the later ones.
+            .with_column(
+                (split_ratio()
+                    .cum_prod(false)
+                    .last()
+                    .over([col(Column::Ticker.as_str())])
+                    / split_ratio()
+                        .cum_prod(false)
+                        .over([col(Column::Ticker.as_str())]))
+                .alias(SPLIT_RATIO),
+            )
+            .group_by([col(Column::Ticker.as_str()), col(Column::Date.as_str())])
+            .agg([
+                (col(Column::AveragePrice.as_str()) / col(SPLIT_RATIO)).last(),
+                (col(Column::AccruedQty.as_str()) * col(SPLIT_RATIO)).last(),
+            ])
+            .sort([Column::Date.as_str()], Default::default());
+
+        let on = [col(Column::Date.as_str())];
+        let history = quotes
+            .clone()
+            .lazy()
+            .select([
+                col(Column::Date.as_str()),
+                col(Column::Ticker.as_str()),
+                col(Column::Price.as_str()).alias(Column::MarketPrice.as_str()),
+            ])
+            .sort([Column::Date.as_str()], Default::default())
+            .join(positions, on.clone(), on.clone(), Self::asof_by_ticker())
+            .filter(col(Column::AccruedQty.as_str()).gt(lit(0)));
+
+        let history = if let Ok(dividends) = Dividends::try_from_orders(self.orders) {
+            let dividends = dividends
+                .collect()?
+                .lazy()
+                .sort([Column::Date.as_str()], Default::default())
+                .select([
+                    col(Column::Date.as_str()),
+                    col(Column::Ticker.as_str()),
+                    col(Column::Amount.as_str())
+                        .cum_sum(false)
+                        .over([col(Column::Ticker.as_str())])
+                        .alias(Column::Dividends.as_str()),
+                ]);
+            history
+                .join(dividends, on.clone(), on, Self::asof_by_ticker())
+                .with_column(col(Column::Dividends.as_str()).fill_null(lit(0.0)))
+        } else {
+            history.with_column(lit(0.0).alias(Column::Dividends.as_str()))
+        };
+
+        Ok(history
+            .with_columns([
+                utils::polars::compute::market_value(),
+                utils::polars::compute::paper_profit(),
+            ])
+            .select([
+                col(Column::Date.as_str()),
+                col(Column::Ticker.as_str()),
+                col(Column::AccruedQty.as_str()),
+                col(Column::AveragePrice.as_str()),
+                col(Column::MarketPrice.as_str()),
+                col(Column::MarketValue.as_str()),
+                col(Column::PaperProfit.as_str()),
+                col(Column::Dividends.as_str()),
+            ])
+            .sort(
+                [Column::Ticker.as_str(), Column::Date.as_str()],
+                Default::default(),
+            )
+            .collect()?)
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+    use super::*;
+
+    #[test]
+    fn history_success() {
+        let orders = df!(
+            Column::Date.into() => &["2024-01-02", "2024-01-04", "2024-01-05", "2024-01-08", "2024-01-09"],
+            Column::Action.into() => &["Buy", "Dividend", "Buy", "Split", "Sell"],
+            Column::Ticker.into() => &["AAPL"; 5],
+            Column::Qty.into() => &[10.0, 1.0, 10.0, 2.0, 40.0],
+            Column::Price.into() => &[100.0, 5.0, 110.0, 0.0, 60.0],
+            Column::Amount.into() => &[1000.0, 5.0, 1100.0, 0.0, 2400.0],
+            Column::Currency.into() => &["USD"; 5],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Column::Date.into()))
+        .collect()
+        .unwrap();
+
+        let quotes = df!(
+            Column::Date.into() => &[
+                "2024-01-01", "2024-01-02", "2024-01-03", "2024-01-05",
+                "2024-01-08", "2024-01-09", "2024-01-10", "2024-01-03",
+            ],
+            Column::Ticker.into() => &["AAPL", "AAPL", "AAPL", "AAPL", "AAPL", "AAPL", "AAPL", "GOOGL"],
+            Column::Price.into() => &[49.0, 50.0, 51.0, 55.0, 56.0, 60.0, 61.0, 140.0],
+            Column::Currency.into() => &["USD"; 8],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Column::Date.into()))
+        .collect()
+        .unwrap();
+
+        let result = History::from_orders(orders)
+            .collect(&quotes)
+            .unwrap()
+            .lazy()
+            .with_column(col(Column::Date.as_str()).cast(DataType::String))
+            .collect()
+            .unwrap();
+
+        // The quantities before the split are doubled, as the quotes are adjusted.
+        let expected = df!(
+            Column::Date.into() => &["2024-01-02", "2024-01-03", "2024-01-05", "2024-01-08"],
+            Column::Ticker.into() => &["AAPL"; 4],
+            Column::AccruedQty.into() => &[20.0, 20.0, 40.0, 40.0],
+            Column::AveragePrice.into() => &[50.0, 50.0, 52.5, 52.5],
+            Column::MarketPrice.into() => &[50.0, 51.0, 55.0, 56.0],
+            Column::MarketValue.into() => &[1000.0, 1020.0, 2200.0, 2240.0],
+            Column::PaperProfit.into() => &[0.0, 20.0, 100.0, 140.0],
+            Column::Dividends.into() => &[0.0, 0.0, 5.0, 5.0],
+        )
+        .unwrap();
+
+        assert_eq!(expected, result);
+    }
+}
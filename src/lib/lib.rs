@@ -1,14 +1,20 @@
 pub mod broker;
+pub mod corporate_actions;
 pub mod currency;
 pub mod dividends;
+pub mod error;
 pub mod global_conf;
 pub mod googlesheet;
+pub mod history;
 pub mod liquidated;
+pub mod lots;
 pub mod perpetual_inventory;
 pub mod portfolio;
 pub mod schema;
 pub mod scraper;
 pub mod summary;
+pub mod tax;
 pub mod timeline;
 pub mod uninvested;
 pub mod utils;
+pub mod xirr;
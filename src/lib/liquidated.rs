@@ -1,4 +1,5 @@
 use crate::currency;
+use crate::lots::{CostBasis, LotSelection, Lots};
 use crate::perpetual_inventory::AverageCost;
 use crate::schema::{Column, Currency};
 use crate::scraper::IScraper;
@@ -12,12 +13,39 @@ pub struct Profit {
 
 impl Profit {
     pub fn from_orders(orders: impl IntoLazy) -> Result<Self> {
-        let orders: LazyFrame = orders.lazy();
-        let data = AverageCost::from_orders(orders.clone())
-            .with_cumulative()
+        Self::from_orders_with(orders, CostBasis::AverageCost, LotSelection::default())
+    }
+
+    /// The lot methods report a row for each sold lot, with its acquisition date and holding
+    /// period. The `selection` picks the lots of specific sells.
+    pub fn from_orders_with(
+        orders: impl IntoLazy,
+        cost_basis: CostBasis,
+        selection: LotSelection,
+    ) -> Result<Self> {
+        let data = match cost_basis {
+            CostBasis::AverageCost => {
+                ensure!(selection.is_empty(), "The average cost can't select lots");
+                Self::average_cost(orders.lazy())?
+            }
+            _ => Lots::from_orders(orders, cost_basis)
+                .with_selection(selection)
+                .disposals()?,
+        };
+
+        ensure!(
+            data.shape().0 > 0,
+            "Profit: Orders must contain selling operations"
+        );
+        Ok(Profit { data: data.lazy() })
+    }
+
+    fn average_cost(orders: LazyFrame) -> Result<DataFrame> {
+        let data = AverageCost::from_orders(orders)
+            .with_cumulative()?
             .collect()?
             .lazy()
-            .filter(utils::polars::filter::sell())
+            .filter(utils::polars::filter::disposal())
             .group_by([
                 Column::Date.as_str(),
                 Column::Ticker.as_str(),
@@ -40,27 +68,26 @@ impl Profit {
                 col(Column::Profit.into()),
             ]);
 
-        let data = data.collect()?.agg_chunks();
-        ensure!(
-            data.shape().0 > 0,
-            "Profit: Orders must contain selling operations"
-        );
-        Ok(Profit { data: data.lazy() })
+        Ok(data.collect()?.agg_chunks())
     }
 
     pub fn normalize_currency(
         mut self,
         scraper: &mut impl IScraper,
         currency: Currency,
-        present_date: Option<chrono::NaiveDate>,
+        rate: currency::ExchangeRate,
     ) -> Result<Self> {
-        self.data = currency::normalize(
+        self.data = currency::convert(
             self.data.clone(),
             Column::Currency.as_str(),
-            &[col(Column::Amount.as_str()), col(Column::Price.as_str())],
+            &[
+                col(Column::Amount.as_str()),
+                col(Column::Price.as_str()),
+                col(Column::Profit.as_str()),
+            ],
             currency,
             scraper,
-            present_date,
+            rate,
         )?;
 
         Ok(self)
@@ -122,6 +149,44 @@ mod unittest {
         assert_eq!(expected, result);
     }
 
+    #[test]
+    fn realized_profit_normalize_currency() {
+        let orders = utils::test::generate_mocking_orders()
+            .lazy()
+            .with_column(lit(Currency::GBP.as_str()).alias(Column::Currency.as_str()));
+
+        let mut scraper = utils::test::mock::Scraper::new();
+        let result = Profit::from_orders(orders)
+            .unwrap()
+            .normalize_currency(
+                &mut scraper,
+                Currency::USD,
+                currency::ExchangeRate::Present(None),
+            )
+            .unwrap()
+            .collect()
+            .unwrap()
+            .lazy()
+            .select([
+                col(Column::Currency.as_str()),
+                dtype_col(&DataType::Float64).round(2),
+            ])
+            .collect()
+            .unwrap();
+
+        // GBP/USD is 1.23 in the mocked scraper.
+        let expected = df! (
+            Column::Currency.into() => &["USD";3],
+            Column::Qty.into() => &[3.0, 4.0, 8.0],
+            Column::Price.into() => &[165.56, 43.54, 44.77],
+            Column::Amount.into() => &[496.67, 174.17, 358.18],
+            Column::Profit.into() => &[100.07, 3.72, 17.28],
+        )
+        .unwrap();
+
+        assert_eq!(expected, result);
+    }
+
     #[test]
     fn realized_profit_repeated_daily_sell() {
         let orders = utils::test::generate_mocking_orders().lazy();
@@ -171,4 +236,75 @@ mod unittest {
 
         assert_eq!(expected, result);
     }
+
+    #[test]
+    fn realized_profit_corporate_actions() {
+        let orders = utils::test::generate_corporate_action_orders();
+
+        let result = Profit::from_orders(orders)
+            .unwrap()
+            .collect()
+            .unwrap()
+            .lazy()
+            .with_column(dtype_col(&DataType::Float64).round(4))
+            .collect()
+            .unwrap();
+
+        // The cash of the merger disposes of the 25% of the cost basis not carried.
+        let expected = df! (
+            Column::Date.into() => &["2023-03-01", "2023-06-01"],
+            Column::Ticker.into() => &["SPIN", "SPIN"],
+            Column::Qty.into() => &[2.0, 8.0],
+            Column::Price.into() => &[100.0, 50.0],
+            Column::Amount.into() => &[200.0, 400.0],
+            Column::Currency.into() => &["USD", "USD"],
+            Column::Profit.into() => &[16.0, 216.0],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Column::Date.into()).alias(Column::Date.into()))
+        .collect()
+        .unwrap();
+
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn realized_profit_fifo() {
+        let orders = utils::test::generate_mocking_orders();
+
+        let result = Profit::from_orders_with(orders, CostBasis::Fifo, LotSelection::default())
+            .unwrap()
+            .collect()
+            .unwrap()
+            .lazy()
+            .select([
+                col(Column::Ticker.as_str()),
+                col(Column::AcquisitionDate.as_str()),
+                col(Column::Qty.as_str()),
+                col(Column::Profit.as_str()).round(4),
+                col(Column::HoldingPeriod.as_str()),
+            ])
+            .collect()
+            .unwrap();
+
+        // The second GOOGL sell takes the rest of the first lot and part of the second one.
+        let expected = df! (
+            Column::Ticker.into() => &["APPL", "GOOGL", "GOOGL", "GOOGL"],
+            Column::AcquisitionDate.into() => &["2024-03-21", "2024-04-15", "2024-04-15", "2024-04-22"],
+            Column::Qty.into() => &[3.0, 4.0, 4.0, 4.0],
+            Column::Profit.into() => &[81.36, 3.8, 7.8, 8.4],
+            Column::HoldingPeriod.into() => &["Short"; 4],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(
+            utils::polars::str_to_date(Column::AcquisitionDate.into())
+                .alias(Column::AcquisitionDate.into()),
+        )
+        .collect()
+        .unwrap();
+
+        assert_eq!(expected, result);
+    }
 }
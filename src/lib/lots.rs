@@ -0,0 +1,522 @@
+use crate::perpetual_inventory::carried_fraction;
+use crate::schema::{Action, Column};
+use crate::utils;
+use anyhow::{ensure, Context, Result};
+use chrono::{Months, NaiveDate};
+use polars::prelude::*;
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Lots smaller than this are considered sold.
+const EPSILON: f64 = 1e-9;
+
+/// How the cost of the sold shares is computed.
+#[derive(Debug, Default, Clone, Copy, PartialEq, strum::EnumString, strum::IntoStaticStr)]
+#[strum(serialize_all = "PascalCase")]
+pub enum CostBasis {
+    #[default]
+    AverageCost,
+    Fifo,
+    Lifo,
+    HighestCost,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, strum::IntoStaticStr)]
+#[strum(serialize_all = "PascalCase")]
+pub enum HoldingPeriod {
+    Short,
+    Long,
+}
+
+impl HoldingPeriod {
+    /// Long term when sold more than one year after the acquisition.
+    pub fn new(acquired: NaiveDate, sold: NaiveDate) -> Self {
+        if sold > acquired + Months::new(12) {
+            Self::Long
+        } else {
+            Self::Short
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        self.into()
+    }
+}
+
+/// The lots chosen for specific sells, by ticker and sell date.
+#[derive(Debug, Default, Clone)]
+pub struct LotSelection {
+    lots: HashMap<(String, NaiveDate), Vec<(NaiveDate, f64)>>,
+}
+
+impl LotSelection {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lots.is_empty()
+    }
+
+    /// Sell `qty` shares of the lot of `ticker` acquired on `acquired` on the `sold` date.
+    pub fn with_lot(
+        mut self,
+        ticker: impl Into<String>,
+        sold: NaiveDate,
+        acquired: NaiveDate,
+        qty: f64,
+    ) -> Self {
+        self.lots
+            .entry((ticker.into(), sold))
+            .or_default()
+            .push((acquired, qty));
+        self
+    }
+
+    /// Reads a csv file with the columns Date, Ticker, AcquisitionDate and Qty.
+    pub fn from_file(file: &Path) -> Result<Self> {
+        let frame = LazyCsvReader::new(file)
+            .has_header(true)
+            .with_infer_schema_length(Some(0))
+            .finish()?
+            .select([
+                utils::polars::str_to_date(Column::Date.as_str()),
+                col(Column::Ticker.as_str()),
+                utils::polars::str_to_date(Column::AcquisitionDate.as_str()),
+                col(Column::Qty.as_str()).cast(DataType::Float64),
+            ])
+            .collect()
+            .with_context(|| format!("Failed to read lot selection {:?}", file))?;
+
+        let sold = utils::polars::column_date(&frame, Column::Date.as_str())?;
+        let tickers = utils::polars::column_str(&frame, Column::Ticker.as_str())?;
+        let acquired = utils::polars::column_date(&frame, Column::AcquisitionDate.as_str())?;
+        let qty = utils::polars::column_f64(&frame, Column::Qty.as_str())?;
+
+        Ok(sold.into_iter().zip(tickers).zip(acquired).zip(qty).fold(
+            Self::new(),
+            |selection, (((sold, ticker), acquired), qty)| {
+                selection.with_lot(ticker, sold, acquired, qty)
+            },
+        ))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Lot {
+    acquired: NaiveDate,
+    qty: f64,
+    /// Cost per share.
+    cost: f64,
+}
+
+/// A row of the sold lots, `Amount` is the share of the proceeds of the lot.
+#[derive(Default)]
+struct Disposals {
+    date: Vec<NaiveDate>,
+    acquired: Vec<NaiveDate>,
+    ticker: Vec<String>,
+    qty: Vec<f64>,
+    price: Vec<f64>,
+    amount: Vec<f64>,
+    currency: Vec<String>,
+    profit: Vec<f64>,
+    holding_period: Vec<&'static str>,
+}
+
+impl Disposals {
+    fn finish(self) -> Result<DataFrame> {
+        Ok(df!(
+            Column::Date.as_str() => self.date,
+            Column::AcquisitionDate.as_str() => self.acquired,
+            Column::Ticker.as_str() => self.ticker,
+            Column::Qty.as_str() => self.qty,
+            Column::Price.as_str() => self.price,
+            Column::Amount.as_str() => self.amount,
+            Column::Currency.as_str() => self.currency,
+            Column::Profit.as_str() => self.profit,
+            Column::HoldingPeriod.as_str() => self.holding_period,
+        )?)
+    }
+}
+
+/// A sell of `qty` shares at `price`, matched against the lots of `ticker`.
+struct Sell<'a> {
+    date: NaiveDate,
+    ticker: &'a str,
+    qty: f64,
+    price: f64,
+    amount: f64,
+    currency: &'a str,
+}
+
+/// Keeps the purchase lots of each ticker and matches the sells against them.
+pub struct Lots {
+    orders: LazyFrame,
+    cost_basis: CostBasis,
+    selection: LotSelection,
+    lots: HashMap<String, Vec<Lot>>,
+    disposals: Disposals,
+}
+
+impl Lots {
+    /// `cost_basis` is the order in which the lots are sold, the average cost has no lots.
+    pub fn from_orders(orders: impl IntoLazy, cost_basis: CostBasis) -> Self {
+        Self {
+            orders: orders.lazy(),
+            cost_basis,
+            selection: LotSelection::default(),
+            lots: HashMap::new(),
+            disposals: Disposals::default(),
+        }
+    }
+
+    /// Sells the selected lots first, the rest follows the cost basis.
+    pub fn with_selection(mut self, selection: LotSelection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    fn sell(&mut self, sell: Sell) -> Result<()> {
+        let lots = self.lots.entry(sell.ticker.to_owned()).or_default();
+        let mut order: Vec<usize> = (0..lots.len()).collect();
+        match self.cost_basis {
+            CostBasis::Lifo => order.reverse(),
+            CostBasis::HighestCost => order.sort_by(|a, b| lots[*b].cost.total_cmp(&lots[*a].cost)),
+            _ => (),
+        }
+
+        // The selected lots go first, up to the selected quantity.
+        let mut picks: Vec<(usize, f64)> = Vec::new();
+        if let Some(selected) = self
+            .selection
+            .lots
+            .get(&(sell.ticker.to_owned(), sell.date))
+        {
+            for (acquired, qty) in selected {
+                let matching: Vec<usize> = order
+                    .iter()
+                    .copied()
+                    .filter(|i| lots[*i].acquired == *acquired)
+                    .collect();
+                let held: f64 = matching.iter().map(|i| lots[*i].qty).sum();
+                ensure!(
+                    !matching.is_empty(),
+                    "{}: No lot acquired on {acquired} to sell on {}",
+                    sell.ticker,
+                    sell.date
+                );
+                ensure!(
+                    *qty <= held + EPSILON,
+                    "{}: Selection of {qty} shares sold on {} exceeds the lot of {held} acquired on {acquired}",
+                    sell.ticker,
+                    sell.date
+                );
+                let mut qty = *qty;
+                for i in matching {
+                    let picked = qty.min(lots[i].qty);
+                    picks.push((i, picked));
+                    qty -= picked;
+                }
+            }
+        }
+        picks.extend(order.iter().map(|i| (*i, f64::INFINITY)));
+
+        let mut remaining = sell.qty;
+        let mut sold = vec![0.0; lots.len()];
+        for (i, limit) in picks {
+            let qty = remaining.min(limit).min(lots[i].qty - sold[i]);
+            if qty <= EPSILON {
+                continue;
+            }
+            sold[i] += qty;
+            remaining -= qty;
+            self.disposals
+                .push(&sell, qty, lots[i].acquired, lots[i].cost);
+        }
+        ensure!(
+            remaining <= EPSILON,
+            "{}: Sell of {} shares on {} exceeds the lots of {}",
+            sell.ticker,
+            sell.qty,
+            sell.date,
+            sell.qty - remaining
+        );
+
+        for (lot, sold) in lots.iter_mut().zip(sold) {
+            lot.qty -= sold;
+        }
+        lots.retain(|lot| lot.qty > EPSILON);
+        Ok(())
+    }
+
+    /// Moves the lots of `ticker` to `new_ticker` with `ratio` new shares per share and
+    /// `carried` of the cost, the acquisition dates are kept.
+    fn carry(&mut self, ticker: &str, new_ticker: &str, ratio: f64, carried: f64) {
+        let carried: Vec<_> = self
+            .lots
+            .get(ticker)
+            .into_iter()
+            .flatten()
+            .map(|lot| Lot {
+                acquired: lot.acquired,
+                qty: lot.qty * ratio,
+                cost: lot.cost * carried / ratio,
+            })
+            .collect();
+        let lots = self.lots.entry(new_ticker.to_owned()).or_default();
+        lots.extend(carried);
+        lots.sort_by_key(|lot| lot.acquired);
+    }
+
+    /// The sold lots with their holding period.
+    pub fn disposals(mut self) -> Result<DataFrame> {
+        ensure!(
+            self.cost_basis != CostBasis::AverageCost,
+            "The average cost has no lots"
+        );
+
+        let orders = self
+            .orders
+            .clone()
+            .filter(
+                utils::polars::filter::buy_or_sell_or_split()
+                    .or(utils::polars::filter::corporate_action()),
+            )
+            .sort(
+                [Column::Date.as_str()],
+                SortMultipleOptions::default().with_maintain_order(true),
+            )
+            .collect()?;
+
+        let dates = utils::polars::column_date(&orders, Column::Date.as_str())?;
+        let actions = utils::polars::column_str(&orders, Column::Action.as_str())?;
+        let tickers = utils::polars::column_str(&orders, Column::Ticker.as_str())?;
+        let currencies = utils::polars::column_str(&orders, Column::Currency.as_str())?;
+        let new_tickers = utils::polars::column_opt_str(&orders, Column::NewTicker.as_str())?;
+        let values = |column: Column| -> Result<Vec<f64>> {
+            Ok(orders
+                .column(column.as_str())?
+                .cast(&DataType::Float64)?
+                .f64()?
+                .into_iter()
+                .map(|x| x.unwrap_or(0.0))
+                .collect())
+        };
+        let (qty, price, amount) = (
+            values(Column::Qty)?,
+            values(Column::Price)?,
+            values(Column::Amount)?,
+        );
+
+        for i in 0..orders.height() {
+            let (ticker, date) = (tickers[i], dates[i]);
+            let sell = Sell {
+                date,
+                ticker,
+                qty: qty[i],
+                price: price[i],
+                amount: amount[i],
+                currency: currencies[i],
+            };
+            let action = Action::from_str(actions[i])?;
+            let fraction = carried_fraction(action, price[i]);
+            match action {
+                Action::Buy => self.lots.entry(ticker.to_owned()).or_default().push(Lot {
+                    acquired: date,
+                    qty: qty[i],
+                    cost: price[i],
+                }),
+                Action::Sell | Action::CashInLieu => self.sell(sell)?,
+                Action::Split => {
+                    for lot in self.lots.entry(ticker.to_owned()).or_default() {
+                        lot.qty *= qty[i];
+                        lot.cost /= qty[i];
+                    }
+                }
+                Action::Rename => {
+                    if let Some(new_ticker) = new_tickers[i] {
+                        self.carry(ticker, new_ticker, qty[i], fraction);
+                        self.lots.remove(ticker);
+                    }
+                }
+                Action::SpinOff => {
+                    if let Some(new_ticker) = new_tickers[i] {
+                        self.carry(ticker, new_ticker, qty[i], fraction);
+                    }
+                    for lot in self.lots.entry(ticker.to_owned()).or_default() {
+                        lot.cost *= 1.0 - fraction;
+                    }
+                }
+                Action::Merger => {
+                    // `Price` is the fraction of the cost of the cash, it disposes of every lot.
+                    if let Some(new_ticker) = new_tickers[i] {
+                        self.carry(ticker, new_ticker, qty[i], fraction);
+                    }
+                    let lots = self.lots.remove(ticker).unwrap_or_default();
+                    let held: f64 = lots.iter().map(|lot| lot.qty).sum();
+                    if amount[i] > 0.0 && held > 0.0 {
+                        let sell = Sell {
+                            qty: held,
+                            price: amount[i] / held,
+                            ..sell
+                        };
+                        for lot in lots {
+                            let cost = lot.cost * (1.0 - fraction);
+                            self.disposals.push(&sell, lot.qty, lot.acquired, cost);
+                        }
+                    }
+                }
+                _ => unreachable!("Only the inventory actions are filtered"),
+            }
+        }
+
+        self.disposals.finish()
+    }
+}
+
+impl Disposals {
+    fn push(&mut self, sell: &Sell, qty: f64, acquired: NaiveDate, cost: f64) {
+        self.date.push(sell.date);
+        self.acquired.push(acquired);
+        self.ticker.push(sell.ticker.to_owned());
+        self.qty.push(qty);
+        self.price.push(sell.price);
+        self.amount.push(sell.amount * qty / sell.qty);
+        self.currency.push(sell.currency.to_owned());
+        self.profit.push((sell.price - cost) * qty);
+        self.holding_period
+            .push(HoldingPeriod::new(acquired, sell.date).as_str());
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+    use super::*;
+
+    fn orders() -> DataFrame {
+        df!(
+            Column::Date.into() => &["2022-01-03", "2022-06-01", "2023-03-01", "2023-03-02"],
+            Column::Action.into() => &["Buy", "Buy", "Sell", "Sell"],
+            Column::Ticker.into() => &["AAPL"; 4],
+            Column::Qty.into() => &[10.0, 10.0, 5.0, 10.0],
+            Column::Price.into() => &[100.0, 150.0, 200.0, 180.0],
+            Column::Amount.into() => &[1000.0, 1500.0, 1000.0, 1800.0],
+            Column::Currency.into() => &["USD"; 4],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Column::Date.into()))
+        .collect()
+        .unwrap()
+    }
+
+    fn disposals(lots: Lots) -> DataFrame {
+        lots.disposals()
+            .unwrap()
+            .lazy()
+            .select([
+                col(Column::Date.as_str()).cast(DataType::String),
+                col(Column::AcquisitionDate.as_str()).cast(DataType::String),
+                col(Column::Qty.as_str()),
+                col(Column::Amount.as_str()),
+                col(Column::Profit.as_str()),
+                col(Column::HoldingPeriod.as_str()),
+            ])
+            .collect()
+            .unwrap()
+    }
+
+    #[test]
+    fn lots_fifo() {
+        let expected = df!(
+            Column::Date.into() => &["2023-03-01", "2023-03-02", "2023-03-02"],
+            Column::AcquisitionDate.into() => &["2022-01-03", "2022-01-03", "2022-06-01"],
+            Column::Qty.into() => &[5.0, 5.0, 5.0],
+            Column::Amount.into() => &[1000.0, 900.0, 900.0],
+            Column::Profit.into() => &[500.0, 400.0, 150.0],
+            Column::HoldingPeriod.into() => &["Long", "Long", "Short"],
+        )
+        .unwrap();
+
+        assert_eq!(
+            expected,
+            disposals(Lots::from_orders(orders(), CostBasis::Fifo))
+        );
+    }
+
+    #[test]
+    fn lots_lifo_and_highest_cost() {
+        let expected = df!(
+            Column::Date.into() => &["2023-03-01", "2023-03-02", "2023-03-02"],
+            Column::AcquisitionDate.into() => &["2022-06-01", "2022-06-01", "2022-01-03"],
+            Column::Qty.into() => &[5.0, 5.0, 5.0],
+            Column::Amount.into() => &[1000.0, 900.0, 900.0],
+            Column::Profit.into() => &[250.0, 150.0, 400.0],
+            Column::HoldingPeriod.into() => &["Short", "Short", "Long"],
+        )
+        .unwrap();
+
+        assert_eq!(
+            expected,
+            disposals(Lots::from_orders(orders(), CostBasis::Lifo))
+        );
+        assert_eq!(
+            expected,
+            disposals(Lots::from_orders(orders(), CostBasis::HighestCost))
+        );
+    }
+
+    #[test]
+    fn lots_specific_selection() {
+        let date = |x: &str| x.parse().unwrap();
+        let selection =
+            LotSelection::new().with_lot("AAPL", date("2023-03-01"), date("2022-06-01"), 2.0);
+
+        let expected = df!(
+            Column::Date.into() => &["2023-03-01", "2023-03-01", "2023-03-02", "2023-03-02"],
+            Column::AcquisitionDate.into() => &["2022-06-01", "2022-01-03", "2022-01-03", "2022-06-01"],
+            Column::Qty.into() => &[2.0, 3.0, 7.0, 3.0],
+            Column::Amount.into() => &[400.0, 600.0, 1260.0, 540.0],
+            Column::Profit.into() => &[100.0, 300.0, 560.0, 90.0],
+            Column::HoldingPeriod.into() => &["Short", "Long", "Long", "Short"],
+        )
+        .unwrap();
+
+        assert_eq!(
+            expected,
+            disposals(Lots::from_orders(orders(), CostBasis::Fifo).with_selection(selection))
+        );
+    }
+
+    #[test]
+    fn lots_invalid_sells_fail() {
+        let date = |x: &str| x.parse().unwrap();
+        let error = |lots: Lots| lots.disposals().unwrap_err().to_string();
+
+        let selection =
+            LotSelection::new().with_lot("AAPL", date("2023-03-01"), date("2022-02-01"), 2.0);
+        assert_eq!(
+            error(Lots::from_orders(orders(), CostBasis::Fifo).with_selection(selection)),
+            "AAPL: No lot acquired on 2022-02-01 to sell on 2023-03-01"
+        );
+
+        let selection =
+            LotSelection::new().with_lot("AAPL", date("2023-03-01"), date("2022-06-01"), 12.0);
+        assert_eq!(
+            error(Lots::from_orders(orders(), CostBasis::Fifo).with_selection(selection)),
+            "AAPL: Selection of 12 shares sold on 2023-03-01 exceeds the lot of 10 acquired on 2022-06-01"
+        );
+
+        let orders = orders()
+            .lazy()
+            .filter(col(Column::Date.as_str()).neq(lit(date("2022-06-01"))))
+            .collect()
+            .unwrap();
+        assert_eq!(
+            error(Lots::from_orders(orders, CostBasis::Fifo)),
+            "AAPL: Sell of 10 shares on 2023-03-02 exceeds the lots of 5"
+        );
+    }
+}
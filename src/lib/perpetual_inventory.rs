@@ -1,14 +1,79 @@
 use crate::schema::{self, Action};
 use crate::utils;
 use anyhow::Result;
-use polars::lazy::dsl::as_struct;
 use polars::prelude::*;
+use std::collections::HashMap;
 use std::str::FromStr;
 
 pub struct AverageCost {
     data: LazyFrame,
 }
 
+/// The fraction of the cost basis a rename, merger or spin-off carries to the `NewTicker`,
+/// `price` is the `Price` of the order.
+pub fn carried_fraction(action: Action, price: f64) -> f64 {
+    match action {
+        Action::Rename => 1.0,
+        Action::Merger => 1.0 - price,
+        Action::SpinOff => price,
+        _ => 0.0,
+    }
+}
+
+/// The rows of the orders with the running average price and quantity of their ticker.
+#[derive(Default)]
+struct Rows<'a> {
+    index: Vec<IdxSize>,
+    ticker: Vec<&'a str>,
+    action: Vec<&'static str>,
+    qty: Vec<f64>,
+    price: Vec<f64>,
+    amount: Vec<f64>,
+    average_price: Vec<f64>,
+    accrued_qty: Vec<f64>,
+}
+
+impl<'a> Rows<'a> {
+    #[allow(clippy::too_many_arguments)]
+    fn push(
+        &mut self,
+        index: usize,
+        ticker: &'a str,
+        action: Action,
+        qty: f64,
+        price: f64,
+        amount: f64,
+        average_price: f64,
+        accrued_qty: f64,
+    ) {
+        self.index.push(index as IdxSize);
+        self.ticker.push(ticker);
+        self.action.push(action.as_str());
+        self.qty.push(qty);
+        self.price.push(price);
+        self.amount.push(amount);
+        self.average_price.push(average_price);
+        self.accrued_qty.push(accrued_qty);
+    }
+
+    /// Copies the other columns from the rows of `frame` at `index`.
+    fn finish(self, frame: &DataFrame) -> Result<DataFrame> {
+        let mut result = frame.take(&IdxCa::from_vec("", self.index))?;
+        for series in [
+            Series::new(schema::Column::Ticker.as_str(), self.ticker),
+            Series::new(schema::Column::Action.as_str(), self.action),
+            Series::new(schema::Column::Qty.as_str(), self.qty),
+            Series::new(schema::Column::Price.as_str(), self.price),
+            Series::new(schema::Column::Amount.as_str(), self.amount),
+            Series::new(schema::Column::AveragePrice.as_str(), self.average_price),
+            Series::new(schema::Column::AccruedQty.as_str(), self.accrued_qty),
+        ] {
+            result.with_column(series)?;
+        }
+        Ok(result)
+    }
+}
+
 impl AverageCost {
     pub fn from_orders(orders: impl IntoLazy) -> Self {
         Self {
@@ -19,76 +84,103 @@ impl AverageCost {
     }
     /// The Perpetual inventory average cost can be computed by the formula:
     /// avg[n] = ((avg[n-1] * cum_qty[n-1] + amount[n] ) / cum_qty[n]) if (qty[n] > 0) otherwise avg[n-1]
-    pub fn with_cumulative(mut self) -> Self {
-        self.data = self
+    ///
+    /// Renames, mergers and spin-offs carry the cost basis to the `NewTicker`, the shares received
+    /// are added as a buy at the carried cost. The cash of a merger is a disposal of the basis not
+    /// carried, its row holds the disposed quantity, the cash per share and the cost per share.
+    pub fn with_cumulative(mut self) -> Result<Self> {
+        let frame = self
             .data
-            .filter(utils::polars::filter::buy_or_sell_or_split())
-            .with_column(
-                // Use struct type to operate over two columns.
-                as_struct(vec![
-                    col(schema::Column::Price.into()),
-                    col(schema::Column::Qty.into()),
-                    col(schema::Column::Action.into()),
-                ])
-                // Apply function on group by Ticker.
-                .apply(
-                    |data| {
-                        // data is a Series with the whole column data after grouping.
-                        let (mut cum_price, mut cum_qty) = (0.0, 0.0);
-                        let (avg, cum_qty): (Vec<_>, Vec<_>) = data
-                            .struct_()?
-                            .into_iter()
-                            .map(|values| {
-                                // values is a slice with all fields of the struct.
-                                let mut iter = values.iter();
-                                //Unwrap fields of the struct as f64.
-                                let AnyValue::Float64(price) = iter.next().unwrap() else {
-                                    panic!("Can't unwrap price in {:?}", values);
-                                };
-                                let AnyValue::Float64(qty) = iter.next().unwrap() else {
-                                    panic!("Can't unwrap as qty in {:?}", values);
-                                };
-                                let AnyValue::String(action) = *iter.next().unwrap() else {
-                                    panic!("Can't unwrap Action in {:?}", values);
-                                };
-
-                                // Compute the cum_qty and average price using the formula above and return a tuple that will be converted into a struct.
-                                (cum_price, cum_qty) = match Action::from_str(action).unwrap() {
-                                    Action::Split => (cum_price / qty, cum_qty * qty),
-                                    Action::Sell => (cum_price, cum_qty - qty),
-                                    Action::Buy => {
-                                        let new_cum_qty = cum_qty + qty;
-                                        let cum_price =
-                                            (cum_price * cum_qty + price * qty) / new_cum_qty;
-                                        (cum_price, new_cum_qty)
-                                    }
-                                    _ => panic!("Unsupported action"),
-                                };
-                                (cum_price, cum_qty)
-                            })
-                            .unzip();
-
-                        // Maybe there's a batter way to construct a series of struct from map?
-                        Ok(Some(
-                            df!(
-                                schema::Column::AveragePrice.into() => avg.as_slice(),
-                                schema::Column::AccruedQty.into() => cum_qty.as_slice(),
-                            )?
-                            .into_struct("")
-                            .into_series(),
-                        ))
-                    },
-                    GetOutput::from_type(DataType::Struct(vec![Field {
-                        name: "".into(),
-                        dtype: DataType::Float64,
-                    }])),
-                )
-                .over([col(schema::Column::Ticker.into())])
-                .alias("struct"),
+            .filter(
+                utils::polars::filter::buy_or_sell_or_split()
+                    .or(utils::polars::filter::corporate_action()),
             )
-            // Break the struct column into separated columns.
-            .unnest(["struct"]);
-        self
+            .collect()?;
+
+        let tickers = utils::polars::column_str(&frame, schema::Column::Ticker.as_str())?;
+        let actions = utils::polars::column_str(&frame, schema::Column::Action.as_str())?;
+        let new_tickers =
+            utils::polars::column_opt_str(&frame, schema::Column::NewTicker.as_str())?;
+        let values = |column: schema::Column| -> Result<Vec<f64>> {
+            Ok(frame
+                .column(column.as_str())?
+                .cast(&DataType::Float64)?
+                .f64()?
+                .into_iter()
+                .map(|x| x.unwrap_or(0.0))
+                .collect())
+        };
+        let (qty, price, amount) = (
+            values(schema::Column::Qty)?,
+            values(schema::Column::Price)?,
+            values(schema::Column::Amount)?,
+        );
+
+        let mut positions: HashMap<&str, (f64, f64)> = HashMap::new();
+        let mut rows = Rows::default();
+        for i in 0..frame.height() {
+            let ticker = tickers[i];
+            let action = Action::from_str(actions[i])?;
+            let (avg, held) = positions.get(ticker).copied().unwrap_or_default();
+
+            // The cost basis carried to the new ticker.
+            let carried = avg * held * carried_fraction(action, price[i]);
+
+            let (new_avg, new_held) = match action {
+                Action::Split => (avg / qty[i], held * qty[i]),
+                Action::Sell | Action::CashInLieu => (avg, held - qty[i]),
+                Action::Buy => {
+                    let new_held = held + qty[i];
+                    ((avg * held + price[i] * qty[i]) / new_held, new_held)
+                }
+                Action::Rename | Action::Merger => (avg, 0.0),
+                Action::SpinOff if held > 0.0 => (avg - carried / held, held),
+                Action::SpinOff => (avg, held),
+                _ => unreachable!("Only the inventory actions are filtered"),
+            };
+            positions.insert(ticker, (new_avg, new_held));
+
+            if action == Action::Merger && held > 0.0 {
+                // The cash disposes of the shares at the basis not carried.
+                let cost = avg - carried / held;
+                rows.push(
+                    i,
+                    ticker,
+                    action,
+                    held,
+                    amount[i] / held,
+                    amount[i],
+                    cost,
+                    0.0,
+                );
+            } else {
+                rows.push(
+                    i, ticker, action, qty[i], price[i], amount[i], new_avg, new_held,
+                );
+            }
+
+            let received = held * qty[i];
+            if let (Some(new_ticker), true) = (new_tickers[i], received > 0.0) {
+                let (avg, held) = positions.get(new_ticker).copied().unwrap_or_default();
+                let total = held + received;
+                let avg = (avg * held + carried) / total;
+                positions.insert(new_ticker, (avg, total));
+                let price = carried / received;
+                rows.push(
+                    i,
+                    new_ticker,
+                    Action::Buy,
+                    received,
+                    price,
+                    carried,
+                    avg,
+                    total,
+                );
+            }
+        }
+
+        self.data = rows.finish(&frame)?.lazy();
+        Ok(self)
     }
 
     pub fn collect(self) -> Result<DataFrame> {
@@ -121,6 +213,7 @@ mod unittest {
 
         let result = AverageCost::from_orders(orders)
             .with_cumulative()
+            .unwrap()
             .collect_latest()
             // .collect()
             .unwrap()
@@ -139,7 +232,37 @@ mod unittest {
             Column::AccruedQty.into() => &[13.20, 10.0],
         )
         .unwrap()
-        .sort(&[Column::Ticker.as_str()], Default::default())
+        .sort([Column::Ticker.as_str()], Default::default())
+        .unwrap();
+
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn average_cost_corporate_actions() {
+        let orders = utils::test::generate_corporate_action_orders();
+
+        let result = AverageCost::from_orders(orders)
+            .with_cumulative()
+            .unwrap()
+            .collect_latest()
+            .unwrap()
+            .lazy()
+            .select([
+                col(Column::Ticker.into()),
+                dtype_col(&DataType::Float64).round(4),
+            ])
+            .sort([Column::Ticker.as_str()], Default::default())
+            .collect()
+            .unwrap();
+
+        // The renamed position keeps its cost, the spin-off takes 20% of it and the
+        // merger carries 75% of it to the 12 shares received, the rest is the cost of the cash.
+        let expected = df! (
+            Column::Ticker.into() => &["ACQ", "FB", "META", "SPIN"],
+            Column::AveragePrice.into() => &[46.0, 300.0, 184.0, 23.0],
+            Column::AccruedQty.into() => &[12.0, 0.0, 20.0, 0.0],
+        )
         .unwrap();
 
         assert_eq!(expected, result);
@@ -1,8 +1,9 @@
 use crate::currency;
 use crate::perpetual_inventory::AverageCost;
 use crate::schema;
-use crate::scraper::IScraper;
+use crate::scraper::{overrides, IScraper};
 use crate::utils;
+use crate::xirr;
 use anyhow::{ensure, Result};
 use polars::lazy::dsl::dtype_col;
 use polars::prelude::*;
@@ -12,6 +13,7 @@ pub struct Portfolio {
     working_frame: LazyFrame,
     uninvested_cash: Option<LazyFrame>,
     present_date: chrono::NaiveDate,
+    manual_prices: Series,
 }
 
 impl Portfolio {
@@ -20,8 +22,11 @@ impl Portfolio {
         present_date: Option<chrono::NaiveDate>,
     ) -> Result<Self> {
         let raw_input: LazyFrame = orders.lazy();
-        let result = raw_input
-            .clone()
+        // The shares received by corporate actions are buys in the average cost.
+        let result = AverageCost::from_orders(raw_input.clone())
+            .with_cumulative()?
+            .collect()?
+            .lazy()
             // Filter buy and sell actions.
             .filter(utils::polars::filter::buy_or_sell())
             .with_column(utils::polars::compute::negative_qty_on_sell())
@@ -53,29 +58,55 @@ impl Portfolio {
             raw_input,
             uninvested_cash: None,
             present_date: present_date.unwrap_or(chrono::Local::now().date_naive()),
+            manual_prices: Series::new_empty(schema::Column::Ticker.as_str(), &DataType::String),
         })
     }
 
+    /// The market price is the latest quote in its currency, `ManualPrice` flags the prices of the
+    /// overrides files.
     pub fn with_quotes(mut self, quotes: &DataFrame) -> Result<Self> {
+        let manual = if quotes
+            .get_column_names()
+            .contains(&schema::Column::Source.as_str())
+        {
+            col(schema::Column::Source.as_str()).eq(lit(overrides::MANUAL))
+        } else {
+            lit(false)
+        };
+        let latest = |column: Expr| {
+            column
+                .sort_by(
+                    [col(schema::Column::Date.as_str())],
+                    SortMultipleOptions::default(),
+                )
+                .last()
+        };
         let quotes = quotes
             .clone()
             .lazy()
             .filter(col(schema::Column::Date.as_str()).lt_eq(lit(self.present_date)))
-            .with_column(
-                col(schema::Column::Currency.as_str())
+            .with_column(manual.alias(schema::Column::ManualPrice.as_str()))
+            // An override may be in another currency than the scraped quotes.
+            .group_by([col(schema::Column::Ticker.as_str())])
+            .agg([
+                latest(col(schema::Column::Currency.as_str()))
                     .alias(schema::Column::MarketPriceCurrency.as_str()),
-            )
-            .group_by([
-                col(schema::Column::Ticker.as_str()),
-                col(schema::Column::MarketPriceCurrency.as_str()),
+                latest(col(schema::Column::Price.as_str()))
+                    .alias(schema::Column::MarketPrice.into()),
+                latest(col(schema::Column::ManualPrice.as_str())),
             ])
-            .agg([col(schema::Column::Price.as_str())
-                .sort_by(
-                    [col(schema::Column::Date.as_str())],
-                    SortMultipleOptions::default(),
-                )
-                .last()
-                .alias(schema::Column::MarketPrice.into())]);
+            .collect()?;
+        // Flagged when collected, the bool column wouldn't survive the fill of the joins.
+        self.manual_prices = quotes
+            .clone()
+            .lazy()
+            .filter(col(schema::Column::ManualPrice.as_str()))
+            .collect()?
+            .column(schema::Column::Ticker.as_str())?
+            .clone();
+        let quotes = quotes
+            .lazy()
+            .select([col("*").exclude([schema::Column::ManualPrice.as_str()])]);
         let result = self.working_frame.collect()?;
 
         let match_on: Vec<_> = [schema::Column::Ticker]
@@ -93,7 +124,7 @@ impl Portfolio {
 
     pub fn with_average_price(mut self) -> Result<Self> {
         let avg = AverageCost::from_orders(self.raw_input.clone())
-            .with_cumulative()
+            .with_cumulative()?
             .collect_latest()
             .expect("Average cost failed");
 
@@ -128,6 +159,82 @@ impl Portfolio {
         self
     }
 
+    /// Annualized return of each position, where buys, sells, dividends and taxes are the
+    /// cash flows and the market value is the terminal one, null when it has no solution. Requires
+    /// the paper profit.
+    pub fn with_xirr(
+        mut self,
+        scraper: &mut impl IScraper,
+        currency: schema::Currency,
+        rate: currency::ExchangeRate,
+    ) -> Result<Self> {
+        let action = || col(schema::Column::Action.as_str());
+        let flows = self
+            .raw_input
+            .clone()
+            .filter(col(schema::Column::Date.as_str()).lt_eq(lit(self.present_date)))
+            .filter(
+                action().is_in(lit(Series::new(
+                    "flows",
+                    [
+                        schema::Action::Buy,
+                        schema::Action::Sell,
+                        schema::Action::Dividend,
+                        schema::Action::Tax,
+                    ]
+                    .map(schema::Action::as_str),
+                ))),
+            )
+            .select([
+                col(schema::Column::Date.as_str()),
+                col(schema::Column::Ticker.as_str()),
+                col(schema::Column::Currency.as_str()),
+                when(
+                    action()
+                        .eq(lit(schema::Action::Buy.as_str()))
+                        .or(action().eq(lit(schema::Action::Tax.as_str()))),
+                )
+                .then(col(schema::Column::Amount.as_str()) * lit(-1))
+                .otherwise(col(schema::Column::Amount.as_str()))
+                .alias(schema::Column::Amount.as_str()),
+            ]);
+
+        let columns = [
+            col(schema::Column::Date.as_str()),
+            col(schema::Column::Ticker.as_str()),
+            col(schema::Column::Amount.as_str()),
+        ];
+        let flows = currency::convert(
+            flows,
+            schema::Column::Currency.as_str(),
+            &[col(schema::Column::Amount.as_str())],
+            currency,
+            scraper,
+            rate,
+        )?
+        .select(&columns);
+
+        let market_value = self.working_frame.clone().select([
+            lit(self.present_date)
+                .cast(DataType::Date)
+                .alias(schema::Column::Date.as_str()),
+            col(schema::Column::Ticker.as_str()),
+            col(schema::Column::MarketValue.as_str()).alias(schema::Column::Amount.as_str()),
+        ]);
+
+        let rates =
+            xirr::by_ticker(&concat([flows, market_value], Default::default())?.collect()?)?;
+
+        self.working_frame = self.working_frame.join(
+            rates.lazy(),
+            [col(schema::Column::Ticker.into())],
+            [col(schema::Column::Ticker.into())],
+            JoinArgs::new(JoinType::Left),
+        );
+
+        Ok(self)
+    }
+
     pub fn with_profit(mut self) -> Self {
         self.working_frame = self
             .working_frame
@@ -270,6 +377,11 @@ impl Portfolio {
         Ok(self
             .working_frame
             .select([col("*").exclude(exclude)])
+            .with_column(
+                col(schema::Column::Ticker.as_str())
+                    .is_in(lit(self.manual_prices))
+                    .alias(schema::Column::ManualPrice.as_str()),
+            )
             .collect()?)
     }
 }
@@ -316,6 +428,99 @@ mod unittest {
         assert_eq!(expected, result);
     }
 
+    #[test]
+    fn portfolio_flags_manual_prices() {
+        let orders = utils::test::generate_mocking_orders();
+
+        let mut scraper = utils::test::mock::Scraper::new();
+        let quotes = scraper
+            .with_ticker(&["GOOGL".to_owned(), "APPL".to_owned()], None)
+            .load_blocking(SearchPeriod::new(None, None, None))
+            .unwrap()
+            .quotes
+            .lazy()
+            .with_column(
+                when(col(Column::Ticker.as_str()).eq(lit("GOOGL")))
+                    .then(lit(overrides::MANUAL))
+                    .otherwise(lit("Mock"))
+                    .alias(Column::Source.as_str()),
+            )
+            .collect()
+            .unwrap();
+        let result = Portfolio::try_from_orders(orders, None)
+            .unwrap()
+            .with_quotes(&quotes)
+            .unwrap()
+            .collect()
+            .unwrap()
+            .lazy()
+            .select([col(Column::Ticker.into()), col(Column::ManualPrice.into())])
+            .sort([Column::Ticker.as_str()], Default::default())
+            .collect()
+            .unwrap();
+
+        let expected = df! (
+            Column::Ticker.into() => &["APPL", "GOOGL"],
+            Column::ManualPrice.into() => &[false, true],
+        )
+        .unwrap();
+
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn portfolio_with_override_in_other_currency() {
+        let orders = utils::test::generate_mocking_orders();
+
+        let mut scraper = utils::test::mock::Scraper::new();
+        let quotes = scraper
+            .with_ticker(&["GOOGL".to_owned(), "APPL".to_owned()], None)
+            .load_blocking(SearchPeriod::new(None, None, None))
+            .unwrap()
+            .quotes
+            .lazy()
+            .with_column(lit("Mock").alias(Column::Source.as_str()));
+        let manual = df!(
+            Column::Date.into() => &["2022-10-02"],
+            Column::Ticker.into() => &["GOOGL"],
+            Column::Price.into() => &[27.5],
+            Column::Currency.into() => &["GBP"],
+            Column::Source.into() => &[overrides::MANUAL],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(col(Column::Date.into()).cast(DataType::Date));
+        let quotes = concat([quotes, manual], Default::default())
+            .unwrap()
+            .collect()
+            .unwrap();
+
+        let result = Portfolio::try_from_orders(orders, None)
+            .unwrap()
+            .with_quotes(&quotes)
+            .unwrap()
+            .collect()
+            .unwrap()
+            .lazy()
+            .select([
+                col(Column::Ticker.into()),
+                col(Column::MarketPrice.into()),
+                col(Column::MarketPriceCurrency.into()),
+            ])
+            .sort([Column::Ticker.as_str()], Default::default())
+            .collect()
+            .unwrap();
+
+        let expected = df! (
+            Column::Ticker.into() => &["APPL", "GOOGL"],
+            Column::MarketPrice.into() => &[103.95, 27.5],
+            Column::MarketPriceCurrency.into() => &["USD", "GBP"],
+        )
+        .unwrap();
+
+        assert_eq!(expected, result);
+    }
+
     #[test]
     fn portfolio_with_average_price_success() {
         let orders = utils::test::generate_mocking_orders();
@@ -590,4 +795,115 @@ mod unittest {
         std::env::set_var("POLARS_FMT_MAX_COLS", "20"); // maximum number of columns shown when formatting DataFrames.
         assert_eq!(expected, result);
     }
+
+    #[test]
+    fn portfolio_with_xirr_success() {
+        let orders = df!(
+            Column::Date.into() => &["2023-01-01", "2023-01-01", "2024-01-01"],
+            Column::Action.into() => &["Buy", "Buy", "Dividend"],
+            Column::Ticker.into() => &["AAPL", "GOOGL", "AAPL"],
+            Column::Qty.into() => &[10.0, 10.0, 1.0],
+            Column::Price.into() => &[100.0, 100.0, 50.0],
+            Column::Amount.into() => &[1000.0, 1000.0, 50.0],
+            Column::Country.into() => &["Usa", "Usa", "Usa"],
+            Column::Currency.into() => &["USD", "USD", "USD"],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Column::Date.into()))
+        .collect()
+        .unwrap();
+
+        let quotes = df!(
+            Column::Date.into() => &["2024-01-01", "2024-01-01"],
+            Column::Ticker.into() => &["AAPL", "GOOGL"],
+            Column::Price.into() => &[105.0, 90.0],
+            Column::Currency.into() => &["USD", "USD"],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Column::Date.into()))
+        .collect()
+        .unwrap();
+
+        let mut scraper = utils::test::mock::Scraper::new();
+        let result = Portfolio::try_from_orders(orders, "2024-01-01".parse().ok())
+            .unwrap()
+            .with_quotes(&quotes)
+            .unwrap()
+            .with_average_price()
+            .unwrap()
+            .paper_profit()
+            .with_xirr(
+                &mut scraper,
+                schema::Currency::USD,
+                currency::ExchangeRate::Present("2024-01-01".parse().ok()),
+            )
+            .unwrap()
+            .collect()
+            .unwrap()
+            .lazy()
+            .select([
+                col(Column::Ticker.into()),
+                col(Column::Xirr.into()).round(4),
+            ])
+            .sort([Column::Ticker.as_str()], Default::default())
+            .collect()
+            .unwrap();
+
+        // AAPL is worth 1050 plus 50 of dividends a year after paying 1000.
+        let expected = df! (
+            Column::Ticker.into() => &["AAPL", "GOOGL"],
+            Column::Xirr.into() => &[10.0, -10.0],
+        )
+        .unwrap();
+
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn portfolio_with_corporate_actions() {
+        let orders = utils::test::generate_corporate_action_orders();
+        let quotes = df!(
+            Column::Date.into() => &["2023-06-30", "2023-06-30"],
+            Column::Ticker.into() => &["META", "ACQ"],
+            Column::Price.into() => &[280.0, 50.0],
+            Column::Currency.into() => &["USD", "USD"],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Column::Date.into()))
+        .collect()
+        .unwrap();
+
+        let result = Portfolio::try_from_orders(orders, "2023-07-01".parse().ok())
+            .unwrap()
+            .with_quotes(&quotes)
+            .unwrap()
+            .with_average_price()
+            .unwrap()
+            .collect()
+            .unwrap()
+            .lazy()
+            .select([
+                col(Column::Ticker.into()),
+                col(Column::AccruedQty.into()),
+                col(Column::AveragePrice.into()).round(4),
+                col(Column::MarketPrice.into()),
+            ])
+            .sort([Column::Ticker.as_str()], Default::default())
+            .collect()
+            .unwrap();
+
+        // The renamed and the received positions keep their cost basis.
+        let expected = df! (
+            Column::Ticker.into() => &["ACQ", "META"],
+            Column::AccruedQty.into() => &[12.0, 20.0],
+            Column::AveragePrice.into() => &[46.0, 184.0],
+            Column::MarketPrice.into() => &[50.0, 280.0],
+        )
+        .unwrap();
+
+        assert_eq!(expected, result);
+    }
 }
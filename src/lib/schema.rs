@@ -12,6 +12,14 @@ pub enum Column {
     Commission,
     Country,
     Currency,
+    Account,
+    Broker,
+    NewTicker,
+    AcquisitionDate,
+    HoldingPeriod,
+    DisallowedLoss,
+    ReplacementDate,
+    AdjustedPrice,
     PortfolioCost,
     UninvestedCash,
     AveragePrice,
@@ -30,6 +38,33 @@ pub enum Column {
     LiquidatedProfit,
     NetProfit,
     AllocationRate,
+    Xirr,
+    TimeWeightedReturn,
+    BenchmarkValue,
+    BenchmarkReturn,
+    TaxYear,
+    MatchingRule,
+    Proceeds,
+    AllowableCost,
+    Gain,
+    Loss,
+    NetGain,
+    AnnualExemptAmount,
+    TaxableGain,
+    TaxCategory,
+    Month,
+    ExemptGain,
+    LossCarryForward,
+    Darf,
+    Year,
+    Source,
+    Kind,
+    Start,
+    End,
+    Count,
+    Pair,
+    Rate,
+    ManualPrice,
 }
 
 impl Column {
@@ -39,7 +74,7 @@ impl Column {
     }
 }
 
-#[derive(Debug, strum::IntoStaticStr, strum::EnumString)]
+#[derive(Debug, Clone, Copy, PartialEq, strum::IntoStaticStr, strum::EnumString)]
 #[strum(serialize_all = "PascalCase")]
 pub enum Action {
     Sell,
@@ -52,6 +87,16 @@ pub enum Action {
     Interest,
     Withdraw,
     Ignore,
+    /// The ticker changed to `NewTicker`, with `Qty` new shares per share.
+    Rename,
+    /// The company was acquired for `Qty` shares of `NewTicker` per share plus `Amount` in cash,
+    /// `Price` is the fraction of the cost basis allocated to the cash.
+    Merger,
+    /// `Qty` shares of `NewTicker` per share were distributed, `Price` is the fraction of the
+    /// cost basis allocated to them.
+    SpinOff,
+    /// `Qty` fractional shares were paid in cash at `Price`.
+    CashInLieu,
 }
 
 impl Action {
@@ -60,7 +105,9 @@ impl Action {
     }
 }
 
-#[derive(Debug, strum::Display, strum::IntoStaticStr)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, strum::Display, strum::IntoStaticStr, strum::EnumString,
+)]
 #[strum(serialize_all = "PascalCase")]
 pub enum Type {
     Stock,
@@ -99,6 +146,9 @@ impl Country {
             ("US", _) => Country::Usa,
             ("GB", _) => Country::Uk,
             ("IE", _) => Country::Ireland,
+            ("AT" | "BE" | "DE" | "ES" | "FI" | "FR" | "GR" | "IT" | "LU" | "NL" | "PT", _) => {
+                Country::EU
+            }
             _ => Country::default(),
         }
     }
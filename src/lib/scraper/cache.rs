@@ -1,95 +1,384 @@
-use crate::schema::{Column, Currency};
+use crate::schema::{Column, Country, Currency};
 use crate::utils;
 use anyhow::{Context, Result};
 
+use chrono::NaiveDate;
+use itertools::Itertools;
 use polars::prelude::*;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use super::storage::{IStorage, Lock, Parquet, Table};
 use super::*;
-use tokio::fs::File;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
 
-pub struct Cache<T> {
+/// Time waiting for other runs using the cache.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The tables with market data, the ranges are tracked for each one.
+const KINDS: [Table; 3] = [Table::Quotes, Table::Splits, Table::Dividends];
+const TABLES: [Table; 4] = [
+    Table::Quotes,
+    Table::Splits,
+    Table::Dividends,
+    Table::Ranges,
+];
+
+/// Date range held in the cache per ticker and kind of data, it's saved in the `Ranges` table.
+#[derive(Default)]
+pub struct Ranges(HashMap<(String, Table), (NaiveDate, NaiveDate)>);
+
+impl Ranges {
+    pub fn read(storage: &impl IStorage) -> Result<Self> {
+        let mut ranges = Self::default();
+        let df = storage.read(Table::Ranges)?;
+        if df.height() == 0 {
+            return Ok(ranges);
+        }
+
+        let tickers = utils::polars::column_str(&df, Column::Ticker.as_str())?;
+        let kinds = utils::polars::column_str(&df, Column::Kind.as_str())?;
+        let starts = utils::polars::column_date(&df, Column::Start.as_str())?;
+        let ends = utils::polars::column_date(&df, Column::End.as_str())?;
+        for (((ticker, kind), start), end) in tickers.into_iter().zip(kinds).zip(starts).zip(ends) {
+            ranges
+                .0
+                .insert((ticker.to_owned(), kind.parse()?), (start, end));
+        }
+        Ok(ranges)
+    }
+
+    pub fn write(&self, storage: &impl IStorage) -> Result<()> {
+        let entries: Vec<_> = self
+            .0
+            .iter()
+            .sorted_by_key(|((ticker, kind), _)| (ticker.clone(), kind.as_str()))
+            .collect();
+        let mut df = df!(
+            Column::Ticker.as_str() => entries.iter().map(|((ticker, _), _)| ticker.as_str()).collect::<Vec<_>>(),
+            Column::Kind.as_str() => entries.iter().map(|((_, kind), _)| kind.as_str()).collect::<Vec<_>>(),
+            Column::Start.as_str() => entries.iter().map(|(_, (start, _))| *start).collect::<Vec<_>>(),
+            Column::End.as_str() => entries.iter().map(|(_, (_, end))| *end).collect::<Vec<_>>(),
+        )?;
+        storage.write(Table::Ranges, &mut df)
+    }
+
+    /// The parts of the period not held for some kind of data, before and after the range.
+    pub fn gaps(&self, ticker: &str, period: &SearchPeriod) -> Vec<(NaiveDate, NaiveDate)> {
+        let mut gaps = Vec::new();
+        for kind in KINDS {
+            match self.0.get(&(ticker.to_owned(), kind)) {
+                None => gaps.push((period.start, period.end)),
+                Some(&(start, end)) => {
+                    if period.start < start {
+                        gaps.push((period.start, start - chrono::Duration::days(1)));
+                    }
+                    if period.end > end {
+                        gaps.push((end + chrono::Duration::days(1), period.end));
+                    }
+                }
+            }
+        }
+        gaps.sort();
+        gaps.dedup();
+        gaps
+    }
+
+    /// Forgets the dates between `start` and `end`. A range can't have holes, the dates after the
+    /// invalidated ones are forgotten too.
+    pub fn invalidate(&mut self, ticker: &str, start: Option<NaiveDate>, end: Option<NaiveDate>) {
+        let from = start.unwrap_or(NaiveDate::MIN);
+        let to = end.unwrap_or(NaiveDate::MAX);
+        for kind in KINDS {
+            let key = (ticker.to_owned(), kind);
+            let Some(&(held_start, held_end)) = self.0.get(&key) else {
+                continue;
+            };
+            if to < held_start || from > held_end {
+                continue;
+            }
+            if from <= held_start && to >= held_end {
+                self.0.remove(&key);
+            } else if from <= held_start {
+                self.0
+                    .insert(key, (to + chrono::Duration::days(1), held_end));
+            } else {
+                self.0
+                    .insert(key, (held_start, from - chrono::Duration::days(1)));
+            }
+        }
+    }
+
+    /// Adds a fetched period, adjacent to the range held or overlapping it.
+    pub fn extend(&mut self, ticker: &str, kind: Table, start: NaiveDate, end: NaiveDate) {
+        self.0
+            .entry((ticker.to_owned(), kind))
+            .and_modify(|range| *range = (range.0.min(start), range.1.max(end)))
+            .or_insert((start, end));
+    }
+}
+
+/// A ticker or an exchange rate requested to the cache.
+enum Request {
+    Ticker(String, Country),
+    Currency(Currency, Currency),
+}
+
+impl Request {
+    fn ticker(&self) -> String {
+        match self {
+            Request::Ticker(ticker, _) => ticker.clone(),
+            Request::Currency(from, to) => format!("{from}/{to}"),
+        }
+    }
+}
+
+/// Keeps the market data in a storage, parquet by default, and requests to the inner scraper
+/// only the dates missing for each ticker, i.e. the full history of a new ticker and the days
+/// since the last run.
+pub struct Cache<T, S = Parquet> {
     inner: T,
-    quotes_cache: PathBuf,
-    splits_cache: PathBuf,
-    dividends_cache: PathBuf,
-    tickers: Vec<String>,
-    cached_tickers: Vec<String>,
+    storage: S,
+    requests: Vec<Request>,
 }
 
-impl<T> Cache<T>
-where
-    T: IScraper + std::marker::Send,
-{
+impl<T> Cache<T> {
     pub fn new(inner: T, cache_dir: PathBuf) -> Self {
         std::fs::create_dir_all(&cache_dir).expect("Can't create cache dir");
+        Self::with_storage(inner, Parquet::new(cache_dir))
+    }
+}
+
+/// Maintenance of the cache, the inner scraper isn't used.
+impl<T, S> Cache<T, S>
+where
+    S: IStorage,
+{
+    pub fn with_storage(inner: T, storage: S) -> Self {
         Self {
             inner,
-            quotes_cache: cache_dir.join("quotes.csv"),
-            splits_cache: cache_dir.join("splits.csv"),
-            dividends_cache: cache_dir.join("dividends.csv"),
-            tickers: Vec::new(),
-            cached_tickers: Vec::new(),
+            storage,
+            requests: Vec::new(),
         }
     }
 
-    fn cache_valid(&self) -> bool {
-        self.tickers
-            .iter()
-            .all(|item| self.cached_tickers.contains(item))
-    }
-
-    pub async fn load_csv(&mut self, file: PathBuf) -> Result<DataFrame> {
-        let mut f = File::open(&file)
-            .await
-            .with_context(|| format!("Could not open file: {:?}", file))?;
-        let mut content = Vec::new();
-        f.read_to_end(&mut content).await?;
-        let res = std::panic::catch_unwind(|| {
-            CsvReader::new(std::io::Cursor::new(content))
-                .finish()
-                .expect("Failed to Load json")
-                .lazy()
-                .with_column(col(Column::Date.into()).cast(DataType::Date))
-                .collect()
-                .expect("Failed to cast date")
-        });
-        res.map_err(|e| anyhow::anyhow!("Panic occurred: {:?}", e))
+    fn lock(&self) -> Result<Lock> {
+        tokio_test::block_on(Lock::acquire(self.storage.lock_file(), LOCK_TIMEOUT))
     }
 
-    pub async fn dump_csv(&mut self, mut df: DataFrame, file: PathBuf) -> Result<()> {
-        if df.shape().0 > 0 {
-            let mut f = File::create(&file)
-                .await
-                .with_context(|| format!("Could not open file: {:?}", file))?;
-            let mut writer = Vec::new();
-            CsvWriter::new(&mut writer).finish(&mut df)?;
-            f.write_all(&writer).await?;
+    /// The cached tickers with the dates held and the number of rows of each kind of data.
+    pub fn tickers(&self) -> Result<DataFrame> {
+        let _lock = self.lock()?;
+        #[derive(Default)]
+        struct Row {
+            start: Option<NaiveDate>,
+            end: Option<NaiveDate>,
+            count: u32,
+        }
+        let mut rows: BTreeMap<(String, &str), Row> = BTreeMap::new();
+        for kind in KINDS {
+            let df = self.storage.read(kind)?;
+            if df.height() == 0 {
+                continue;
+            }
+            for (ticker, count) in utils::polars::column_str(&df, Column::Ticker.as_str())?
+                .into_iter()
+                .counts()
+            {
+                rows.entry((ticker.to_owned(), kind.as_str()))
+                    .or_default()
+                    .count = count as u32;
+            }
+        }
+        for ((ticker, kind), (start, end)) in Ranges::read(&self.storage)?.0 {
+            let row = rows.entry((ticker, kind.as_str())).or_default();
+            (row.start, row.end) = (Some(start), Some(end));
+        }
+
+        let (keys, rows): (Vec<_>, Vec<_>) = rows.into_iter().unzip();
+        let (tickers, kinds): (Vec<_>, Vec<_>) = keys.into_iter().unzip();
+        let (starts, ends, counts): (Vec<_>, Vec<_>, Vec<_>) =
+            itertools::multiunzip(rows.into_iter().map(|x| (x.start, x.end, x.count)));
+        Ok(df!(
+            Column::Ticker.as_str() => tickers,
+            Column::Kind.as_str() => kinds,
+            Column::Start.as_str() => starts,
+            Column::End.as_str() => ends,
+            Column::Count.as_str() => counts,
+        )?)
+    }
+
+    /// Removes the rows of the ticker between the dates, all of them without dates, to load them
+    /// again in the next run. Returns the number of rows removed.
+    pub fn invalidate(
+        &self,
+        ticker: &str,
+        start: Option<NaiveDate>,
+        end: Option<NaiveDate>,
+    ) -> Result<usize> {
+        let _lock = self.lock()?;
+        let mut selected = col(Column::Ticker.as_str()).eq(lit(ticker));
+        if let Some(start) = start {
+            selected = selected.and(col(Column::Date.as_str()).gt_eq(lit(start)));
+        }
+        if let Some(end) = end {
+            selected = selected.and(col(Column::Date.as_str()).lt_eq(lit(end)));
+        }
+        let removed = self.remove(selected)?;
+
+        let mut ranges = Ranges::read(&self.storage)?;
+        ranges.invalidate(ticker, start, end);
+        ranges.write(&self.storage)?;
+        Ok(removed)
+    }
+
+    /// Removes the tickers not in `tickers`, the exchange rates are kept. Returns the tickers
+    /// removed.
+    pub fn prune(&self, tickers: &[String]) -> Result<Vec<String>> {
+        let _lock = self.lock()?;
+        let mut ranges = Ranges::read(&self.storage)?;
+        let mut cached: Vec<String> = ranges.0.keys().map(|(x, _)| x.clone()).collect();
+        for kind in KINDS {
+            let df = self.storage.read(kind)?;
+            if df.height() > 0 {
+                cached.extend(
+                    utils::polars::column_str(&df, Column::Ticker.as_str())?
+                        .into_iter()
+                        .map(str::to_owned),
+                );
+            }
+        }
+        let pruned: Vec<_> = cached
+            .into_iter()
+            .unique()
+            .filter(|x| !x.contains('/') && !tickers.contains(x))
+            .sorted()
+            .collect();
+        if pruned.is_empty() {
+            return Ok(pruned);
+        }
+
+        self.remove(
+            col(Column::Ticker.as_str()).is_in(lit(Series::new("pruned", pruned.clone()))),
+        )?;
+        ranges.0.retain(|(x, _), _| !pruned.contains(x));
+        ranges.write(&self.storage)?;
+        Ok(pruned)
+    }
+
+    /// Removes the rows matching `selected` from the tables with market data.
+    fn remove(&self, selected: Expr) -> Result<usize> {
+        let mut removed = 0;
+        for kind in KINDS {
+            let df = self.storage.read(kind)?;
+            if df.height() == 0 {
+                continue;
+            }
+            let mut kept = df.clone().lazy().filter(selected.clone().not()).collect()?;
+            if kept.height() < df.height() {
+                removed += df.height() - kept.height();
+                self.storage.write(kind, &mut kept)?;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Writes the tables as parquet files of a tar.gz archive, the layout of the offline prices.
+    /// The empty tables are written too, so the import clears them.
+    pub fn export(&self, file: &Path) -> Result<()> {
+        let _lock = self.lock()?;
+        let mut files = Vec::new();
+        for table in TABLES {
+            let mut df = self.storage.read(table)?;
+            // Never written, it has no columns. The import clears the tables not archived.
+            if df.width() == 0 {
+                continue;
+            }
+            let mut content = Vec::new();
+            ParquetWriter::new(&mut content).finish(&mut df)?;
+            files.push((format!("{}.parquet", table.file_name()), content));
+        }
+        let writer =
+            std::fs::File::create(file).with_context(|| format!("Failed to create {:?}", file))?;
+        utils::tar::write(&files, writer)
+    }
+
+    /// Replaces the tables with the ones of an archive written by `export`, the tables missing in
+    /// it are cleared.
+    pub fn import(&self, file: &Path) -> Result<()> {
+        let _lock = self.lock()?;
+        let reader =
+            std::fs::File::open(file).with_context(|| format!("Failed to open {:?}", file))?;
+        let files = utils::tar::read(reader)?;
+        anyhow::ensure!(
+            files.iter().any(|(name, _)| name == "quotes.parquet"),
+            "No quotes in {:?}",
+            file
+        );
+        for table in TABLES {
+            let name = format!("{}.parquet", table.file_name());
+            let mut df = match files.iter().find(|(x, _)| *x == name) {
+                Some((_, content)) => ParquetReader::new(std::io::Cursor::new(content)).finish()?,
+                None => self.storage.read(table)?.clear(),
+            };
+            if df.width() > 0 {
+                self.storage.write(table, &mut df)?;
+            }
         }
         Ok(())
     }
 }
 
-impl<T> IScraper for Cache<T>
+impl<T, S> Cache<T, S>
+where
+    T: IScraper + std::marker::Send,
+    S: IStorage + std::marker::Send,
+{
+    /// Appends the fetched rows when they're all new, otherwise the table is written again.
+    fn store(
+        &self,
+        table: Table,
+        cached: &DataFrame,
+        mut merged: DataFrame,
+        mut fetched: DataFrame,
+    ) -> Result<()> {
+        if fetched.height() == 0 {
+            return Ok(());
+        }
+        let appended = cached.height() + fetched.height() == merged.height();
+        if appended && (cached.height() == 0 || cached.schema() == fetched.schema()) {
+            self.storage.append(table, &mut fetched)
+        } else {
+            self.storage.write(table, &mut merged)
+        }
+    }
+}
+
+impl<T, S> IScraper for Cache<T, S>
 where
     T: IScraper + std::marker::Send,
+    S: IStorage + std::marker::Send,
 {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
     fn with_ticker(
         &mut self,
         tickers: &[String],
         countries: Option<&[schema::Country]>,
     ) -> &mut Self {
-        self.tickers.extend_from_slice(tickers);
-        self.inner.with_ticker(tickers, countries);
+        let countries = countries.map_or(vec![Country::Usa; tickers.len()], |x| x.to_vec());
+        for (ticker, country) in tickers.iter().zip(countries) {
+            self.requests.push(Request::Ticker(ticker.clone(), country));
+        }
         self
     }
 
     fn with_currency(&mut self, from: Currency, to: Currency) -> &mut Self {
-        let value = format!("{from}/{to}");
-        if !self.tickers.contains(&value) {
-            self.tickers.push(value);
-            self.inner.with_currency(from, to);
+        let ticker = format!("{from}/{to}");
+        if !self.requests.iter().any(|x| x.ticker() == ticker) {
+            self.requests.push(Request::Currency(from, to));
         }
         self
     }
@@ -100,70 +389,116 @@ where
 
     fn reset(&mut self) -> &mut Self {
         self.inner.reset();
-        self.cached_tickers.extend(self.tickers.clone());
-        self.cached_tickers.sort();
-        self.cached_tickers.dedup();
-        self.tickers.clear();
+        self.requests.clear();
         self
     }
 
     async fn load(&mut self, period: SearchPeriod) -> Result<ScraperData> {
-        let mut cached_data = ScraperData::default();
-        let quotes = self
-            .load_csv(self.quotes_cache.clone())
-            .await
-            .unwrap_or_default();
-
-        let latest_update = if quotes.shape().0 > 0 {
-            let date = utils::polars::latest_date(&quotes);
-            cached_data.concat_quotes(quotes)?;
-            let splits = self
-                .load_csv(self.splits_cache.clone())
-                .await
-                .unwrap_or_default();
-            cached_data.concat_splits(splits)?;
-            let dividends = self
-                .load_csv(self.dividends_cache.clone())
-                .await
-                .unwrap_or_default();
-            cached_data.concat_dividends(dividends)?;
-            date - chrono::Duration::days(1)
-        } else {
-            period.start
-        };
+        let _lock = Lock::acquire(self.storage.lock_file(), LOCK_TIMEOUT).await?;
+        let mut cached_data = ScraperData::new(
+            self.storage.read(Table::Quotes)?,
+            self.storage.read(Table::Splits)?,
+            self.storage.read(Table::Dividends)?,
+        );
+        let mut ranges = Ranges::read(&self.storage)?;
 
-        if !self.cache_valid() {
-            let update_period = SearchPeriod::new(Some(latest_update), None, None);
-            log::info!("Updating cache {:?} {:?} ...", self.tickers, update_period);
+        // The tickers missing the same dates are loaded together.
+        let mut gaps: BTreeMap<(NaiveDate, NaiveDate), Vec<usize>> = BTreeMap::new();
+        for (i, request) in self.requests.iter().enumerate() {
+            for gap in ranges.gaps(&request.ticker(), &period) {
+                gaps.entry(gap).or_default().push(i);
+            }
+        }
+
+        // Today's quotes change until the market closes, they are loaded again in the next run.
+        let yesterday = chrono::Local::now().date_naive() - chrono::Duration::days(1);
+        let mut fetched = ScraperData::default();
+        let mut failures = Vec::new();
+        for ((start, end), requests) in &gaps {
+            for &i in requests {
+                match &self.requests[i] {
+                    Request::Ticker(ticker, country) => {
+                        self.inner
+                            .with_ticker(std::slice::from_ref(ticker), Some(&[*country]));
+                    }
+                    Request::Currency(from, to) => {
+                        self.inner.with_currency(*from, *to);
+                    }
+                }
+            }
+            let tickers: Vec<_> = requests
+                .iter()
+                .map(|&i| self.requests[i].ticker())
+                .collect();
+            let update_period =
+                SearchPeriod::new(Some(*start), Some(*end), Some(period.interval_days));
+            log::info!("Updating cache {:?} {:?} ...", tickers, update_period);
             let data = self
                 .inner
                 .load(update_period)
                 .await
-                .with_context(|| format!("Failed to load {:?}", &self.tickers))?;
+                .with_context(|| format!("Failed to load {:?}", &tickers))?;
 
-            cached_data
+            let end = (*end).min(yesterday);
+            for ticker in tickers
+                .iter()
+                .filter(|x| !data.failures.iter().any(|(y, _)| &y == x))
+            {
+                if *start <= end {
+                    for kind in KINDS {
+                        ranges.extend(ticker, kind, *start, end);
+                    }
+                }
+            }
+            failures.extend(data.failures);
+            fetched
                 .concat_quotes(data.quotes)?
-                .concat_dividends(data.dividends)?
-                .concat_splits(data.splits)?;
+                .concat_splits(data.splits)?
+                .concat_dividends(data.dividends)?;
+        }
 
-            self.dump_csv(cached_data.quotes.clone(), self.quotes_cache.clone())
-                .await?;
-            self.dump_csv(cached_data.splits.clone(), self.splits_cache.clone())
-                .await?;
-            self.dump_csv(cached_data.dividends.clone(), self.dividends_cache.clone())
-                .await?;
+        if !gaps.is_empty() {
+            let cached = std::mem::take(&mut cached_data);
+            cached_data.quotes = merge_quotes(cached.quotes.clone(), fetched.quotes.clone())?;
+            cached_data
+                .concat_splits(cached.splits.clone())?
+                .concat_splits(fetched.splits.clone())?
+                .concat_dividends(cached.dividends.clone())?
+                .concat_dividends(fetched.dividends.clone())?;
+
+            self.store(
+                Table::Quotes,
+                &cached.quotes,
+                cached_data.quotes.clone(),
+                fetched.quotes,
+            )?;
+            self.store(
+                Table::Splits,
+                &cached.splits,
+                cached_data.splits.clone(),
+                fetched.splits,
+            )?;
+            self.store(
+                Table::Dividends,
+                &cached.dividends,
+                cached_data.dividends.clone(),
+                fetched.dividends,
+            )?;
+            ranges.write(&self.storage)?;
         }
 
-        // TODO: This code is repeated in `is_cache_updated`.
         let start = period.start;
-        let filter = Series::new("filter", self.tickers.clone());
-        cached_data.quotes = cached_data
-            .quotes
-            .lazy()
-            .filter(col(Column::Ticker.as_str()).is_in(filter.clone().lit()))
-            .filter(col(Column::Date.as_str()).lt_eq(lit(period.end)))
-            .filter(col(Column::Date.as_str()).gt_eq(lit(start)))
-            .collect()?;
+        let tickers: Vec<_> = self.requests.iter().map(Request::ticker).collect();
+        let filter = Series::new("filter", tickers);
+        if cached_data.quotes.shape().0 > 0 {
+            cached_data.quotes = cached_data
+                .quotes
+                .lazy()
+                .filter(col(Column::Ticker.as_str()).is_in(filter.clone().lit()))
+                .filter(col(Column::Date.as_str()).lt_eq(lit(period.end)))
+                .filter(col(Column::Date.as_str()).gt_eq(lit(start)))
+                .collect()?;
+        }
 
         if cached_data.dividends.shape().0 > 0 {
             cached_data.dividends = cached_data
@@ -175,6 +510,18 @@ where
                 .collect()?;
         }
 
+        // A ticker failed in a gap but with cached quotes isn't a failure.
+        let found = if cached_data.quotes.height() > 0 {
+            utils::polars::column_str(&cached_data.quotes, Column::Ticker.as_str())?
+        } else {
+            Vec::new()
+        };
+        cached_data.failures = failures
+            .into_iter()
+            .filter(|(ticker, _)| !found.contains(&ticker.as_str()))
+            .unique_by(|(ticker, _)| ticker.clone())
+            .collect();
+
         self.reset();
         Ok(cached_data)
     }
@@ -185,6 +532,13 @@ where
     R: IScraper + std::marker::Send,
     L: IScraper + std::marker::Send,
 {
+    fn name(&self) -> &'static str {
+        match self {
+            either::Left(left) => left.name(),
+            either::Right(right) => right.name(),
+        }
+    }
+
     fn with_ticker(
         &mut self,
         tickers: &[String],
@@ -237,3 +591,209 @@ where
     }
 }
 
+#[cfg(test)]
+mod unittest {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    type Calls = Arc<Mutex<Vec<(Vec<String>, NaiveDate, NaiveDate)>>>;
+
+    /// Records the tickers and periods requested to the offline prices.
+    struct Recorder {
+        inner: Offline,
+        tickers: Vec<String>,
+        calls: Calls,
+    }
+
+    impl IScraper for Recorder {
+        fn name(&self) -> &'static str {
+            "Recorder"
+        }
+
+        fn reset(&mut self) -> &mut Self {
+            self.inner.reset();
+            self.tickers.clear();
+            self
+        }
+
+        fn with_ticker(&mut self, tickers: &[String], countries: Option<&[Country]>) -> &mut Self {
+            self.inner.with_ticker(tickers, countries);
+            self.tickers.extend_from_slice(tickers);
+            self
+        }
+
+        fn with_currency(&mut self, from: Currency, to: Currency) -> &mut Self {
+            self.inner.with_currency(from, to);
+            self.tickers.push(format!("{from}/{to}"));
+            self
+        }
+
+        fn load_blocking(&mut self, search_interval: SearchPeriod) -> Result<ScraperData> {
+            tokio_test::block_on(self.load(search_interval))
+        }
+
+        async fn load(&mut self, period: SearchPeriod) -> Result<ScraperData> {
+            self.calls
+                .lock()
+                .unwrap()
+                .push((self.tickers.clone(), period.start, period.end));
+            let data = self.inner.load(period).await;
+            self.reset();
+            data
+        }
+    }
+
+    fn period(start: &str) -> SearchPeriod {
+        SearchPeriod::from_str(Some(start), Some("2023-03-31"), Some(1))
+    }
+
+    fn date(date: &str) -> NaiveDate {
+        date.parse().unwrap()
+    }
+
+    fn cache(dir: &str) -> (Cache<Recorder>, Calls) {
+        let _ = std::fs::remove_dir_all(dir);
+        let calls = Calls::default();
+        let cache = Cache::new(
+            Recorder {
+                inner: Offline::new("resources/tests/input/prices"),
+                tickers: Vec::new(),
+                calls: calls.clone(),
+            },
+            PathBuf::from(dir),
+        );
+        (cache, calls)
+    }
+
+    #[test]
+    fn load_only_missing_ranges_success() {
+        let (mut cache, calls) = cache("target/cache_ranges");
+        let aapl = ["AAPL".to_owned()];
+        let both = ["AAPL".to_owned(), "GOOGL".to_owned()];
+
+        cache
+            .with_ticker(&aapl, None)
+            .load_blocking(period("2023-01-01"))
+            .unwrap();
+        // The history before the cached range, and a new ticker from the start.
+        cache
+            .with_ticker(&aapl, None)
+            .load_blocking(period("2022-12-01"))
+            .unwrap();
+        let data = cache
+            .with_ticker(&both, None)
+            .with_currency(Currency::USD, Currency::GBP)
+            .load_blocking(period("2022-12-01"))
+            .unwrap();
+        // Everything is cached.
+        cache
+            .with_ticker(&both, None)
+            .load_blocking(period("2022-12-01"))
+            .unwrap();
+
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [
+                (aapl.to_vec(), date("2023-01-01"), date("2023-03-31")),
+                (aapl.to_vec(), date("2022-12-01"), date("2022-12-31")),
+                (
+                    vec!["GOOGL".to_owned(), "USD/GBP".to_owned()],
+                    date("2022-12-01"),
+                    date("2023-03-31")
+                ),
+            ]
+        );
+        let quotes = data
+            .quotes
+            .lazy()
+            .sort_by_exprs(
+                [col(Column::Ticker.into()), col(Column::Date.into())],
+                SortMultipleOptions::default(),
+            )
+            .collect()
+            .unwrap();
+        assert_eq!(
+            utils::polars::column_str(&quotes, Column::Ticker.as_str()).unwrap(),
+            [
+                "AAPL", "AAPL", "AAPL", "AAPL", "AAPL", "GOOGL", "GOOGL", "GOOGL", "USD/GBP",
+                "USD/GBP", "USD/GBP"
+            ]
+        );
+        assert_eq!(
+            utils::polars::column_f64(&quotes, Column::Price.as_str()).unwrap(),
+            [148.31, 129.93, 125.07, 150.0, 160.0, 101.28, 98.5, 101.0, 0.83, 0.82, 0.81]
+        );
+        // The parquet tables are the layout of the offline prices.
+        let offline = Offline::new("target/cache_ranges")
+            .with_ticker(&both, None)
+            .load_blocking(period("2022-12-01"))
+            .unwrap();
+        assert_eq!(offline.quotes.height(), 8);
+    }
+
+    #[test]
+    fn invalidate_prune_and_export_success() {
+        let (mut cache, calls) = cache("target/cache_maintenance");
+        let both = ["AAPL".to_owned(), "GOOGL".to_owned()];
+        cache
+            .with_ticker(&both, None)
+            .load_blocking(period("2022-12-01"))
+            .unwrap();
+
+        let tickers = cache.tickers().unwrap();
+        assert_eq!(
+            utils::polars::column_str(&tickers, Column::Kind.as_str()).unwrap(),
+            [
+                "Dividends",
+                "Quotes",
+                "Splits",
+                "Dividends",
+                "Quotes",
+                "Splits"
+            ]
+        );
+        assert_eq!(
+            tickers.column(Column::Count.as_str()).unwrap(),
+            &Series::new(Column::Count.as_str(), [1u32, 5, 0, 1, 3, 1])
+        );
+
+        // The quote of the end of March is loaded again.
+        assert_eq!(
+            cache
+                .invalidate("AAPL", Some(date("2023-03-31")), None)
+                .unwrap(),
+            1
+        );
+        calls.lock().unwrap().clear();
+        cache
+            .with_ticker(&both, None)
+            .load_blocking(period("2022-12-01"))
+            .unwrap();
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [(
+                vec!["AAPL".to_owned()],
+                date("2023-03-31"),
+                date("2023-03-31")
+            )]
+        );
+
+        assert_eq!(cache.prune(&["AAPL".to_owned()]).unwrap(), ["GOOGL"]);
+        let archive = PathBuf::from("target/cache_maintenance.tar.gz");
+        cache.export(&archive).unwrap();
+
+        // The tables of the archive replace the cached ones, the GOOGL splits are gone.
+        let (mut imported, _) = self::cache("target/cache_imported");
+        imported
+            .with_ticker(&both, None)
+            .load_blocking(period("2022-12-01"))
+            .unwrap();
+        imported.import(&archive).unwrap();
+        let tickers = imported.tickers().unwrap();
+        assert_eq!(tickers, cache.tickers().unwrap());
+        assert_eq!(
+            utils::polars::column_str(&tickers, Column::Ticker.as_str()).unwrap(),
+            ["AAPL", "AAPL", "AAPL"]
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use crate::schema::{Column, Country, Currency};
+use crate::utils;
+use anyhow::Result;
+use itertools::Itertools;
+
+use polars::prelude::*;
+
+use super::*;
+
+/// Tries the providers in order for each ticker, the tickers without quotes are requested to the
+/// next provider. The `Source` column of the quotes records the provider that supplied them, the
+/// tickers no provider has are reported in `ScraperData::failures`.
+pub struct Fallback<T> {
+    providers: Vec<T>,
+    tickers: Vec<String>,
+    countries: Vec<Country>,
+    currencies: Vec<(Currency, Currency)>,
+}
+
+impl<T> Fallback<T>
+where
+    T: IScraper + std::marker::Send,
+{
+    pub fn new(providers: Vec<T>) -> Self {
+        Self {
+            providers,
+            tickers: Vec::new(),
+            countries: Vec::new(),
+            currencies: Vec::new(),
+        }
+    }
+
+    /// The splits and dividends of the tickers supplied by the provider.
+    fn filter_tickers(frame: DataFrame, tickers: &[String]) -> Result<DataFrame> {
+        if frame.height() == 0 {
+            return Ok(frame);
+        }
+        let tickers = Series::new(Column::Ticker.as_str(), tickers);
+        Ok(frame
+            .lazy()
+            .filter(col(Column::Ticker.as_str()).is_in(lit(tickers)))
+            .collect()?)
+    }
+}
+
+impl<T> IScraper for Fallback<T>
+where
+    T: IScraper + std::marker::Send,
+{
+    fn name(&self) -> &'static str {
+        "Fallback"
+    }
+
+    fn reset(&mut self) -> &mut Self {
+        self.tickers.clear();
+        self.countries.clear();
+        self.currencies.clear();
+        self
+    }
+
+    fn with_ticker(&mut self, tickers: &[String], countries: Option<&[Country]>) -> &mut Self {
+        self.tickers.extend_from_slice(tickers);
+        self.countries
+            .extend_from_slice(countries.unwrap_or(&vec![Country::Usa; tickers.len()]));
+        self
+    }
+
+    fn with_currency(&mut self, from: Currency, to: Currency) -> &mut Self {
+        if !self.currencies.contains(&(from, to)) {
+            self.currencies.push((from, to));
+        }
+        self
+    }
+
+    fn load_blocking(&mut self, search_interval: SearchPeriod) -> Result<ScraperData> {
+        tokio_test::block_on(self.load(search_interval))
+    }
+
+    async fn load(&mut self, period: SearchPeriod) -> Result<ScraperData> {
+        let mut data = ScraperData::default();
+        let mut tickers: Vec<_> = self
+            .tickers
+            .iter()
+            .cloned()
+            .zip(self.countries.iter().copied())
+            .collect();
+        let mut currencies = self.currencies.clone();
+        let mut tried = Vec::new();
+        let single = self.providers.len() == 1;
+
+        for provider in self.providers.iter_mut() {
+            if tickers.is_empty() && currencies.is_empty() {
+                break;
+            }
+            tried.push(provider.name());
+
+            let (names, countries): (Vec<_>, Vec<_>) = tickers.iter().cloned().unzip();
+            provider.with_ticker(&names, Some(&countries));
+            for (from, to) in &currencies {
+                provider.with_currency(*from, *to);
+            }
+            let loaded = match provider.load(period.clone()).await {
+                Ok(loaded) => loaded,
+                // A single provider has no fallback, its error is the reason.
+                Err(error) if single => {
+                    self.reset();
+                    return Err(error);
+                }
+                Err(error) => {
+                    log::warn!("{} failed to load {:?}: {error:#}", provider.name(), names);
+                    provider.reset();
+                    continue;
+                }
+            };
+            if loaded.quotes.height() == 0 {
+                continue;
+            }
+
+            let mut quotes = loaded.quotes;
+            if !quotes.get_column_names().contains(&Column::Source.as_str()) {
+                quotes = quotes
+                    .lazy()
+                    .with_column(lit(provider.name()).alias(Column::Source.as_str()))
+                    .collect()?;
+            }
+            let found: Vec<String> = utils::polars::column_str(&quotes, Column::Ticker.as_str())?
+                .into_iter()
+                .unique()
+                .map(str::to_owned)
+                .collect();
+            tickers.retain(|(ticker, _)| !found.contains(ticker));
+            currencies.retain(|(from, to)| !found.contains(&format!("{from}/{to}")));
+
+            data.concat_quotes(quotes)?
+                .concat_splits(Self::filter_tickers(loaded.splits, &found)?)?
+                .concat_dividends(Self::filter_tickers(loaded.dividends, &found)?)?;
+        }
+
+        let reason = format!("No quotes from {}", tried.join(", "));
+        data.failures.extend(
+            tickers
+                .into_iter()
+                .map(|(ticker, _)| ticker)
+                .chain(currencies.iter().map(|(from, to)| format!("{from}/{to}")))
+                .map(|ticker| (ticker, reason.clone())),
+        );
+
+        self.reset();
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+    use super::*;
+    use either::Either;
+
+    /// Stooq has AAPL and the exchange rate, the offline files GOOGL.
+    fn fallback() -> Fallback<Either<Stooq, Offline>> {
+        let dir = std::path::PathBuf::from("target/fallback_prices");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut quotes = df!(
+            Column::Date.into() => &["2024-02-08", "2024-02-08"],
+            Column::Ticker.into() => &["AAPL", "GOOGL"],
+            Column::Price.into() => &[1.0, 145.91],
+            Column::Currency.into() => &["USD", "USD"],
+        )
+        .unwrap();
+        CsvWriter::new(std::fs::File::create(dir.join("quotes.csv")).unwrap())
+            .finish(&mut quotes)
+            .unwrap();
+
+        Fallback::new(vec![
+            Either::Left(
+                Stooq::new()
+                    .with_base_url(utils::test::http::serve_dir("resources/tests/input/stooq")),
+            ),
+            Either::Right(Offline::new(dir).with_lenient(true)),
+        ])
+    }
+
+    fn period() -> SearchPeriod {
+        SearchPeriod::from_str(Some("2024-02-08"), Some("2024-02-08"), Some(1))
+    }
+
+    #[test]
+    fn fallback_per_ticker_success() {
+        let data = fallback()
+            .with_ticker(&["AAPL".to_owned(), "GOOGL".to_owned()], None)
+            .with_currency(Currency::USD, Currency::GBP)
+            .load_blocking(period())
+            .unwrap();
+
+        let quotes = data
+            .quotes
+            .lazy()
+            .sort([Column::Ticker.as_str()], Default::default())
+            .collect()
+            .unwrap();
+        assert_eq!(
+            utils::polars::column_str(&quotes, Column::Ticker.as_str()).unwrap(),
+            ["AAPL", "GOOGL", "USD/GBP"]
+        );
+        assert_eq!(
+            utils::polars::column_f64(&quotes, Column::Price.as_str()).unwrap(),
+            [188.32, 145.91, 0.7927]
+        );
+        assert_eq!(
+            utils::polars::column_str(&quotes, Column::Source.as_str()).unwrap(),
+            ["Stooq", "Offline", "Stooq"]
+        );
+        assert!(data.failures.is_empty());
+    }
+
+    #[test]
+    fn fallback_reports_failures() {
+        let data = fallback()
+            .with_ticker(&["AAPL".to_owned(), "DELISTED".to_owned()], None)
+            .load_blocking(period())
+            .unwrap();
+
+        assert_eq!(
+            utils::polars::column_str(&data.quotes, Column::Ticker.as_str()).unwrap(),
+            ["AAPL"]
+        );
+        assert_eq!(
+            data.failures,
+            [(
+                "DELISTED".to_owned(),
+                "No quotes from Stooq, Offline".to_owned()
+            )]
+        );
+    }
+
+    #[test]
+    fn fallback_offline_with_some_tickers_success() {
+        let dir = std::path::PathBuf::from("target/fallback_some_prices");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("quotes.csv"),
+            "Date,Ticker,Price,Currency\n2024-02-08,GOOGL,145.91,USD\n",
+        )
+        .unwrap();
+        let mut fallback: Fallback<Either<Stooq, Offline>> = Fallback::new(vec![
+            Either::Right(Offline::new(dir).with_lenient(true)),
+            Either::Left(
+                Stooq::new()
+                    .with_base_url(utils::test::http::serve_dir("resources/tests/input/stooq")),
+            ),
+        ]);
+
+        // The offline files have GOOGL, the missing AAPL comes from Stooq.
+        let data = fallback
+            .with_ticker(&["AAPL".to_owned(), "GOOGL".to_owned()], None)
+            .load_blocking(period())
+            .unwrap();
+
+        let quotes = data
+            .quotes
+            .lazy()
+            .sort([Column::Ticker.as_str()], Default::default())
+            .collect()
+            .unwrap();
+        assert_eq!(
+            utils::polars::column_str(&quotes, Column::Source.as_str()).unwrap(),
+            ["Stooq", "Offline"]
+        );
+        assert!(data.failures.is_empty());
+    }
+
+    #[test]
+    fn fallback_single_provider_fails() {
+        let mut fallback = Fallback::new(vec![Offline::new("resources/tests/input/prices")]);
+
+        let error = fallback
+            .with_ticker(&["MSFT".to_owned()], None)
+            .load_blocking(period())
+            .unwrap_err();
+
+        assert!(error.to_string().starts_with("No prices of \"MSFT\""));
+    }
+}
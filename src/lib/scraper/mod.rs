@@ -2,14 +2,26 @@ pub mod yahoo;
 pub use yahoo::Yahoo;
 pub mod cache;
 pub use cache::Cache;
+pub mod offline;
+pub use offline::Offline;
+pub mod stooq;
 use std::str::FromStr;
-
+pub use stooq::Stooq;
+pub mod fallback;
+pub use fallback::Fallback;
+pub mod overrides;
+pub mod storage;
+pub use overrides::Overrides;
+
+use crate::perpetual_inventory::AverageCost;
 use crate::schema;
 use crate::utils;
 use anyhow::Result;
 use polars::prelude::*;
 
 pub trait IScraper {
+    /// Name of the provider in the `Source` column of the quotes.
+    fn name(&self) -> &'static str;
     fn reset(&mut self) -> &mut Self;
     fn with_ticker(&mut self, tickers: &[String], country: Option<&[schema::Country]>)
         -> &mut Self;
@@ -21,11 +33,23 @@ pub trait IScraper {
     ) -> impl std::future::Future<Output = Result<ScraperData>> + Send;
 }
 
+/// The price providers, `Offline` reads the files of `--prices-dir`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, strum::EnumString, strum::IntoStaticStr)]
+#[strum(serialize_all = "PascalCase")]
+pub enum Provider {
+    #[default]
+    Yahoo,
+    Stooq,
+    Offline,
+}
+
 #[derive(Default, Debug)]
 pub struct ScraperData {
     pub quotes: DataFrame,
     pub splits: DataFrame,
     pub dividends: DataFrame,
+    /// Tickers without quotes and the reason.
+    pub failures: Vec<(String, String)>,
 }
 
 impl ScraperData {
@@ -34,18 +58,39 @@ impl ScraperData {
             quotes,
             splits,
             dividends,
+            failures: Vec::new(),
         }
     }
 
     pub fn concat_quotes(&mut self, quotes: DataFrame) -> Result<&mut Self> {
         if quotes.shape().0 > 0 {
-            self.quotes = concat(
-                [self.quotes.clone().lazy(), quotes.lazy()],
-                Default::default(),
-            )?
-            .unique(None, UniqueKeepStrategy::First)
-            .sort([schema::Column::Date.as_str()], Default::default())
-            .collect()?;
+            // Quotes cached before the `Source` column have it empty.
+            let source = schema::Column::Source.as_str();
+            let has_source = |df: &DataFrame| df.get_column_names().contains(&source);
+            let (mut current, mut quotes) = (self.quotes.clone().lazy(), quotes);
+            if self.quotes.width() > 0 && has_source(&self.quotes) != has_source(&quotes) {
+                let null = lit(NULL).cast(DataType::String).alias(source);
+                // In the order of the other side, `Source` may not be the last column.
+                let order = |df: &DataFrame| {
+                    df.get_column_names()
+                        .into_iter()
+                        .map(col)
+                        .collect::<Vec<_>>()
+                };
+                if has_source(&quotes) {
+                    current = current.with_column(null).select(order(&quotes));
+                } else {
+                    quotes = quotes
+                        .lazy()
+                        .with_column(null)
+                        .select(order(&self.quotes))
+                        .collect()?;
+                }
+            }
+            self.quotes = concat([current, quotes.lazy()], Default::default())?
+                .unique(None, UniqueKeepStrategy::First)
+                .sort([schema::Column::Date.as_str()], Default::default())
+                .collect()?;
         }
 
         Ok(self)
@@ -84,6 +129,41 @@ impl ScraperData {
     }
 }
 
+/// The `preferred` quotes replace the `current` ones of the same day and ticker, i.e. the fetched
+/// quotes of today replace the cached ones.
+fn merge_quotes(current: DataFrame, preferred: DataFrame) -> Result<DataFrame> {
+    const PRIORITY: &str = "Priority";
+    let mut data = ScraperData::default();
+    for (quotes, priority) in [(preferred, 0), (current, 1)] {
+        if quotes.height() > 0 {
+            data.concat_quotes(
+                quotes
+                    .lazy()
+                    .with_column(lit(priority).alias(PRIORITY))
+                    .collect()?,
+            )?;
+        }
+    }
+    if data.quotes.height() == 0 {
+        return Ok(data.quotes);
+    }
+
+    Ok(data
+        .quotes
+        .lazy()
+        .sort([PRIORITY], Default::default())
+        .unique_stable(
+            Some(vec![
+                schema::Column::Date.as_str().into(),
+                schema::Column::Ticker.as_str().into(),
+            ]),
+            UniqueKeepStrategy::First,
+        )
+        .drop([PRIORITY])
+        .sort([schema::Column::Date.as_str()], Default::default())
+        .collect()?)
+}
+
 #[derive(Debug, Clone)]
 pub struct SearchPeriod {
     start: chrono::NaiveDate,
@@ -121,8 +201,12 @@ pub async fn load_data<T: IScraper>(
     scraper: &mut T,
     present_date: Option<chrono::NaiveDate>,
 ) -> Result<ScraperData> {
-    let df = orders.lazy();
+    // The tickers received by corporate actions are bought in the average cost.
+    let df = AverageCost::from_orders(orders)
+        .with_cumulative()?
+        .collect()?;
     let df = df
+        .lazy()
         .filter(utils::polars::filter::buy_or_sell())
         .select([
             col(schema::Column::Ticker.as_str()),
@@ -0,0 +1,343 @@
+use crate::error::Error;
+use crate::schema::{Column, Currency};
+use crate::utils;
+use anyhow::{Context, Result};
+
+use chrono::NaiveDate;
+use polars::prelude::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use super::storage::{self, IStorage, Table};
+use super::*;
+
+/// Quotes further than this from the start or the end of the period mean the files don't cover
+/// it, it leaves room for weekends and holidays.
+const MAX_STALE_DAYS: i64 = 7;
+
+/// Reads the market data from a directory with the files of the `Cache`: `quotes`, `splits` and
+/// `dividends`, each one as csv or parquet. The quotes are required, the other files optional.
+/// Exchange rates are quotes of the ticker "FROM/TO", i.e. "USD/GBP".
+pub struct Offline {
+    dir: PathBuf,
+    tickers: Vec<String>,
+    lenient: bool,
+}
+
+impl Offline {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            tickers: Vec::new(),
+            lenient: false,
+        }
+    }
+
+    /// Report the tickers without quotes in `ScraperData::failures` instead of failing.
+    pub fn with_lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// The directory of the parquet `Cache` wins over the parquet file, and it over the csv one.
+    fn read(&self, table: Table) -> Result<Option<DataFrame>> {
+        let name = table.file_name();
+        let parquet = self.dir.join(format!("{name}.parquet"));
+        let csv = self.dir.join(format!("{name}.csv"));
+        let frame = if self.dir.join(&name).is_dir() {
+            storage::Parquet::new(&self.dir).read(table)?
+        } else if parquet.exists() {
+            Self::read_parquet(&parquet)?
+        } else if csv.exists() {
+            Self::read_csv(&csv)?
+        } else {
+            return Ok(None);
+        };
+
+        Ok(Some(
+            frame
+                .lazy()
+                .with_column(col(Column::Date.as_str()).cast(DataType::Date))
+                .filter(col(Column::Ticker.as_str()).is_in(lit(Series::new(
+                    Column::Ticker.as_str(),
+                    self.tickers.clone(),
+                ))))
+                .collect()?,
+        ))
+    }
+
+    fn read_csv(file: &Path) -> Result<DataFrame> {
+        CsvReader::from_path(file)
+            .and_then(|reader| reader.has_header(true).finish())
+            .with_context(|| format!("Failed to read {:?}", file))
+    }
+
+    fn read_parquet(file: &Path) -> Result<DataFrame> {
+        let file =
+            std::fs::File::open(file).with_context(|| format!("Failed to open {:?}", file))?;
+        Ok(ParquetReader::new(file).finish()?)
+    }
+
+    fn filter_period(frame: DataFrame, period: &SearchPeriod) -> Result<DataFrame> {
+        Ok(frame
+            .lazy()
+            .filter(
+                col(Column::Date.as_str())
+                    .gt_eq(lit(period.start))
+                    .and(col(Column::Date.as_str()).lt_eq(lit(period.end))),
+            )
+            .sort(
+                [Column::Date.as_str()],
+                SortMultipleOptions::default().with_maintain_order(true),
+            )
+            .collect()?)
+    }
+}
+
+impl IScraper for Offline {
+    fn name(&self) -> &'static str {
+        "Offline"
+    }
+
+    fn reset(&mut self) -> &mut Self {
+        self.tickers.clear();
+        self
+    }
+
+    fn with_ticker(
+        &mut self,
+        tickers: &[String],
+        _countries: Option<&[schema::Country]>,
+    ) -> &mut Self {
+        self.tickers.extend_from_slice(tickers);
+        self
+    }
+
+    fn with_currency(&mut self, from: Currency, to: Currency) -> &mut Self {
+        let ticker = format!("{from}/{to}");
+        if !self.tickers.contains(&ticker) {
+            self.tickers.push(ticker);
+        }
+        self
+    }
+
+    fn load_blocking(&mut self, search_interval: SearchPeriod) -> Result<ScraperData> {
+        tokio_test::block_on(self.load(search_interval))
+    }
+
+    async fn load(&mut self, period: SearchPeriod) -> Result<ScraperData> {
+        let quotes = self
+            .read(Table::Quotes)?
+            .ok_or_else(|| anyhow::anyhow!("No quotes file in {:?}", self.dir))?;
+
+        let quotes = Self::filter_period(quotes, &period)?;
+        // The quotes are sorted by date, the first one of each ticker is the earliest and the last
+        // one the latest.
+        let mut found: HashMap<&str, (NaiveDate, NaiveDate)> = HashMap::new();
+        for (ticker, date) in utils::polars::column_str(&quotes, Column::Ticker.as_str())?
+            .into_iter()
+            .zip(utils::polars::column_date(&quotes, Column::Date.as_str())?)
+        {
+            found.entry(ticker).or_insert((date, date)).1 = date;
+        }
+        let slack = chrono::Duration::days(MAX_STALE_DAYS);
+        let mut failures = Vec::new();
+        for ticker in &self.tickers {
+            let missing = |start, end| Error::MissingDates {
+                ticker: ticker.clone(),
+                start,
+                end,
+                dir: self.dir.clone(),
+            };
+            let error = match found.get(ticker.as_str()).copied() {
+                None => Error::MissingPrices {
+                    ticker: ticker.clone(),
+                    dir: self.dir.clone(),
+                },
+                Some((earliest, _)) if earliest > period.start + slack => {
+                    missing(period.start, earliest)
+                }
+                Some((_, latest)) if latest < period.end - slack => missing(latest, period.end),
+                Some(_) => continue,
+            };
+            if !self.lenient {
+                return Err(error.into());
+            }
+            failures.push((ticker.clone(), error.to_string()));
+        }
+        // The stale quotes are left to the next provider.
+        let failed = Series::new(
+            Column::Ticker.as_str(),
+            failures
+                .iter()
+                .map(|(ticker, _)| ticker.as_str())
+                .collect::<Vec<_>>(),
+        );
+        let quotes = quotes
+            .lazy()
+            .filter(col(Column::Ticker.as_str()).is_in(lit(failed)).not())
+            .collect()?;
+
+        let splits = match self.read(Table::Splits)? {
+            Some(splits) => splits
+                .lazy()
+                .filter(col(Column::Date.as_str()).lt_eq(lit(period.end)))
+                .collect()?,
+            None => DataFrame::default(),
+        };
+        let dividends = match self.read(Table::Dividends)? {
+            Some(dividends) => Self::filter_period(dividends, &period)?,
+            None => DataFrame::default(),
+        };
+
+        self.reset();
+        let mut data = ScraperData::new(quotes, splits, dividends);
+        data.failures = failures;
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+    use super::*;
+
+    fn prices_dir() -> PathBuf {
+        PathBuf::from("resources/tests/input/prices")
+    }
+
+    fn period(end: &str) -> SearchPeriod {
+        SearchPeriod::new(
+            Some("2023-01-31".parse().unwrap()),
+            Some(end.parse().unwrap()),
+            Some(1),
+        )
+    }
+
+    #[test]
+    fn load_offline_success() {
+        let data = Offline::new(prices_dir())
+            .with_ticker(&["AAPL".to_owned()], None)
+            .with_currency(Currency::USD, Currency::GBP)
+            .load_blocking(period("2023-03-31"))
+            .unwrap();
+
+        assert_eq!(
+            utils::polars::column_str(&data.quotes, Column::Ticker.as_str()).unwrap(),
+            ["AAPL", "USD/GBP", "AAPL", "USD/GBP"]
+        );
+        assert_eq!(
+            utils::polars::column_f64(&data.quotes, Column::Price.as_str()).unwrap(),
+            [150.0, 0.82, 160.0, 0.81]
+        );
+        assert_eq!(
+            utils::polars::column_str(&data.dividends, Column::Ticker.as_str()).unwrap(),
+            ["AAPL"]
+        );
+        assert_eq!(data.splits.height(), 0);
+    }
+
+    #[test]
+    fn load_offline_parquet_success() {
+        let dir = PathBuf::from("target/offline_parquet");
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut quotes = df!(
+            Column::Date.into() => &["2023-01-31", "2023-03-31"],
+            Column::Ticker.into() => &["AAPL", "AAPL"],
+            Column::Price.into() => &[150.0, 160.0],
+            Column::Currency.into() => &["USD", "USD"],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Column::Date.into()))
+        .collect()
+        .unwrap();
+        ParquetWriter::new(std::fs::File::create(dir.join("quotes.parquet")).unwrap())
+            .finish(&mut quotes)
+            .unwrap();
+
+        let data = Offline::new(dir)
+            .with_ticker(&["AAPL".to_owned()], None)
+            .load_blocking(period("2023-03-31"))
+            .unwrap();
+
+        assert_eq!(data.quotes, quotes);
+        assert_eq!(data.dividends.height(), 0);
+    }
+
+    #[test]
+    fn load_offline_missing_ticker_fails() {
+        let error = Offline::new(prices_dir())
+            .with_ticker(&["MSFT".to_owned()], None)
+            .load_blocking(period("2023-03-31"))
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::MissingPrices { ticker, .. }) if ticker == "MSFT"
+        ));
+    }
+
+    #[test]
+    fn load_offline_missing_dates_fails() {
+        let error = Offline::new(prices_dir())
+            .with_ticker(&["AAPL".to_owned()], None)
+            .load_blocking(period("2023-06-30"))
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::MissingDates { .. })
+        ));
+    }
+
+    #[test]
+    fn load_offline_stale_ticker_fails() {
+        let dir = PathBuf::from("target/offline_stale");
+        std::fs::create_dir_all(&dir).unwrap();
+        // The AAPL quotes cover the period, the GOOGL ones end two months before.
+        std::fs::write(
+            dir.join("quotes.csv"),
+            "Date,Ticker,Price,Currency\n\
+             2023-01-31,AAPL,150.0,USD\n\
+             2023-01-31,GOOGL,98.5,USD\n\
+             2023-03-31,AAPL,160.0,USD\n",
+        )
+        .unwrap();
+
+        let error = Offline::new(dir)
+            .with_ticker(&["AAPL".to_owned(), "GOOGL".to_owned()], None)
+            .load_blocking(period("2023-03-31"))
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::MissingDates { ticker, .. }) if ticker == "GOOGL"
+        ));
+    }
+
+    #[test]
+    fn load_offline_late_ticker_fails() {
+        let dir = PathBuf::from("target/offline_late");
+        std::fs::create_dir_all(&dir).unwrap();
+        // The AAPL quotes cover the period, the GOOGL ones start two months after.
+        std::fs::write(
+            dir.join("quotes.csv"),
+            "Date,Ticker,Price,Currency\n\
+             2023-01-31,AAPL,150.0,USD\n\
+             2023-03-31,AAPL,160.0,USD\n\
+             2023-03-31,GOOGL,101.0,USD\n",
+        )
+        .unwrap();
+
+        let error = Offline::new(dir)
+            .with_ticker(&["AAPL".to_owned(), "GOOGL".to_owned()], None)
+            .load_blocking(period("2023-03-31"))
+            .unwrap_err();
+
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::MissingDates { ticker, end, .. })
+                if ticker == "GOOGL" && *end == NaiveDate::from_ymd_opt(2023, 3, 31).unwrap()
+        ));
+    }
+}
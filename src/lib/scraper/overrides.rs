@@ -0,0 +1,294 @@
+use crate::error::Error;
+use crate::schema::{Column, Currency};
+use crate::utils;
+use anyhow::{Context, Result};
+use itertools::Itertools;
+
+use polars::prelude::*;
+use std::path::Path;
+
+use super::*;
+
+/// `Source` of the prices and exchange rates of the overrides files.
+pub const MANUAL: &str = "Manual";
+
+/// Prices and exchange rates entered by the user, for the holdings without quotes in the
+/// providers, i.e. delisted shares or private funds. They replace the quotes of the inner scraper
+/// on the same day, with `Manual` in the `Source` column. The latest override before the period is
+/// kept too, so a valuation holds until a newer quote.
+pub struct Overrides<T> {
+    inner: T,
+    quotes: DataFrame,
+    tickers: Vec<String>,
+}
+
+impl<T> Overrides<T>
+where
+    T: IScraper + std::marker::Send,
+{
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            quotes: DataFrame::default(),
+            tickers: Vec::new(),
+        }
+    }
+
+    /// Prices from a csv file with the columns Date, Ticker, Price and Currency.
+    pub fn with_prices(mut self, file: &Path) -> Result<Self> {
+        let prices = Self::read(file, &[Column::Ticker, Column::Price, Column::Currency])?
+            .lazy()
+            .select([
+                utils::polars::str_to_date(Column::Date.as_str()),
+                col(Column::Ticker.as_str()),
+                col(Column::Price.as_str()).strict_cast(DataType::Float64),
+                col(Column::Currency.as_str()),
+            ])
+            .collect()
+            .with_context(|| format!("Failed to read price overrides {:?}", file))?;
+        for currency in utils::polars::column_str(&prices, Column::Currency.as_str())? {
+            currency
+                .parse::<Currency>()
+                .with_context(|| format!("Unknown currency {currency:?} in {:?}", file))?;
+        }
+        self.add(prices)?;
+        Ok(self)
+    }
+
+    /// Exchange rates from a csv file with the columns Date, Pair and Rate, where the pair is
+    /// "FROM/TO", i.e. "USD/GBP".
+    pub fn with_rates(mut self, file: &Path) -> Result<Self> {
+        let rates = Self::read(file, &[Column::Pair, Column::Rate])?
+            .lazy()
+            .select([
+                utils::polars::str_to_date(Column::Date.as_str()),
+                col(Column::Pair.as_str()).alias(Column::Ticker.as_str()),
+                col(Column::Rate.as_str())
+                    .strict_cast(DataType::Float64)
+                    .alias(Column::Price.as_str()),
+                lit(Currency::NA.as_str()).alias(Column::Currency.as_str()),
+            ])
+            .collect()
+            .with_context(|| format!("Failed to read exchange rate overrides {:?}", file))?;
+        for pair in utils::polars::column_str(&rates, Column::Ticker.as_str())? {
+            let (from, to) = pair
+                .split_once('/')
+                .with_context(|| format!("Invalid pair {pair:?} in {:?}", file))?;
+            from.parse::<Currency>()
+                .and(to.parse::<Currency>())
+                .with_context(|| format!("Unknown currency in {pair:?} in {:?}", file))?;
+        }
+        self.add(rates)?;
+        Ok(self)
+    }
+
+    fn read(file: &Path, required: &[Column]) -> Result<DataFrame> {
+        let frame = LazyCsvReader::new(file)
+            .has_header(true)
+            .with_infer_schema_length(Some(0))
+            .finish()?
+            .collect()
+            .with_context(|| format!("Failed to read {:?}", file))?;
+
+        let columns = frame.get_column_names();
+        if let Some(column) = std::iter::once(&Column::Date)
+            .chain(required)
+            .find(|x| !columns.contains(&x.as_str()))
+        {
+            return Err(Error::MissingColumn {
+                column: column.as_str().to_owned(),
+                file: file.to_path_buf(),
+            }
+            .into());
+        }
+        Ok(frame)
+    }
+
+    fn add(&mut self, quotes: DataFrame) -> Result<()> {
+        let quotes = quotes
+            .lazy()
+            .with_column(lit(MANUAL).alias(Column::Source.as_str()));
+        self.quotes = if self.quotes.width() == 0 {
+            quotes.collect()?
+        } else {
+            concat([self.quotes.clone().lazy(), quotes], Default::default())?.collect()?
+        };
+        Ok(())
+    }
+
+    /// The overrides of the tickers requested in the period, and the latest one before it.
+    fn select(&self, period: &SearchPeriod) -> Result<DataFrame> {
+        if self.quotes.height() == 0 {
+            return Ok(DataFrame::default());
+        }
+        let date = || col(Column::Date.as_str());
+        Ok(self
+            .quotes
+            .clone()
+            .lazy()
+            .filter(
+                col(Column::Ticker.as_str())
+                    .is_in(lit(Series::new(
+                        Column::Ticker.as_str(),
+                        self.tickers.clone(),
+                    )))
+                    .and(date().lt_eq(lit(period.end))),
+            )
+            .filter(
+                date()
+                    .gt_eq(lit(period.start))
+                    .or(date().eq(date().max().over([col(Column::Ticker.as_str())]))),
+            )
+            .collect()?)
+    }
+}
+
+impl<T> IScraper for Overrides<T>
+where
+    T: IScraper + std::marker::Send,
+{
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn reset(&mut self) -> &mut Self {
+        self.tickers.clear();
+        self.inner.reset();
+        self
+    }
+
+    fn with_ticker(
+        &mut self,
+        tickers: &[String],
+        countries: Option<&[schema::Country]>,
+    ) -> &mut Self {
+        self.tickers.extend_from_slice(tickers);
+        self.inner.with_ticker(tickers, countries);
+        self
+    }
+
+    fn with_currency(&mut self, from: Currency, to: Currency) -> &mut Self {
+        self.tickers.push(format!("{from}/{to}"));
+        self.inner.with_currency(from, to);
+        self
+    }
+
+    fn load_blocking(&mut self, search_interval: SearchPeriod) -> Result<ScraperData> {
+        tokio_test::block_on(self.load(search_interval))
+    }
+
+    async fn load(&mut self, period: SearchPeriod) -> Result<ScraperData> {
+        let mut data = self.inner.load(period.clone()).await?;
+        let overrides = self.select(&period)?;
+        self.tickers.clear();
+        if overrides.height() == 0 {
+            return Ok(data);
+        }
+
+        let overridden: Vec<String> =
+            utils::polars::column_str(&overrides, Column::Ticker.as_str())?
+                .into_iter()
+                .unique()
+                .map(str::to_owned)
+                .collect();
+        data.quotes = merge_quotes(std::mem::take(&mut data.quotes), overrides)?;
+        data.failures
+            .retain(|(ticker, _)| !overridden.contains(ticker));
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+    use super::*;
+
+    fn period() -> SearchPeriod {
+        SearchPeriod::from_str(Some("2023-01-31"), Some("2023-03-31"), Some(1))
+    }
+
+    #[test]
+    fn overrides_replace_quotes_success() {
+        let prices = temp_file::with_contents(
+            b"Date,Ticker,Price,Currency\n\
+              2023-01-31,AAPL,151.0,USD\n\
+              2023-04-28,AAPL,170.0,USD\n",
+        );
+        let rates = temp_file::with_contents(b"Date,Pair,Rate\n2023-01-31,USD/GBP,0.8\n");
+        let data = Overrides::new(Offline::new("resources/tests/input/prices"))
+            .with_prices(prices.path())
+            .unwrap()
+            .with_rates(rates.path())
+            .unwrap()
+            .with_ticker(&["AAPL".to_owned()], None)
+            .with_currency(Currency::USD, Currency::GBP)
+            .load_blocking(period())
+            .unwrap();
+
+        let quotes = data
+            .quotes
+            .lazy()
+            .sort(
+                [Column::Date.as_str(), Column::Ticker.as_str()],
+                Default::default(),
+            )
+            .collect()
+            .unwrap();
+        assert_eq!(
+            utils::polars::column_str(&quotes, Column::Ticker.as_str()).unwrap(),
+            ["AAPL", "USD/GBP", "AAPL", "USD/GBP"]
+        );
+        assert_eq!(
+            utils::polars::column_f64(&quotes, Column::Price.as_str()).unwrap(),
+            [151.0, 0.8, 160.0, 0.81]
+        );
+        assert_eq!(
+            quotes
+                .column(Column::Source.as_str())
+                .unwrap()
+                .str()
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            [Some(MANUAL), Some(MANUAL), None, None]
+        );
+    }
+
+    #[test]
+    fn overrides_clear_failures() {
+        let prices = temp_file::with_contents(
+            b"Date,Ticker,Price,Currency\n\
+              2022-06-30,DELISTED,12.5,GBP\n\
+              2022-12-30,DELISTED,10.0,GBP\n",
+        );
+        let data = Overrides::new(Fallback::new(vec![Offline::new(
+            "resources/tests/input/prices",
+        )
+        .with_lenient(true)]))
+        .with_prices(prices.path())
+        .unwrap()
+        .with_ticker(&["DELISTED".to_owned()], None)
+        .load_blocking(period())
+        .unwrap();
+
+        // The latest valuation before the period is the price in it.
+        assert_eq!(
+            utils::polars::column_f64(&data.quotes, Column::Price.as_str()).unwrap(),
+            [10.0]
+        );
+        assert!(data.failures.is_empty());
+    }
+
+    #[test]
+    fn overrides_file_requires_columns() {
+        let prices = temp_file::with_contents(b"Date,Ticker,Price\n2023-01-31,AAPL,151.0\n");
+        let error = Overrides::new(Offline::new("resources/tests/input/prices"))
+            .with_prices(prices.path())
+            .err()
+            .unwrap();
+
+        assert!(matches!(
+            error.downcast_ref::<Error>(),
+            Some(Error::MissingColumn { .. })
+        ));
+    }
+}
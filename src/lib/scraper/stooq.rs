@@ -0,0 +1,266 @@
+use crate::schema::{Column, Currency};
+use anyhow::{Context, Result};
+
+use polars::prelude::*;
+
+use super::*;
+
+const BASE_URL: &str = "https://stooq.com";
+
+/// Daily quotes from the csv files of Stooq. Stooq doesn't provide splits and dividends.
+pub struct Stooq {
+    tickers: Vec<String>,
+    countries: Vec<schema::Country>,
+    base_url: String,
+    client: reqwest::Client,
+}
+
+impl Default for Stooq {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stooq {
+    pub fn new() -> Self {
+        Self {
+            tickers: Vec::new(),
+            countries: Vec::new(),
+            base_url: BASE_URL.to_owned(),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Server of the csv files, defaults to stooq.com.
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Suffix of the market and multiplier of the price, Stooq has no Brazilian and European
+    /// quotes.
+    fn map_country(country: &schema::Country) -> Option<(&'static str, f64)> {
+        match country {
+            schema::Country::Usa => Some((".us", 1.0)),
+            schema::Country::Uk => Some((".uk", 0.01)),
+            schema::Country::Ireland => Some((".uk", 1.0)),
+            schema::Country::NA => Some(("", 1.0)),
+            schema::Country::Brazil | schema::Country::EU | schema::Country::Unknown => None,
+        }
+    }
+
+    /// Parses the "Date,Open,High,Low,Close,Volume" csv, the close price is the quote.
+    fn quotes(
+        csv: String,
+        ticker: &str,
+        country: schema::Country,
+        multiplier: f64,
+        period: &SearchPeriod,
+    ) -> Result<DataFrame> {
+        let currency: Currency = country.into();
+        let frame = CsvReader::new(std::io::Cursor::new(csv))
+            .has_header(true)
+            .finish()
+            .with_context(|| format!("Unexpected response for {ticker}"))?;
+
+        Ok(frame
+            .lazy()
+            .select([
+                col("Date")
+                    .cast(DataType::Date)
+                    .alias(Column::Date.as_str()),
+                lit(ticker).alias(Column::Ticker.as_str()),
+                (col("Close").cast(DataType::Float64) * lit(multiplier))
+                    .alias(Column::Price.as_str()),
+                lit(currency.as_str()).alias(Column::Currency.as_str()),
+            ])
+            .filter(
+                col(Column::Date.as_str())
+                    .gt_eq(lit(period.start))
+                    .and(col(Column::Date.as_str()).lt_eq(lit(period.end))),
+            )
+            .collect()?)
+    }
+
+    async fn fetch(&self, symbol: &str, period: &SearchPeriod) -> Result<String> {
+        let url = format!(
+            "{}/q/d/l/?s={}&d1={}&d2={}&i=d",
+            self.base_url,
+            symbol.to_lowercase(),
+            period.start.format("%Y%m%d"),
+            period.end.format("%Y%m%d"),
+        );
+        let content = self
+            .client
+            .get(&url)
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        // Unknown symbols are answered with "No data" instead of an error status.
+        anyhow::ensure!(content.starts_with("Date,"), "No data for {symbol}");
+        Ok(content)
+    }
+}
+
+impl IScraper for Stooq {
+    fn name(&self) -> &'static str {
+        "Stooq"
+    }
+
+    fn reset(&mut self) -> &mut Self {
+        self.countries.clear();
+        self.tickers.clear();
+        self
+    }
+
+    fn with_ticker(
+        &mut self,
+        tickers: &[String],
+        countries: Option<&[schema::Country]>,
+    ) -> &mut Self {
+        self.tickers.extend_from_slice(tickers);
+        self.countries
+            .extend_from_slice(countries.unwrap_or(&vec![schema::Country::Usa; tickers.len()]));
+        self
+    }
+
+    fn with_currency(&mut self, from: Currency, to: Currency) -> &mut Self {
+        let ticker = format!("{from}/{to}");
+        if !self.tickers.contains(&ticker) {
+            self.tickers.push(ticker);
+            self.countries.push(schema::Country::NA);
+        }
+        self
+    }
+
+    fn load_blocking(&mut self, search_interval: SearchPeriod) -> Result<ScraperData> {
+        tokio_test::block_on(self.load(search_interval))
+    }
+
+    async fn load(&mut self, period: SearchPeriod) -> Result<ScraperData> {
+        let mut data = ScraperData::default();
+        for (ticker, country) in self.tickers.iter().zip(self.countries.iter()) {
+            let Some((suffix, multiplier)) = Self::map_country(country) else {
+                let reason = match country {
+                    schema::Country::Unknown => "Unknown country".to_owned(),
+                    _ => format!("No quotes in {country}"),
+                };
+                log::info!("Stooq has no quotes of {:?}: {reason}", ticker);
+                data.failures.push((ticker.clone(), reason));
+                continue;
+            };
+            let symbol = format!("{}{}", ticker.replace('/', ""), suffix);
+
+            let response = match self.fetch(&symbol, &period).await {
+                Ok(response) => response,
+                Err(reason) => {
+                    log::info!("Failed to load {:?} with {:?}: {reason}", &ticker, period);
+                    data.failures.push((ticker.clone(), reason.to_string()));
+                    continue;
+                }
+            };
+
+            data.concat_quotes(Self::quotes(
+                response,
+                ticker,
+                country.to_owned(),
+                multiplier,
+                &period,
+            )?)?;
+        }
+
+        self.reset();
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+    use super::*;
+    use crate::utils;
+
+    fn stooq() -> Stooq {
+        Stooq::new().with_base_url(utils::test::http::serve_dir("resources/tests/input/stooq"))
+    }
+
+    fn period() -> SearchPeriod {
+        SearchPeriod::from_str(Some("2024-02-06"), Some("2024-02-08"), Some(1))
+    }
+
+    #[test]
+    fn get_quotes_with_country_success() {
+        let data = stooq()
+            .with_ticker(
+                &["AAPL".to_owned(), "TSCO".to_owned()],
+                Some(&[schema::Country::Usa, schema::Country::Uk]),
+            )
+            .load_blocking(period())
+            .unwrap();
+
+        let expected = df!(
+            Column::Date.into() => &["2024-02-06", "2024-02-06", "2024-02-07", "2024-02-07", "2024-02-08", "2024-02-08"],
+            Column::Ticker.into() => &["AAPL", "TSCO", "AAPL", "TSCO", "AAPL", "TSCO"],
+            Column::Price.into() => &[189.3, 2.895, 189.41, 2.904, 188.32, 2.898],
+            Column::Currency.into() => &["USD", "GBP", "USD", "GBP", "USD", "GBP"],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(col(Column::Date.into()).cast(DataType::Date))
+        .with_column(col(Column::Price.into()).round(4))
+        .collect()
+        .unwrap();
+        let quotes = data
+            .quotes
+            .lazy()
+            .sort_by_exprs(
+                [col(Column::Date.into()), col(Column::Ticker.into())],
+                SortMultipleOptions::default(),
+            )
+            .with_column(col(Column::Price.into()).round(4))
+            .collect()
+            .unwrap();
+        assert_eq!(expected, quotes);
+    }
+
+    #[test]
+    fn get_currency_and_skip_unknown_success() {
+        let data = stooq()
+            .with_currency(Currency::USD, Currency::GBP)
+            .with_ticker(&["UNKNOWN".to_owned()], None)
+            .load_blocking(SearchPeriod::from_str(
+                Some("2024-02-08"),
+                Some("2024-02-08"),
+                Some(1),
+            ))
+            .unwrap();
+
+        let expected = df!(
+            Column::Date.into() => &["2024-02-08"],
+            Column::Ticker.into() => &["USD/GBP"],
+            Column::Price.into() => &[0.7927],
+            Column::Currency.into() => &["NA"],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(col(Column::Date.into()).cast(DataType::Date))
+        .collect()
+        .unwrap();
+        assert_eq!(expected, data.quotes);
+    }
+
+    #[test]
+    fn unknown_country_fails() {
+        let data = stooq()
+            .with_ticker(&["NESN".to_owned()], Some(&[schema::Country::Unknown]))
+            .load_blocking(period())
+            .unwrap();
+
+        assert_eq!(data.quotes.height(), 0);
+        assert_eq!(
+            data.failures,
+            [("NESN".to_owned(), "Unknown country".to_owned())]
+        );
+    }
+}
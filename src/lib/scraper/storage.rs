@@ -0,0 +1,326 @@
+use crate::schema::Column;
+use anyhow::{Context, Result};
+
+use polars::prelude::*;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Parts of a parquet table before they're merged in a single file.
+const MAX_PARTS: usize = 16;
+/// A lock file older than this was left by a run that crashed.
+const STALE_LOCK: Duration = Duration::from_secs(600);
+
+/// The tables of the `Cache`, the market data and the date ranges held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::IntoStaticStr, strum::EnumString)]
+#[strum(serialize_all = "PascalCase")]
+pub enum Table {
+    Quotes,
+    Splits,
+    Dividends,
+    Ranges,
+}
+
+impl Table {
+    pub fn as_str(self) -> &'static str {
+        self.into()
+    }
+
+    /// Name of the files of the table, i.e. "quotes".
+    pub fn file_name(self) -> String {
+        self.as_str().to_lowercase()
+    }
+}
+
+/// Where the `Cache` keeps its tables.
+pub trait IStorage {
+    /// The rows of the table, empty if there are none.
+    fn read(&self, table: Table) -> Result<DataFrame>;
+    /// Adds the rows to the table, they must have its columns.
+    fn append(&self, table: Table, df: &mut DataFrame) -> Result<()>;
+    /// Replaces the rows of the table.
+    fn write(&self, table: Table, df: &mut DataFrame) -> Result<()>;
+    /// File locked by the runs sharing the storage.
+    fn lock_file(&self) -> PathBuf;
+}
+
+/// A csv file per table, the layout of the cache before the parquet storage.
+pub struct Csv {
+    dir: PathBuf,
+}
+
+impl Csv {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn file(&self, table: Table) -> PathBuf {
+        self.dir.join(format!("{}.csv", table.file_name()))
+    }
+}
+
+impl IStorage for Csv {
+    fn read(&self, table: Table) -> Result<DataFrame> {
+        let file = self.file(table);
+        if !file.exists() {
+            return Ok(DataFrame::default());
+        }
+        let df = CsvReader::from_path(&file)
+            .and_then(|reader| reader.has_header(true).finish())
+            .with_context(|| format!("Failed to read {:?}", file))?;
+        // The csv has no types, the dates are read as text.
+        let dates: Vec<_> = [Column::Date, Column::Start, Column::End]
+            .into_iter()
+            .filter(|x| df.get_column_names().contains(&x.as_str()))
+            .map(|x| col(x.as_str()).cast(DataType::Date))
+            .collect();
+        Ok(df.lazy().with_columns(dates).collect()?)
+    }
+
+    fn append(&self, table: Table, df: &mut DataFrame) -> Result<()> {
+        let mut rows = self.read(table)?;
+        if rows.height() == 0 {
+            return self.write(table, df);
+        }
+        rows.vstack_mut(df)?;
+        self.write(table, &mut rows)
+    }
+
+    fn write(&self, table: Table, df: &mut DataFrame) -> Result<()> {
+        let file = self.file(table);
+        let mut f =
+            std::fs::File::create(&file).with_context(|| format!("Failed to create {:?}", file))?;
+        CsvWriter::new(&mut f).finish(df)?;
+        Ok(())
+    }
+
+    fn lock_file(&self) -> PathBuf {
+        self.dir.join("cache.lock")
+    }
+}
+
+/// A directory of parquet files per table, the rows appended are written in a new file. The
+/// columns keep their types, i.e. the dates don't need to be parsed again.
+pub struct Parquet {
+    dir: PathBuf,
+}
+
+impl Parquet {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn parts(&self, table: Table) -> Result<Vec<PathBuf>> {
+        let dir = self.dir.join(table.file_name());
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut parts = Vec::new();
+        for entry in std::fs::read_dir(&dir).with_context(|| format!("Failed to read {:?}", dir))? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|x| x == "parquet") {
+                parts.push(path);
+            }
+        }
+        parts.sort();
+        Ok(parts)
+    }
+
+    fn next_index(parts: &[PathBuf]) -> usize {
+        parts
+            .last()
+            .and_then(|x| x.file_stem()?.to_str()?.strip_prefix("part-")?.parse().ok())
+            .map_or(0, |x: usize| x + 1)
+    }
+
+    fn write_part(dir: &Path, index: usize, df: &mut DataFrame) -> Result<()> {
+        std::fs::create_dir_all(dir).with_context(|| format!("Failed to create {:?}", dir))?;
+        // Renamed once complete, a failed write doesn't leave a broken part.
+        let tmp = dir.join(format!("part-{index:05}.tmp"));
+        let file =
+            std::fs::File::create(&tmp).with_context(|| format!("Failed to create {:?}", tmp))?;
+        ParquetWriter::new(file).finish(df)?;
+        std::fs::rename(&tmp, dir.join(format!("part-{index:05}.parquet")))?;
+        Ok(())
+    }
+}
+
+impl IStorage for Parquet {
+    fn read(&self, table: Table) -> Result<DataFrame> {
+        let mut rows = DataFrame::default();
+        for part in self.parts(table)? {
+            let file =
+                std::fs::File::open(&part).with_context(|| format!("Failed to open {:?}", part))?;
+            let df = ParquetReader::new(file)
+                .finish()
+                .with_context(|| format!("Failed to read {:?}", part))?;
+            if rows.width() == 0 {
+                rows = df;
+            } else {
+                rows.vstack_mut(&df)?;
+            }
+        }
+        rows.as_single_chunk();
+        Ok(rows)
+    }
+
+    fn append(&self, table: Table, df: &mut DataFrame) -> Result<()> {
+        let parts = self.parts(table)?;
+        if parts.len() >= MAX_PARTS {
+            let mut rows = self.read(table)?;
+            rows.vstack_mut(df)?;
+            return self.write(table, &mut rows);
+        }
+        Self::write_part(
+            &self.dir.join(table.file_name()),
+            Self::next_index(&parts),
+            df,
+        )
+    }
+
+    fn write(&self, table: Table, df: &mut DataFrame) -> Result<()> {
+        let dir = self.dir.join(table.file_name());
+        let parts = self.parts(table)?;
+        let index = Self::next_index(&parts);
+        Self::write_part(&dir, index, df)?;
+        for part in parts {
+            std::fs::remove_file(&part).with_context(|| format!("Failed to remove {:?}", part))?;
+        }
+        let last = dir.join(format!("part-{index:05}.parquet"));
+        std::fs::rename(last, dir.join("part-00000.parquet"))?;
+        Ok(())
+    }
+
+    fn lock_file(&self) -> PathBuf {
+        self.dir.join("cache.lock")
+    }
+}
+
+/// Exclusive access to a storage shared by several runs, the lock file is removed when dropped.
+pub struct Lock {
+    file: PathBuf,
+}
+
+impl Lock {
+    /// Waits up to `timeout` for the runs holding the lock.
+    pub async fn acquire(file: PathBuf, timeout: Duration) -> Result<Self> {
+        let start = std::time::Instant::now();
+        loop {
+            match std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&file)
+            {
+                Ok(mut f) => {
+                    writeln!(f, "{}", std::process::id())?;
+                    return Ok(Self { file });
+                }
+                Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {
+                    if Self::is_stale(&file) {
+                        log::warn!("Removing the stale lock {:?}", file);
+                        let _ = std::fs::remove_file(&file);
+                        continue;
+                    }
+                    anyhow::ensure!(
+                        start.elapsed() < timeout,
+                        "The cache is in use, remove {:?} if no other run is going on",
+                        file
+                    );
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+                Err(error) => {
+                    return Err(error).with_context(|| format!("Failed to create {:?}", file))
+                }
+            }
+        }
+    }
+
+    fn is_stale(file: &Path) -> bool {
+        std::fs::metadata(file)
+            .and_then(|x| x.modified())
+            .is_ok_and(|x| {
+                SystemTime::now()
+                    .duration_since(x)
+                    .is_ok_and(|age| age > STALE_LOCK)
+            })
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.file);
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+    use super::*;
+    use crate::utils;
+
+    fn quotes(dates: &[&str], prices: &[f64]) -> DataFrame {
+        df!(
+            Column::Date.into() => dates,
+            Column::Ticker.into() => vec!["AAPL"; dates.len()],
+            Column::Price.into() => prices,
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Column::Date.into()))
+        .collect()
+        .unwrap()
+    }
+
+    fn append_and_write(storage: impl IStorage, dir: &Path) {
+        let _ = std::fs::remove_dir_all(dir);
+        std::fs::create_dir_all(dir).unwrap();
+        assert_eq!(storage.read(Table::Quotes).unwrap().height(), 0);
+
+        storage
+            .append(Table::Quotes, &mut quotes(&["2024-02-07"], &[189.41]))
+            .unwrap();
+        storage
+            .append(Table::Quotes, &mut quotes(&["2024-02-08"], &[188.32]))
+            .unwrap();
+        assert_eq!(
+            storage.read(Table::Quotes).unwrap(),
+            quotes(&["2024-02-07", "2024-02-08"], &[189.41, 188.32])
+        );
+
+        storage
+            .write(Table::Quotes, &mut quotes(&["2024-02-09"], &[188.85]))
+            .unwrap();
+        assert_eq!(
+            storage.read(Table::Quotes).unwrap(),
+            quotes(&["2024-02-09"], &[188.85])
+        );
+    }
+
+    #[test]
+    fn parquet_append_and_write_success() {
+        let dir = PathBuf::from("target/storage_parquet");
+        append_and_write(Parquet::new(&dir), &dir);
+        assert_eq!(Parquet::new(&dir).parts(Table::Quotes).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn csv_append_and_write_success() {
+        let dir = PathBuf::from("target/storage_csv");
+        append_and_write(Csv::new(&dir), &dir);
+    }
+
+    #[test]
+    fn lock_is_exclusive() {
+        let dir = PathBuf::from("target/storage_lock");
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = Parquet::new(&dir).lock_file();
+        let _ = std::fs::remove_file(&file);
+
+        tokio_test::block_on(async {
+            let lock = Lock::acquire(file.clone(), Duration::ZERO).await.unwrap();
+            assert!(Lock::acquire(file.clone(), Duration::ZERO).await.is_err());
+            drop(lock);
+            assert!(Lock::acquire(file.clone(), Duration::ZERO).await.is_ok());
+        });
+        assert!(!file.exists());
+    }
+}
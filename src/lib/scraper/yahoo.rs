@@ -1,16 +1,52 @@
 use crate::schema::Column;
 use crate::schema::Currency;
-use anyhow::Result;
+use anyhow::{Context, Result};
 
 use chrono::{self, TimeZone};
+use futures::StreamExt;
+use std::time::Duration;
+use tokio::time::Instant;
 use yahoo_finance_api as yahoo;
 
 use super::*;
 
+/// Requests in flight at the same time.
+const CONCURRENCY: usize = 4;
+const REQUESTS_PER_SECOND: f64 = 5.0;
+/// Retries of a request answered with 429 or 5xx, the wait doubles from `BACKOFF` at each one.
+const RETRIES: u32 = 3;
+const BACKOFF: Duration = Duration::from_millis(500);
+
+/// Spaces the start of the requests to honour a number of requests per second.
+struct RateLimit {
+    interval: Duration,
+    next: tokio::sync::Mutex<Option<Instant>>,
+}
+
+impl RateLimit {
+    fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            next: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    async fn wait(&self) {
+        let mut next = self.next.lock().await;
+        let start = next.map_or(Instant::now(), |next| next.max(Instant::now()));
+        *next = Some(start + self.interval);
+        drop(next);
+        tokio::time::sleep_until(start).await;
+    }
+}
+
 pub struct Yahoo {
     tickers: Vec<String>,
     countries: Vec<schema::Country>,
     provider: yahoo::YahooConnector,
+    concurrency: usize,
+    retries: u32,
+    rate_limit: RateLimit,
 }
 
 impl Default for Yahoo {
@@ -25,18 +61,111 @@ impl Yahoo {
             tickers: Vec::new(),
             countries: Vec::new(),
             provider: yahoo::YahooConnector::new().expect("Failed to connect Yahoo API"),
+            concurrency: CONCURRENCY,
+            retries: RETRIES,
+            rate_limit: RateLimit::new(Duration::from_secs_f64(1.0 / REQUESTS_PER_SECOND)),
+        }
+    }
+
+    /// Number of tickers fetched at the same time.
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Limit of the requests started per second, including the retries, it fails unless above 0.
+    pub fn with_requests_per_second(mut self, requests_per_second: f64) -> Result<Self> {
+        anyhow::ensure!(
+            requests_per_second > 0.0,
+            "The requests per second must be above 0, not {requests_per_second}"
+        );
+        let interval =
+            Duration::try_from_secs_f64(1.0 / requests_per_second).with_context(|| {
+                format!("Can't wait between {requests_per_second} requests per second")
+            })?;
+        self.rate_limit = RateLimit::new(interval);
+        Ok(self)
+    }
+
+    /// Retries of the requests failed with 429 or 5xx.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Too many requests, server and connection errors are worth a retry.
+    fn is_transient(error: &yahoo::YahooError) -> bool {
+        match error {
+            yahoo::YahooError::FetchFailed(status) => {
+                status.starts_with("429") || status.starts_with('5')
+            }
+            yahoo::YahooError::ConnectionFailed(error) => error.is_timeout() || error.is_connect(),
+            _ => false,
         }
     }
 
-    fn map_country(country: &schema::Country) -> (&'static str, f64) {
+    fn reason(error: yahoo::YahooError) -> String {
+        match &error {
+            yahoo::YahooError::FetchFailed(status) => format!("{error}: {status}"),
+            _ => format!("{:#}", anyhow::Error::from(error)),
+        }
+    }
+
+    /// The FX symbols "USDGBP=x" are the tickers "USD/GBP".
+    fn ticker(symbol: &str) -> String {
+        if symbol.contains("=x") {
+            let ticker = symbol.replace("=x", "");
+            let (from, to) = ticker.split_at(3);
+            format!("{from}/{to}")
+        } else {
+            symbol.to_owned()
+        }
+    }
+
+    async fn fetch(&self, symbol: &str, period: &SearchPeriod) -> Result<yahoo::YResponse, String> {
+        let timestamp = |date: chrono::NaiveDate| {
+            time::OffsetDateTime::from_unix_timestamp(
+                date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp(),
+            )
+            .map_err(|_| format!("Date {date} out of range"))
+        };
+        let (start, end) = (timestamp(period.start)?, timestamp(period.end)?);
+
+        let mut backoff = BACKOFF;
+        for retry in 0.. {
+            self.rate_limit.wait().await;
+            let response = self
+                .provider
+                .get_quote_history_interval(
+                    symbol,
+                    start,
+                    end,
+                    &format!("{}d", period.interval_days),
+                )
+                .await;
+            match response {
+                Err(error) if retry < self.retries && Self::is_transient(&error) => {
+                    log::info!("Retrying {symbol} in {backoff:?}: {}", Self::reason(error));
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                response => return response.map_err(Self::reason),
+            }
+        }
+        unreachable!()
+    }
+
+    /// The suffix of the euro markets depends on the exchange, i.e. `.AS` or `.DE`, so their
+    /// tickers must carry it.
+    fn map_country(country: &schema::Country) -> Option<(&'static str, f64)> {
         match country {
-            schema::Country::Usa => ("", 1.0),
-            schema::Country::Uk => (".L", 0.01),
-            schema::Country::Brazil => (".SA", 1.0),
-            schema::Country::Ireland => (".L", 1.0),
-            schema::Country::NA => ("", 1.0),
-            schema::Country::EU => todo!(),
-            schema::Country::Unknown => panic!("Country must be known"),
+            schema::Country::Usa => Some(("", 1.0)),
+            schema::Country::Uk => Some((".L", 0.01)),
+            schema::Country::Brazil => Some((".SA", 1.0)),
+            schema::Country::Ireland => Some((".L", 1.0)),
+            schema::Country::NA => Some(("", 1.0)),
+            schema::Country::EU => Some(("", 1.0)),
+            schema::Country::Unknown => None,
         }
     }
 
@@ -47,13 +176,7 @@ impl Yahoo {
         country: schema::Country,
         multiplier: f64,
     ) -> Result<DataFrame> {
-        let ticker = if ticker.contains("=x") {
-            let ticker = ticker.replace("=x", "");
-            let (from, to) = ticker.split_at(3);
-            format!("{from}/{to}")
-        } else {
-            ticker.to_owned()
-        };
+        let ticker = Self::ticker(ticker);
         let currency: schema::Currency = country.into();
         let (date, price, currency): (Vec<_>, Vec<_>, Vec<_>) =
             itertools::multiunzip(response.quotes()?.iter().map(|quote| {
@@ -124,6 +247,10 @@ impl Yahoo {
 }
 
 impl IScraper for Yahoo {
+    fn name(&self) -> &'static str {
+        "Yahoo"
+    }
+
     fn reset(&mut self) -> &mut Self {
         self.countries.clear();
         self.tickers.clear();
@@ -156,42 +283,38 @@ impl IScraper for Yahoo {
     }
 
     async fn load(&mut self, period: SearchPeriod) -> Result<ScraperData> {
-        let mut data = ScraperData::default();
-        for (ticker, country) in self.tickers.iter().zip(self.countries.iter()) {
-            let (suffix, multiplier) = Self::map_country(country);
-            let symbol = format!("{}{}", ticker, suffix);
+        let this = &*self;
+        let mut requests = Vec::new();
+        for (ticker, country) in this.tickers.iter().zip(this.countries.iter()) {
+            let period = &period;
+            requests.push(async move {
+                let Some((suffix, multiplier)) = Self::map_country(country) else {
+                    return (ticker, *country, 1.0, Err("Unknown country".to_owned()));
+                };
+                let symbol = format!("{}{}", ticker, suffix);
+                let response = this.fetch(&symbol, period).await;
+                (ticker, *country, multiplier, response)
+            });
+        }
+        let responses: Vec<_> = futures::stream::iter(requests)
+            .buffer_unordered(this.concurrency)
+            .collect()
+            .await;
 
-            let response = self
-                .provider
-                .get_quote_history_interval(
-                    &symbol,
-                    time::OffsetDateTime::from_unix_timestamp(
-                        period
-                            .start
-                            .and_hms_opt(0, 0, 0)
-                            .unwrap()
-                            .and_utc()
-                            .timestamp(),
-                    )?,
-                    time::OffsetDateTime::from_unix_timestamp(
-                        period
-                            .end
-                            .and_hms_opt(0, 0, 0)
-                            .unwrap()
-                            .and_utc()
-                            .timestamp(),
-                    )?,
-                    &format!("{}d", period.interval_days),
-                )
-                .await;
-            let Ok(response) = response else {
-                log::info!("Failed to load {:?} with {:?}", &ticker, period);
-                continue;
+        let mut data = ScraperData::default();
+        for (ticker, country, multiplier, response) in responses {
+            let response = match response {
+                Ok(response) => response,
+                Err(reason) => {
+                    log::warn!("Failed to load {:?} with {:?}: {reason}", &ticker, period);
+                    data.failures.push((Self::ticker(ticker), reason));
+                    continue;
+                }
             };
 
-            data.concat_quotes(self.quotes(&response, ticker, country.to_owned(), multiplier)?)?
+            data.concat_quotes(self.quotes(&response, ticker, country, multiplier)?)?
                 .concat_splits(self.splits(&response, ticker)?)?
-                .concat_dividends(self.dividends(&response, ticker, country.to_owned())?)?;
+                .concat_dividends(self.dividends(&response, ticker, country)?)?;
         }
 
         self.reset();
@@ -387,4 +510,65 @@ mod unittest {
     fn currency_usd_eur() {
         currency_quotes(Currency::USD, Currency::EUR, 0.9280099868774414);
     }
+
+    #[test]
+    fn unknown_ticker_is_a_failure() {
+        let mut yh = Yahoo::new().with_retries(0);
+        let data = yh
+            .with_ticker(&["UNKNOWN-TICKER".to_owned()], None)
+            .load_blocking(SearchPeriod::from_str(
+                Some("2024-02-01"),
+                Some("2024-02-08"),
+                Some(1),
+            ))
+            .unwrap();
+
+        assert_eq!(data.quotes.height(), 0);
+        assert_eq!(data.failures.len(), 1);
+        assert_eq!(data.failures[0].0, "UNKNOWN-TICKER");
+    }
+
+    #[test]
+    fn retry_transient_errors() {
+        let fetch_failed = |status: &str| yahoo::YahooError::FetchFailed(status.to_owned());
+        assert!(Yahoo::is_transient(&fetch_failed("429 Too Many Requests")));
+        assert!(Yahoo::is_transient(&fetch_failed(
+            "503 Service Unavailable"
+        )));
+        assert!(!Yahoo::is_transient(&fetch_failed("404 Not Found")));
+        assert!(!Yahoo::is_transient(&yahoo::YahooError::EmptyDataSet));
+    }
+
+    #[test]
+    fn rate_limit_spaces_requests() {
+        tokio_test::block_on(async {
+            let limit = RateLimit::new(Duration::from_millis(50));
+            let start = Instant::now();
+            for _ in 0..5 {
+                limit.wait().await;
+            }
+            assert!(start.elapsed() >= Duration::from_millis(200));
+        });
+    }
+
+    #[test]
+    fn requests_per_second_above_zero() {
+        for rate in [0.0, -1.0, f64::NAN, 1e-320] {
+            assert!(Yahoo::new().with_requests_per_second(rate).is_err());
+        }
+        assert!(Yahoo::new().with_requests_per_second(0.5).is_ok());
+    }
+
+    #[test]
+    fn unknown_country_fails() {
+        let data = Yahoo::new()
+            .with_ticker(&["NESN".to_owned()], Some(&[schema::Country::Unknown]))
+            .load_blocking(SearchPeriod::new(None, None, None))
+            .unwrap();
+
+        assert_eq!(
+            data.failures,
+            [("NESN".to_owned(), "Unknown country".to_owned())]
+        );
+    }
 }
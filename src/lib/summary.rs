@@ -3,6 +3,7 @@ use crate::schema;
 use crate::schema::Column;
 use crate::scraper::IScraper;
 use crate::utils;
+use crate::xirr;
 use anyhow::Result;
 use polars::prelude::*;
 
@@ -81,20 +82,20 @@ impl Summary {
         orders: impl IntoLazy,
         currency: schema::Currency,
         scraper: &mut impl IScraper,
-        present_date: Option<chrono::NaiveDate>,
+        rate: currency::ExchangeRate,
     ) -> Result<&mut Self> {
         let mut captal_invested = orders
             .lazy()
             .filter(utils::polars::filter::deposit_and_withdraw())
             .with_column(utils::polars::compute::negative_amount_on_withdraw());
 
-        captal_invested = currency::normalize(
+        captal_invested = currency::convert(
             captal_invested,
             schema::Column::Currency.as_str(),
             &[col(Column::Amount.as_str())],
             currency,
             scraper,
-            present_date,
+            rate,
         )?;
 
         let captal_invested = captal_invested
@@ -112,6 +113,60 @@ impl Summary {
         Ok(self)
     }
 
+    /// Annualized return of the portfolio, where deposits and withdraws are the cash flows and
+    /// the market value plus the uninvested cash is the terminal one.
+    pub fn with_xirr(
+        &mut self,
+        orders: impl IntoLazy,
+        currency: schema::Currency,
+        scraper: &mut impl IScraper,
+        rate: currency::ExchangeRate,
+        present_date: Option<chrono::NaiveDate>,
+    ) -> Result<&mut Self> {
+        let present_date = present_date.unwrap_or(chrono::Local::now().date_naive());
+        let flows = orders
+            .lazy()
+            .filter(utils::polars::filter::deposit_and_withdraw())
+            .filter(col(Column::Date.as_str()).lt_eq(lit(present_date)))
+            .with_column(utils::polars::compute::negative_amount_on_withdraw() * lit(-1));
+
+        let flows = currency::convert(
+            flows,
+            schema::Column::Currency.as_str(),
+            &[col(Column::Amount.as_str())],
+            currency,
+            scraper,
+            rate,
+        )?
+        .collect()?;
+
+        let terminal = self
+            .data
+            .clone()
+            .select([(col(Column::MarketValue.as_str())
+                + col(Column::UninvestedCash.as_str()).sum())
+            .first()])
+            .collect()?;
+
+        let mut flows: Vec<_> = utils::polars::column_date(&flows, Column::Date.as_str())?
+            .into_iter()
+            .zip(utils::polars::column_f64(&flows, Column::Amount.as_str())?)
+            .collect();
+        flows.extend(
+            utils::polars::column_f64(&terminal, Column::MarketValue.as_str())?
+                .into_iter()
+                .map(|value| (present_date, value)),
+        );
+
+        self.data = polars::functions::concat_df_horizontal(&[
+            self.data.clone().collect()?,
+            df!(Column::Xirr.as_str() => [xirr::xirr(&flows).map(|rate| rate * 100.0)])?,
+        ])?
+        .lazy();
+
+        Ok(self)
+    }
+
     pub fn collect(&mut self) -> Result<DataFrame> {
         Ok(self
             .finish()
@@ -128,12 +183,19 @@ impl Summary {
                         .filter(col(DESCRIPTION).eq(lit(Column::PrimaryCapital.as_str()))))
                 .alias(RATE),
             )
+            // The XIRR is already a rate.
+            .with_column(
+                when(col(DESCRIPTION).eq(lit(Column::Xirr.as_str())))
+                    .then(lit(NULL))
+                    .otherwise(col(RATE))
+                    .alias(RATE),
+            )
             .with_column(dtype_col(&DataType::Float64).round(2))
             .collect()?)
     }
 
     pub fn finish(&mut self) -> LazyFrame {
-        let column_order: Vec<_> = [
+        let mut column_order: Vec<_> = [
             Column::PrimaryCapital,
             Column::PortfolioCost,
             Column::MarketValue,
@@ -146,6 +208,13 @@ impl Summary {
         .iter()
         .map(|x| col(x.into()))
         .collect();
+        if self
+            .data
+            .schema()
+            .is_ok_and(|schema| schema.contains(Column::Xirr.as_str()))
+        {
+            column_order.push(col(Column::Xirr.as_str()));
+        }
 
         self.data
             .clone()
@@ -159,3 +228,68 @@ impl Summary {
             .with_column(dtype_col(&DataType::Float64).round(2))
     }
 }
+
+#[cfg(test)]
+mod unittest {
+    use super::*;
+
+    #[test]
+    fn summary_with_xirr_success() {
+        let portfolio = df!(
+            Column::Ticker.into() => &["AAPL", schema::Type::Cash.as_str()],
+            Column::Amount.into() => &[1000.0, 50.0],
+            Column::AccruedQty.into() => &[10.0, 1.0],
+            Column::AveragePrice.into() => &[100.0, 0.0],
+            Column::MarketPrice.into() => &[105.0, 0.0],
+            Column::PaperProfit.into() => &[50.0, 0.0],
+        )
+        .unwrap();
+
+        let orders = df!(
+            Column::Date.into() => &["2023-01-01", "2023-06-01", "2023-06-01"],
+            Column::Action.into() => &["Deposit", "Deposit", "Withdraw"],
+            Column::Amount.into() => &[1000.0, 200.0, 200.0],
+            Column::Currency.into() => &["USD", "USD", "USD"],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Column::Date.into()))
+        .collect()
+        .unwrap();
+
+        let mut scraper = utils::test::mock::Scraper::new();
+        let rate = currency::ExchangeRate::Present(None);
+        let result = Summary::from_portfolio(portfolio)
+            .unwrap()
+            .with_dividends(DataFrame::default())
+            .unwrap()
+            .with_capital_invested(orders.clone(), schema::Currency::USD, &mut scraper, rate)
+            .unwrap()
+            .with_liquidated_profit(DataFrame::default())
+            .unwrap()
+            .with_xirr(
+                orders,
+                schema::Currency::USD,
+                &mut scraper,
+                rate,
+                "2024-01-01".parse().ok(),
+            )
+            .unwrap()
+            .collect()
+            .unwrap()
+            .lazy()
+            .filter(col(DESCRIPTION).eq(lit(Column::Xirr.as_str())))
+            .collect()
+            .unwrap();
+
+        // Deposited 1000 a year ago, worth 1050 plus 50 of cash.
+        let expected = df!(
+            DESCRIPTION => &[Column::Xirr.as_str()],
+            Column::Amount.into() => &[10.0],
+            RATE => &[None::<f64>],
+        )
+        .unwrap();
+
+        assert_eq!(expected, result);
+    }
+}
@@ -0,0 +1,711 @@
+use crate::perpetual_inventory::carried_fraction;
+use crate::schema::{Action, Column, Currency, Type};
+use crate::utils;
+use anyhow::{ensure, Result};
+use chrono::{Datelike, NaiveDate};
+use polars::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+/// Swing trade gains of stocks are exempt in the months with sales up to R$20k.
+const MONTHLY_EXEMPTION: f64 = 20_000.0;
+const SWING_TRADE_RATE: f64 = 0.15;
+const DAY_TRADE_RATE: f64 = 0.20;
+const FII_RATE: f64 = 0.20;
+/// A DARF below R$10 isn't paid, the tax is added to the next month.
+const MINIMUM_DARF: f64 = 10.0;
+const TOLERANCE: f64 = 1e-6;
+
+/// The losses of each category only offset later gains of the same category.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, strum::IntoStaticStr)]
+#[strum(serialize_all = "PascalCase")]
+enum Category {
+    SwingTrade,
+    DayTrade,
+    Fii,
+}
+
+impl Category {
+    fn rate(self) -> f64 {
+        match self {
+            Category::SwingTrade => SWING_TRADE_RATE,
+            Category::DayTrade => DAY_TRADE_RATE,
+            Category::Fii => FII_RATE,
+        }
+    }
+}
+
+impl FromStr for Category {
+    type Err = anyhow::Error;
+
+    fn from_str(category: &str) -> Result<Self> {
+        match category {
+            "SwingTrade" => Ok(Category::SwingTrade),
+            "DayTrade" => Ok(Category::DayTrade),
+            "Fii" => Ok(Category::Fii),
+            _ => anyhow::bail!("Unknown tax category {category}"),
+        }
+    }
+}
+
+/// All buys and sells of a ticker in a day, the shares bought and sold in the same day are a
+/// day trade.
+#[derive(Debug)]
+struct Day {
+    date: NaiveDate,
+    bought: f64,
+    cost: f64,
+    sold: f64,
+    proceeds: f64,
+    sale_cost: f64,
+    split: f64,
+}
+
+impl Day {
+    fn new(date: NaiveDate) -> Self {
+        Self {
+            date,
+            bought: 0.0,
+            cost: 0.0,
+            sold: 0.0,
+            proceeds: 0.0,
+            sale_cost: 0.0,
+            split: 1.0,
+        }
+    }
+}
+
+/// A rename, merger or spin-off, the position of `ticker` is carried to `new_ticker`.
+struct Carry<'a> {
+    date: NaiveDate,
+    ticker: &'a str,
+    new_ticker: Option<&'a str>,
+    action: Action,
+    /// Shares of `new_ticker` per share of `ticker`.
+    ratio: f64,
+    /// The fraction of the cost carried to `new_ticker`.
+    fraction: f64,
+    amount: f64,
+}
+
+/// The quantity and cost of a position after each change.
+type History = Vec<(NaiveDate, f64, f64)>;
+
+/// The trades of a day of a ticker or a corporate action, walked in date order.
+#[derive(Clone, Copy)]
+enum Event<'a> {
+    Day(&'a str, usize),
+    Carry(&'a Carry<'a>),
+}
+
+#[derive(Default)]
+struct Disposals {
+    date: Vec<NaiveDate>,
+    ticker: Vec<String>,
+    asset_type: Vec<&'static str>,
+    category: Vec<&'static str>,
+    qty: Vec<f64>,
+    proceeds: Vec<f64>,
+    cost: Vec<f64>,
+}
+
+impl Disposals {
+    /// Records `qty` shares of the sells of `day` matched with `cost` of acquisition.
+    fn push(
+        &mut self,
+        ticker: &str,
+        asset_type: Type,
+        day: &Day,
+        category: Category,
+        qty: f64,
+        cost: f64,
+    ) {
+        let share = qty / day.sold;
+        self.date.push(day.date);
+        self.ticker.push(ticker.to_owned());
+        self.asset_type.push(asset_type.into());
+        self.category.push(category.into());
+        self.qty.push(qty);
+        self.proceeds.push(day.proceeds * share);
+        self.cost.push(cost + day.sale_cost * share);
+    }
+}
+
+#[derive(Default)]
+struct Months {
+    month: Vec<String>,
+    category: Vec<&'static str>,
+    proceeds: Vec<f64>,
+    gain: Vec<f64>,
+    exempt: Vec<f64>,
+    loss: Vec<f64>,
+    taxable: Vec<f64>,
+    tax: Vec<f64>,
+}
+
+#[derive(Default)]
+struct Positions {
+    year: Vec<i32>,
+    ticker: Vec<String>,
+    qty: Vec<f64>,
+    cost: Vec<f64>,
+}
+
+/// The swing trade and day trade categories of the asset type.
+fn categories(asset_type: Type) -> (Category, Category) {
+    if asset_type == Type::Fii {
+        (Category::Fii, Category::Fii)
+    } else {
+        (Category::SwingTrade, Category::DayTrade)
+    }
+}
+
+/// The month of the DARF, i.e. "2023-05".
+pub fn month(date: NaiveDate) -> String {
+    date.format("%Y-%m").to_string()
+}
+
+/// Capital gains of the orders in BRL, the other currencies are taxed as foreign assets.
+pub struct CapitalGains {
+    data: LazyFrame,
+    positions: DataFrame,
+}
+
+impl CapitalGains {
+    /// Sells are matched first with the buys of the same day (day trade) and then with the
+    /// average cost of the position, purchase costs are added to the cost.
+    pub fn try_from_orders(orders: impl IntoLazy) -> Result<Self> {
+        let orders = orders
+            .lazy()
+            .filter(
+                utils::polars::filter::buy_or_sell_or_split()
+                    .or(utils::polars::filter::corporate_action())
+                    .and(col(Column::Currency.as_str()).eq(lit(Currency::BRL.as_str()))),
+            )
+            .with_columns([
+                col(Column::Tax.as_str()).fill_null(lit(0.0)),
+                col(Column::Commission.as_str()).fill_null(lit(0.0)),
+                col(Column::Type.as_str()).fill_null(lit(Type::Stock.as_str())),
+            ])
+            .collect()?;
+        ensure!(
+            orders
+                .clone()
+                .lazy()
+                .filter(utils::polars::filter::disposal())
+                .collect()?
+                .shape()
+                .0
+                > 0,
+            "CapitalGains: Orders must contain selling operations in BRL"
+        );
+
+        let tickers = utils::polars::column_str(&orders, Column::Ticker.as_str())?;
+        let new_tickers = utils::polars::column_opt_str(&orders, Column::NewTicker.as_str())?;
+        let dates = utils::polars::column_date(&orders, Column::Date.as_str())?;
+        let actions = utils::polars::column_str(&orders, Column::Action.as_str())?;
+        let types = utils::polars::column_str(&orders, Column::Type.as_str())?;
+        let qty = utils::polars::column_f64(&orders, Column::Qty.as_str())?;
+        let price = utils::polars::column_opt_f64(&orders, Column::Price.as_str())?;
+        let amount = utils::polars::column_f64(&orders, Column::Amount.as_str())?;
+        let tax = utils::polars::column_f64(&orders, Column::Tax.as_str())?;
+        let commission = utils::polars::column_f64(&orders, Column::Commission.as_str())?;
+
+        let mut days: BTreeMap<&str, (Type, BTreeMap<NaiveDate, Day>)> = BTreeMap::new();
+        let mut carries = Vec::new();
+        for i in 0..orders.height() {
+            let (ticker, date) = (tickers[i], dates[i]);
+            let asset_type = Type::from_str(types[i]).unwrap_or(Type::Stock);
+            let action = Action::from_str(actions[i])?;
+            if matches!(action, Action::Rename | Action::Merger | Action::SpinOff) {
+                if let Some(new_ticker) = new_tickers[i] {
+                    days.entry(new_ticker)
+                        .or_insert_with(|| (asset_type, BTreeMap::new()));
+                }
+                carries.push(Carry {
+                    date,
+                    ticker,
+                    new_ticker: new_tickers[i],
+                    action,
+                    ratio: qty[i],
+                    fraction: carried_fraction(action, price[i].unwrap_or_default()),
+                    amount: amount[i],
+                });
+                continue;
+            }
+
+            let (_, ticker_days) = days
+                .entry(ticker)
+                .or_insert_with(|| (asset_type, BTreeMap::new()));
+            let day = ticker_days.entry(date).or_insert_with(|| Day::new(date));
+            match action {
+                Action::Buy => {
+                    day.bought += qty[i];
+                    day.cost += amount[i] + tax[i] + commission[i];
+                }
+                Action::Sell | Action::CashInLieu => {
+                    day.sold += qty[i];
+                    day.proceeds += amount[i];
+                    day.sale_cost += tax[i] + commission[i];
+                }
+                Action::Split => day.split *= qty[i],
+                _ => unreachable!("Only buy, sell, split and corporate actions are filtered"),
+            }
+        }
+
+        let years =
+            utils::polars::first_date(&orders).year()..=utils::polars::latest_date(&orders).year();
+
+        let days: BTreeMap<&str, (Type, Vec<Day>)> = days
+            .into_iter()
+            .map(|(ticker, (asset_type, days))| {
+                (ticker, (asset_type, days.into_values().collect()))
+            })
+            .collect();
+        let mut disposals = Disposals::default();
+        let mut positions = Positions::default();
+        for (ticker, history) in Self::match_disposals(&days, &carries, &mut disposals)? {
+            for year in years.clone() {
+                let end = NaiveDate::from_ymd_opt(year, 12, 31).expect("Valid year end");
+                if let Some((_, qty, cost)) = history.iter().rev().find(|(date, ..)| *date <= end) {
+                    if *qty > TOLERANCE {
+                        positions.year.push(year);
+                        positions.ticker.push(ticker.to_owned());
+                        positions.qty.push(*qty);
+                        positions.cost.push(*cost);
+                    }
+                }
+            }
+        }
+
+        let data = df!(
+            Column::Date.into() => disposals.date,
+            Column::Ticker.into() => disposals.ticker,
+            Column::Type.into() => disposals.asset_type,
+            Column::TaxCategory.into() => disposals.category,
+            Column::Qty.into() => disposals.qty,
+            Column::Proceeds.into() => disposals.proceeds,
+            Column::AllowableCost.into() => disposals.cost,
+        )?
+        .lazy()
+        .with_column(
+            (col(Column::Proceeds.as_str()) - col(Column::AllowableCost.as_str()))
+                .alias(Column::Gain.as_str()),
+        );
+        let positions = df!(
+            Column::Year.into() => positions.year,
+            Column::Ticker.into() => positions.ticker,
+            Column::Qty.into() => positions.qty,
+            Column::PortfolioCost.into() => positions.cost,
+        )?;
+
+        Ok(Self { data, positions })
+    }
+
+    /// Matches the sells of each day with the buys of the day and then with the average cost,
+    /// returns the position of each ticker after each day. The tickers are walked together in
+    /// date order, so the renames, mergers and spin-offs carry the position to the new ticker
+    /// after the trades of their date.
+    fn match_disposals<'a>(
+        days: &BTreeMap<&'a str, (Type, Vec<Day>)>,
+        carries: &'a [Carry<'a>],
+        disposals: &mut Disposals,
+    ) -> Result<BTreeMap<&'a str, History>> {
+        let mut events: Vec<(NaiveDate, Event)> = days
+            .iter()
+            .flat_map(|(ticker, (_, days))| {
+                days.iter()
+                    .enumerate()
+                    .map(|(i, day)| (day.date, Event::Day(ticker, i)))
+            })
+            .chain(
+                carries
+                    .iter()
+                    .map(|carry| (carry.date, Event::Carry(carry))),
+            )
+            .collect();
+        events.sort_by_key(|(date, event)| (*date, matches!(event, Event::Carry(_))));
+
+        let mut pools: HashMap<&str, (f64, f64)> = HashMap::new();
+        let mut history: BTreeMap<&str, History> = BTreeMap::new();
+        for (date, event) in events {
+            if let Event::Day(ticker, i) = event {
+                let (asset_type, ticker_days) = &days[ticker];
+                let (swing_trade, day_trade) = categories(*asset_type);
+                let day = &ticker_days[i];
+                let (pool_qty, pool_cost) = pools.entry(ticker).or_default();
+                *pool_qty *= day.split;
+                let unit_cost = if day.bought > TOLERANCE {
+                    day.cost / day.bought
+                } else {
+                    0.0
+                };
+
+                let traded = day.sold.min(day.bought);
+                if traded > TOLERANCE {
+                    disposals.push(
+                        ticker,
+                        *asset_type,
+                        day,
+                        day_trade,
+                        traded,
+                        traded * unit_cost,
+                    );
+                }
+
+                let bought = day.bought - traded;
+                if bought > TOLERANCE {
+                    *pool_qty += bought;
+                    *pool_cost += bought * unit_cost;
+                }
+
+                let sold = day.sold - traded;
+                if sold > TOLERANCE {
+                    ensure!(
+                        sold <= *pool_qty + TOLERANCE,
+                        "{ticker}: Sell of {sold} shares on {} exceeds the position of {pool_qty}",
+                        day.date
+                    );
+                    let qty = sold.min(*pool_qty);
+                    let cost = *pool_cost * qty / *pool_qty;
+                    disposals.push(ticker, *asset_type, day, swing_trade, qty, cost);
+                    *pool_qty -= qty;
+                    *pool_cost -= cost;
+                }
+                history
+                    .entry(ticker)
+                    .or_default()
+                    .push((date, *pool_qty, *pool_cost));
+            }
+
+            if let Event::Carry(carry) = event {
+                let (qty, cost) = pools.remove(carry.ticker).unwrap_or_default();
+                let carried = cost * carry.fraction;
+                match carry.action {
+                    Action::SpinOff => {
+                        pools.insert(carry.ticker, (qty, cost - carried));
+                    }
+                    // The cash of a merger is a sale of the cost not carried.
+                    Action::Merger if carry.amount > 0.0 && qty > TOLERANCE => {
+                        let asset_type = days.get(carry.ticker).map_or(Type::Stock, |x| x.0);
+                        let day = Day {
+                            sold: qty,
+                            proceeds: carry.amount,
+                            ..Day::new(date)
+                        };
+                        let (swing_trade, _) = categories(asset_type);
+                        disposals.push(
+                            carry.ticker,
+                            asset_type,
+                            &day,
+                            swing_trade,
+                            qty,
+                            cost - carried,
+                        );
+                    }
+                    _ => (),
+                }
+                let (qty_left, cost_left) = pools.get(carry.ticker).copied().unwrap_or_default();
+                history
+                    .entry(carry.ticker)
+                    .or_default()
+                    .push((date, qty_left, cost_left));
+
+                if let (Some(new_ticker), true) = (carry.new_ticker, qty > TOLERANCE) {
+                    let (pool_qty, pool_cost) = pools.entry(new_ticker).or_default();
+                    *pool_qty += qty * carry.ratio;
+                    *pool_cost += carried;
+                    history
+                        .entry(new_ticker)
+                        .or_default()
+                        .push((date, *pool_qty, *pool_cost));
+                }
+            }
+        }
+        Ok(history)
+    }
+
+    /// The matched sells, one row per day trade and swing trade of each day.
+    pub fn collect(self) -> Result<DataFrame> {
+        Ok(self
+            .data
+            .sort(
+                [Column::Date.as_str(), Column::Ticker.as_str()],
+                SortMultipleOptions::default().with_maintain_order(true),
+            )
+            .collect()?)
+    }
+
+    /// Gains by month and category, after the exemption and the losses carried forward.
+    pub fn by_month(&self) -> Result<DataFrame> {
+        let data = self.data.clone().collect()?;
+
+        // Proceeds, gain and exempt gain of each month and category.
+        let mut months: BTreeMap<(String, Category), (f64, f64, f64)> = BTreeMap::new();
+        let mut stock_sales: BTreeMap<String, f64> = BTreeMap::new();
+        let rows = utils::polars::column_date(&data, Column::Date.as_str())?
+            .into_iter()
+            .zip(utils::polars::column_str(&data, Column::Type.as_str())?)
+            .zip(utils::polars::column_str(
+                &data,
+                Column::TaxCategory.as_str(),
+            )?)
+            .zip(utils::polars::column_f64(&data, Column::Proceeds.as_str())?)
+            .zip(utils::polars::column_f64(&data, Column::Gain.as_str())?);
+        let mut eligible = Vec::new();
+        for ((((date, asset_type), category), proceeds), gain) in rows {
+            let category = Category::from_str(category)?;
+            let entry = months.entry((month(date), category)).or_default();
+            entry.0 += proceeds;
+            entry.1 += gain;
+            // Only the swing trade sales of stocks count for the exemption.
+            if category == Category::SwingTrade && asset_type == Type::Stock.as_str() {
+                *stock_sales.entry(month(date)).or_default() += proceeds;
+                if gain > 0.0 {
+                    eligible.push((month(date), gain));
+                }
+            }
+        }
+        for (month, gain) in eligible {
+            if stock_sales[&month] <= MONTHLY_EXEMPTION {
+                months
+                    .get_mut(&(month, Category::SwingTrade))
+                    .expect("Month of the sale")
+                    .2 += gain;
+            }
+        }
+
+        let mut report = Months::default();
+        let mut losses: BTreeMap<Category, f64> = BTreeMap::new();
+        for ((month, category), (proceeds, gain, exempt)) in months {
+            let loss = losses.entry(category).or_default();
+            let net = gain - exempt;
+            let taxable_gain = if net > 0.0 {
+                let taxable_gain = (net - *loss).max(0.0);
+                *loss = (*loss - net).max(0.0);
+                taxable_gain
+            } else {
+                *loss -= net;
+                0.0
+            };
+
+            report.month.push(month);
+            report.category.push(category.into());
+            report.proceeds.push(proceeds);
+            report.gain.push(gain);
+            report.exempt.push(exempt);
+            report.loss.push(*loss);
+            report.taxable.push(taxable_gain);
+            report.tax.push(taxable_gain * category.rate());
+        }
+
+        Ok(df!(
+            Column::Month.into() => report.month,
+            Column::TaxCategory.into() => report.category,
+            Column::Proceeds.into() => report.proceeds,
+            Column::Gain.into() => report.gain,
+            Column::ExemptGain.into() => report.exempt,
+            Column::LossCarryForward.into() => report.loss,
+            Column::TaxableGain.into() => report.taxable,
+            Column::Tax.into() => report.tax,
+        )?
+        .lazy()
+        .with_column(dtype_col(&DataType::Float64).round(2))
+        .collect()?)
+    }
+
+    /// The DARF of each month, the tax below the minimum is paid with the next month.
+    pub fn darf(&self) -> Result<DataFrame> {
+        let by_month = self
+            .by_month()?
+            .lazy()
+            .group_by_stable([col(Column::Month.as_str())])
+            .agg([col(Column::Tax.as_str()).sum()])
+            .collect()?;
+
+        let mut pending = 0.0;
+        let darf: Vec<f64> = utils::polars::column_f64(&by_month, Column::Tax.as_str())?
+            .into_iter()
+            .map(|tax| {
+                pending += tax;
+                if pending < MINIMUM_DARF {
+                    0.0
+                } else {
+                    std::mem::take(&mut pending)
+                }
+            })
+            .collect();
+
+        Ok(by_month
+            .lazy()
+            .with_column(lit(Series::new(Column::Darf.as_str(), darf)))
+            .with_column(dtype_col(&DataType::Float64).round(2))
+            .collect()?)
+    }
+
+    /// The positions at the end of each year at average cost, as declared in "Bens e Direitos".
+    pub fn bens_e_direitos(&self) -> Result<DataFrame> {
+        Ok(self
+            .positions
+            .clone()
+            .lazy()
+            .with_column(
+                (col(Column::PortfolioCost.as_str()) / col(Column::Qty.as_str()))
+                    .alias(Column::AveragePrice.as_str()),
+            )
+            .with_column(dtype_col(&DataType::Float64).round(2))
+            .collect()?)
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+    use super::*;
+    use crate::schema;
+    use crate::schema::Action::{Buy, Sell, Split};
+    use crate::schema::Column::*;
+
+    fn orders() -> DataFrame {
+        let actions = [Buy, Sell, Sell, Buy, Sell, Buy, Sell, Buy, Sell, Split, Buy];
+        df! (
+            Date.into() => &[
+                "2023-01-10", "2023-02-15", "2023-03-20", "2023-03-20", "2023-03-20", "2023-04-10",
+                "2023-04-10", "2023-04-05", "2023-05-10", "2023-06-01", "2023-07-01",
+            ],
+            Action.into() => actions.map(|x| x.as_str()),
+            Ticker.into() => &[
+                "PETR4", "PETR4", "PETR4", "VALE3", "VALE3", "VALE3", "VALE3", "HGLG11", "HGLG11",
+                "HGLG11", "AAPL",
+            ],
+            Qty.into() => &[1000.0, 500.0, 500.0, 100.0, 100.0, 100.0, 100.0, 10.0, 5.0, 2.0, 1.0],
+            Amount.into() => &[
+                20000.0, 15000.0, 25000.0, 6000.0, 5800.0, 5000.0, 5500.0, 1600.0, 840.0, 0.0,
+                190.0,
+            ],
+            Commission.into() => &[10.0, 5.0, 5.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            Tax.into() => &[0.0; 11],
+            Type.into() => &[
+                "Stock", "Stock", "Stock", "Stock", "Stock", "Stock", "Stock", "Fii", "Fii", "Fii",
+                "Stock",
+            ],
+            Currency.into() => &[
+                "BRL", "BRL", "BRL", "BRL", "BRL", "BRL", "BRL", "BRL", "BRL", "BRL", "USD",
+            ],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Date.into()).alias(Date.into()))
+        .collect()
+        .unwrap()
+    }
+
+    #[test]
+    fn monthly_tax_by_category() {
+        let capital_gains = CapitalGains::try_from_orders(orders()).unwrap();
+
+        // February is exempt, the day trade loss of March offsets the day trade gain of April.
+        let expected = df! (
+            Month.into() => &["2023-02", "2023-03", "2023-03", "2023-04", "2023-05"],
+            TaxCategory.into() => &["SwingTrade", "SwingTrade", "DayTrade", "DayTrade", "Fii"],
+            Proceeds.into() => &[15000.0, 25000.0, 5800.0, 5500.0, 840.0],
+            Gain.into() => &[4990.0, 14990.0, -200.0, 500.0, 40.0],
+            ExemptGain.into() => &[4990.0, 0.0, 0.0, 0.0, 0.0],
+            LossCarryForward.into() => &[0.0, 0.0, 200.0, 0.0, 0.0],
+            TaxableGain.into() => &[0.0, 14990.0, 0.0, 300.0, 40.0],
+            Tax.into() => &[0.0, 2248.5, 0.0, 60.0, 8.0],
+        )
+        .unwrap();
+        assert_eq!(expected, capital_gains.by_month().unwrap());
+
+        // The tax of May is below the minimum DARF.
+        let expected = df! (
+            Month.into() => &["2023-02", "2023-03", "2023-04", "2023-05"],
+            Tax.into() => &[0.0, 2248.5, 60.0, 8.0],
+            Darf.into() => &[0.0, 2248.5, 60.0, 0.0],
+        )
+        .unwrap();
+        assert_eq!(expected, capital_gains.darf().unwrap());
+    }
+
+    #[test]
+    fn bens_e_direitos_at_average_cost() {
+        let result = CapitalGains::try_from_orders(orders())
+            .unwrap()
+            .bens_e_direitos()
+            .unwrap();
+
+        let expected = df! (
+            Year.into() => &[2023],
+            Ticker.into() => &["HGLG11"],
+            Qty.into() => &[10.0],
+            PortfolioCost.into() => &[800.0],
+            AveragePrice.into() => &[80.0],
+        )
+        .unwrap();
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn corporate_actions_carry_the_position() {
+        let sell = df! (
+            Date.into() => &["2023-07-03"],
+            Action.into() => &[Sell.as_str()],
+            Ticker.into() => &["META"],
+            NewTicker.into() => &[None::<&str>],
+            Qty.into() => &[20.0],
+            Price.into() => &[280.0],
+            Amount.into() => &[5600.0],
+            Country.into() => &["Usa"],
+            Currency.into() => &["USD"],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Date.into()).alias(Date.into()));
+        let orders = concat(
+            [utils::test::generate_corporate_action_orders().lazy(), sell],
+            Default::default(),
+        )
+        .unwrap()
+        .with_columns([
+            lit(0.0).alias(Tax.into()),
+            lit(0.0).alias(Commission.into()),
+            lit(schema::Type::Stock.as_str()).alias(Type.into()),
+            lit(schema::Currency::BRL.as_str()).alias(Currency.into()),
+        ]);
+        let capital_gains = CapitalGains::try_from_orders(orders).unwrap();
+
+        let expected = df! (
+            Year.into() => &[2023, 2022],
+            Ticker.into() => &["ACQ", "META"],
+            Qty.into() => &[12.0, 20.0],
+            PortfolioCost.into() => &[552.0, 4600.0],
+            AveragePrice.into() => &[46.0, 230.0],
+        )
+        .unwrap();
+        assert_eq!(expected, capital_gains.bens_e_direitos().unwrap());
+
+        let expected = df! (
+            Date.into() => &["2023-03-01", "2023-06-01", "2023-07-03"],
+            Ticker.into() => &["SPIN", "SPIN", "META"],
+            Type.into() => &["Stock", "Stock", "Stock"],
+            TaxCategory.into() => &["SwingTrade", "SwingTrade", "SwingTrade"],
+            Qty.into() => &[2.0, 8.0, 20.0],
+            Proceeds.into() => &[200.0, 400.0, 5600.0],
+            AllowableCost.into() => &[184.0, 184.0, 3680.0],
+            Gain.into() => &[16.0, 216.0, 1920.0],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Date.into()).alias(Date.into()))
+        .collect()
+        .unwrap();
+        let result = capital_gains
+            .collect()
+            .unwrap()
+            .lazy()
+            .with_column(dtype_col(&DataType::Float64).round(2))
+            .collect()
+            .unwrap();
+        assert_eq!(expected, result);
+    }
+}
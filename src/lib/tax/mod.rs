@@ -0,0 +1,3 @@
+pub mod brazil;
+pub mod uk;
+pub mod us;
@@ -0,0 +1,597 @@
+use crate::currency;
+use crate::perpetual_inventory::carried_fraction;
+use crate::schema::{Action, Column, Currency};
+use crate::scraper::IScraper;
+use crate::utils;
+use anyhow::{ensure, Result};
+use chrono::{Datelike, NaiveDate};
+use polars::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+/// Acquisitions up to 30 days after a disposal are matched before the Section 104 pool.
+const BED_AND_BREAKFAST_DAYS: i64 = 30;
+const TOLERANCE: f64 = 1e-6;
+
+#[derive(Debug, Clone, Copy, strum::IntoStaticStr)]
+#[strum(serialize_all = "PascalCase")]
+enum Rule {
+    SameDay,
+    BedAndBreakfast,
+    Section104,
+}
+
+/// All acquisitions and disposals of a ticker in a day, HMRC treats them as single transactions.
+/// The quantities are in shares of after the latest split, `factor` shares per share of the day.
+#[derive(Debug)]
+struct Day {
+    date: NaiveDate,
+    factor: f64,
+    bought: f64,
+    cost: f64,
+    sold: f64,
+    proceeds: f64,
+    sale_cost: f64,
+}
+
+impl Day {
+    fn unit_cost(&self) -> f64 {
+        self.cost / self.bought
+    }
+
+    fn new(date: NaiveDate, factor: f64) -> Self {
+        Self {
+            date,
+            factor,
+            bought: 0.0,
+            cost: 0.0,
+            sold: 0.0,
+            proceeds: 0.0,
+            sale_cost: 0.0,
+        }
+    }
+}
+
+/// A rename, merger or spin-off, the pool of `ticker` is carried to `new_ticker`.
+struct Carry<'a> {
+    date: NaiveDate,
+    ticker: &'a str,
+    new_ticker: Option<&'a str>,
+    action: Action,
+    /// Shares of `ticker` of after the latest split per share of the day.
+    factor: f64,
+    /// Shares of `new_ticker` per share of `ticker`, both of after their latest split.
+    ratio: f64,
+    /// The fraction of the cost carried to `new_ticker`.
+    fraction: f64,
+    amount: f64,
+}
+
+/// The trades of a day of a ticker or a corporate action, walked in date order.
+#[derive(Clone, Copy)]
+enum Event<'a> {
+    Day(&'a str, usize),
+    Carry(&'a Carry<'a>),
+}
+
+#[derive(Default)]
+struct Disposals {
+    date: Vec<NaiveDate>,
+    ticker: Vec<String>,
+    rule: Vec<&'static str>,
+    qty: Vec<f64>,
+    proceeds: Vec<f64>,
+    cost: Vec<f64>,
+}
+
+impl Disposals {
+    /// Records `qty` shares of the disposal `day` matched with `cost` of acquisition, the
+    /// reported quantity is in shares of the day.
+    fn push(&mut self, ticker: &str, day: &Day, rule: Rule, qty: f64, cost: f64) {
+        let share = qty / day.sold;
+        self.date.push(day.date);
+        self.ticker.push(ticker.to_owned());
+        self.rule.push(rule.into());
+        self.qty.push(qty / day.factor);
+        self.proceeds.push(day.proceeds * share);
+        self.cost.push(cost + day.sale_cost * share);
+    }
+}
+
+/// The UK tax year runs from 6 April to 5 April, i.e. "2023/24".
+pub fn tax_year(date: NaiveDate) -> String {
+    let start = if (date.month(), date.day()) >= (4, 6) {
+        date.year()
+    } else {
+        date.year() - 1
+    };
+    format!("{}/{:02}", start, (start + 1) % 100)
+}
+
+pub fn annual_exempt_amount(tax_year: &str) -> f64 {
+    let start: i32 = tax_year[..4].parse().expect("Invalid tax year");
+    match start {
+        ..=2010 => 10_100.0,
+        2011 | 2012 => 10_600.0,
+        2013 => 10_900.0,
+        2014 => 11_000.0,
+        2015 | 2016 => 11_100.0,
+        2017 => 11_300.0,
+        2018 => 11_700.0,
+        2019 => 12_000.0,
+        2020..=2022 => 12_300.0,
+        2023 => 6_000.0,
+        _ => 3_000.0,
+    }
+}
+
+pub struct CapitalGains {
+    data: LazyFrame,
+}
+
+impl CapitalGains {
+    /// Matches the disposals following the HMRC share identification rules, every leg is
+    /// converted to GBP with the exchange rate of its own date.
+    pub fn try_from_orders(orders: impl IntoLazy, scraper: &mut impl IScraper) -> Result<Self> {
+        let orders: LazyFrame = orders.lazy();
+        let trades = orders
+            .clone()
+            .filter(
+                utils::polars::filter::buy_or_sell().or(utils::polars::filter::corporate_action()),
+            )
+            .with_columns([
+                col(Column::Tax.as_str()).fill_null(lit(0.0)),
+                col(Column::Commission.as_str()).fill_null(lit(0.0)),
+            ]);
+        ensure!(
+            trades
+                .clone()
+                .filter(utils::polars::filter::disposal())
+                .collect()?
+                .shape()
+                .0
+                > 0,
+            "CapitalGains: Orders must contain selling operations"
+        );
+
+        let trades = currency::normalize_historical(
+            trades,
+            Column::Currency.as_str(),
+            &[
+                col(Column::Amount.as_str()),
+                col(Column::Tax.as_str()),
+                col(Column::Commission.as_str()),
+            ],
+            Currency::GBP,
+            scraper,
+        )?
+        .collect()?;
+
+        let splits = orders.filter(utils::polars::filter::split()).collect()?;
+        let mut ratios: HashMap<&str, Vec<(NaiveDate, f64)>> = HashMap::new();
+        for ((ticker, date), ratio) in utils::polars::column_str(&splits, Column::Ticker.as_str())?
+            .into_iter()
+            .zip(utils::polars::column_date(&splits, Column::Date.as_str())?)
+            .zip(utils::polars::column_f64(&splits, Column::Qty.as_str())?)
+        {
+            ratios.entry(ticker).or_default().push((date, ratio));
+        }
+        // Express every quantity in shares of after the latest split.
+        let factor = |ticker: &str, date: NaiveDate| -> f64 {
+            ratios
+                .get(ticker)
+                .map(|x| {
+                    x.iter()
+                        .filter(|(split, _)| *split > date)
+                        .map(|(_, ratio)| ratio)
+                        .product()
+                })
+                .unwrap_or(1.0)
+        };
+
+        let tickers = utils::polars::column_str(&trades, Column::Ticker.as_str())?;
+        let new_tickers = utils::polars::column_opt_str(&trades, Column::NewTicker.as_str())?;
+        let dates = utils::polars::column_date(&trades, Column::Date.as_str())?;
+        let actions = utils::polars::column_str(&trades, Column::Action.as_str())?;
+        let qty = utils::polars::column_f64(&trades, Column::Qty.as_str())?;
+        let price = utils::polars::column_opt_f64(&trades, Column::Price.as_str())?;
+        let amount = utils::polars::column_f64(&trades, Column::Amount.as_str())?;
+        let tax = utils::polars::column_f64(&trades, Column::Tax.as_str())?;
+        let commission = utils::polars::column_f64(&trades, Column::Commission.as_str())?;
+
+        let mut days: BTreeMap<&str, BTreeMap<NaiveDate, Day>> = BTreeMap::new();
+        let mut carries = Vec::new();
+        for i in 0..trades.height() {
+            let (ticker, date) = (tickers[i], dates[i]);
+            let action = Action::from_str(actions[i])?;
+            if matches!(action, Action::Rename | Action::Merger | Action::SpinOff) {
+                let new_factor = new_tickers[i].map_or(1.0, |new_ticker| factor(new_ticker, date));
+                carries.push(Carry {
+                    date,
+                    ticker,
+                    new_ticker: new_tickers[i],
+                    action,
+                    factor: factor(ticker, date),
+                    ratio: qty[i] * new_factor / factor(ticker, date),
+                    fraction: carried_fraction(action, price[i].unwrap_or_default()),
+                    amount: amount[i],
+                });
+                continue;
+            }
+
+            let factor = factor(ticker, date);
+            let day = days
+                .entry(ticker)
+                .or_default()
+                .entry(date)
+                .or_insert_with(|| Day::new(date, factor));
+            match action {
+                Action::Buy => {
+                    day.bought += qty[i] * factor;
+                    day.cost += amount[i] + tax[i] + commission[i];
+                }
+                Action::Sell | Action::CashInLieu => {
+                    day.sold += qty[i] * factor;
+                    day.proceeds += amount[i];
+                    day.sale_cost += tax[i] + commission[i];
+                }
+                _ => unreachable!("Only buy, sell and corporate actions are filtered"),
+            }
+        }
+
+        let days: BTreeMap<&str, Vec<Day>> = days
+            .into_iter()
+            .map(|(ticker, days)| (ticker, days.into_values().collect()))
+            .collect();
+        let mut disposals = Disposals::default();
+        let mut unmatched = HashMap::new();
+        for (ticker, days) in &days {
+            unmatched.insert(*ticker, Self::match_identical(ticker, days, &mut disposals));
+        }
+        Self::match_pools(&days, unmatched, &carries, &mut disposals)?;
+
+        let data = df!(
+            Column::Date.into() => disposals.date,
+            Column::Ticker.into() => disposals.ticker,
+            Column::MatchingRule.into() => disposals.rule,
+            Column::Qty.into() => disposals.qty,
+            Column::Proceeds.into() => disposals.proceeds,
+            Column::AllowableCost.into() => disposals.cost,
+        )?
+        .lazy()
+        .with_column(
+            (col(Column::Proceeds.as_str()) - col(Column::AllowableCost.as_str()))
+                .alias(Column::Gain.as_str()),
+        );
+
+        Ok(Self { data })
+    }
+
+    /// Matches each disposal with the same day acquisitions and then with the acquisitions of
+    /// the following 30 days, returns the shares bought and sold left of each day.
+    fn match_identical(
+        ticker: &str,
+        days: &[Day],
+        disposals: &mut Disposals,
+    ) -> (Vec<f64>, Vec<f64>) {
+        let mut bought: Vec<_> = days.iter().map(|x| x.bought).collect();
+        let mut sold: Vec<_> = days.iter().map(|x| x.sold).collect();
+
+        for (i, day) in days.iter().enumerate() {
+            let qty = sold[i].min(bought[i]);
+            if qty > TOLERANCE {
+                disposals.push(ticker, day, Rule::SameDay, qty, qty * day.unit_cost());
+                sold[i] -= qty;
+                bought[i] -= qty;
+            }
+        }
+
+        for (i, day) in days.iter().enumerate() {
+            let limit = day.date + chrono::Duration::days(BED_AND_BREAKFAST_DAYS);
+            for (j, acquisition) in days
+                .iter()
+                .enumerate()
+                .skip(i + 1)
+                .take_while(|(_, x)| x.date <= limit)
+            {
+                let qty = sold[i].min(bought[j]);
+                if qty > TOLERANCE {
+                    let cost = qty * acquisition.unit_cost();
+                    disposals.push(ticker, day, Rule::BedAndBreakfast, qty, cost);
+                    sold[i] -= qty;
+                    bought[j] -= qty;
+                }
+            }
+        }
+        (bought, sold)
+    }
+
+    /// Matches the rest of the disposals with the Section 104 pool of their ticker. The tickers
+    /// are walked together in date order, so the renames, mergers and spin-offs carry the pool
+    /// to the new ticker after the trades of their date.
+    fn match_pools(
+        days: &BTreeMap<&str, Vec<Day>>,
+        unmatched: HashMap<&str, (Vec<f64>, Vec<f64>)>,
+        carries: &[Carry],
+        disposals: &mut Disposals,
+    ) -> Result<()> {
+        let mut events: Vec<(NaiveDate, Event)> = days
+            .iter()
+            .flat_map(|(ticker, days)| {
+                days.iter()
+                    .enumerate()
+                    .map(|(i, day)| (day.date, Event::Day(ticker, i)))
+            })
+            .chain(
+                carries
+                    .iter()
+                    .map(|carry| (carry.date, Event::Carry(carry))),
+            )
+            .collect();
+        events.sort_by_key(|(date, event)| (*date, matches!(event, Event::Carry(_))));
+
+        let mut pools: HashMap<&str, (f64, f64)> = HashMap::new();
+        for (_, event) in events {
+            if let Event::Day(ticker, i) = event {
+                let day = &days[ticker][i];
+                let (bought, sold) = (unmatched[ticker].0[i], unmatched[ticker].1[i]);
+                let (pool_qty, pool_cost) = pools.entry(ticker).or_default();
+                if bought > TOLERANCE {
+                    *pool_qty += bought;
+                    *pool_cost += bought * day.unit_cost();
+                }
+                if sold > TOLERANCE {
+                    ensure!(
+                        sold <= *pool_qty + TOLERANCE,
+                        "{ticker}: Disposal of {} shares on {} exceeds the pool of {pool_qty}",
+                        sold,
+                        day.date
+                    );
+                    let qty = sold.min(*pool_qty);
+                    let cost = *pool_cost * qty / *pool_qty;
+                    disposals.push(ticker, day, Rule::Section104, qty, cost);
+                    *pool_qty -= qty;
+                    *pool_cost -= cost;
+                }
+            }
+
+            if let Event::Carry(carry) = event {
+                let (qty, cost) = pools.remove(carry.ticker).unwrap_or_default();
+                let carried = cost * carry.fraction;
+                match carry.action {
+                    Action::SpinOff => {
+                        pools.insert(carry.ticker, (qty, cost - carried));
+                    }
+                    // The cash of a merger disposes of the cost not carried.
+                    Action::Merger if carry.amount > 0.0 && qty > TOLERANCE => {
+                        let day = Day {
+                            sold: qty,
+                            proceeds: carry.amount,
+                            ..Day::new(carry.date, carry.factor)
+                        };
+                        disposals.push(carry.ticker, &day, Rule::Section104, qty, cost - carried);
+                    }
+                    _ => (),
+                }
+                if let (Some(new_ticker), true) = (carry.new_ticker, qty > TOLERANCE) {
+                    let (pool_qty, pool_cost) = pools.entry(new_ticker).or_default();
+                    *pool_qty += qty * carry.ratio;
+                    *pool_cost += carried;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The matched disposals, one row per identification rule applied.
+    pub fn collect(self) -> Result<DataFrame> {
+        Ok(self
+            .data
+            .sort(
+                [Column::Date.as_str(), Column::Ticker.as_str()],
+                SortMultipleOptions::default().with_maintain_order(true),
+            )
+            .collect()?)
+    }
+
+    /// Gains and losses by tax year, after the annual exempt amount.
+    pub fn by_tax_year(&self) -> Result<DataFrame> {
+        let data = self
+            .data
+            .clone()
+            .with_column(
+                col(Column::Date.as_str())
+                    .map(
+                        |series| {
+                            Ok(Some(
+                                series
+                                    .date()?
+                                    .as_date_iter()
+                                    .map(|date| date.map(tax_year))
+                                    .collect::<StringChunked>()
+                                    .into_series(),
+                            ))
+                        },
+                        GetOutput::from_type(DataType::String),
+                    )
+                    .alias(Column::TaxYear.as_str()),
+            )
+            .collect()?;
+
+        let exempt_amount: HashMap<_, _> =
+            utils::polars::column_str(&data, Column::TaxYear.as_str())?
+                .into_iter()
+                .map(|year| (year.to_owned(), annual_exempt_amount(year)))
+                .collect();
+
+        let gain = col(Column::Gain.as_str());
+        Ok(data
+            .lazy()
+            .group_by([col(Column::TaxYear.as_str())])
+            .agg([
+                col(Column::Proceeds.as_str()).sum(),
+                col(Column::AllowableCost.as_str()).sum(),
+                gain.clone().filter(gain.clone().gt(lit(0))).sum(),
+                (gain.clone().filter(gain.lt(lit(0))).sum() * lit(-1)).alias(Column::Loss.as_str()),
+            ])
+            .with_columns([
+                (col(Column::Gain.as_str()) - col(Column::Loss.as_str()))
+                    .alias(Column::NetGain.as_str()),
+                utils::polars::map_column_str_to_f64(Column::TaxYear.as_str(), exempt_amount)
+                    .alias(Column::AnnualExemptAmount.as_str()),
+            ])
+            .with_column(
+                when(col(Column::NetGain.as_str()).gt(col(Column::AnnualExemptAmount.as_str())))
+                    .then(col(Column::NetGain.as_str()) - col(Column::AnnualExemptAmount.as_str()))
+                    .otherwise(lit(0.0))
+                    .alias(Column::TaxableGain.as_str()),
+            )
+            .sort([Column::TaxYear.as_str()], Default::default())
+            .with_column(dtype_col(&DataType::Float64).round(2))
+            .collect()?)
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+    use super::*;
+    use crate::schema::Action::{Buy, Sell, Split};
+    use crate::schema::Column::*;
+
+    fn orders() -> DataFrame {
+        let actions = [Buy, Buy, Sell, Buy, Buy, Buy, Sell, Split, Sell];
+        df! (
+            Date.into() => &["2023-01-10", "2023-05-01", "2023-06-15", "2023-06-15", "2023-07-01", "2023-03-01", "2023-09-01", "2024-01-10", "2024-05-01"],
+            Action.into() => actions.map(|x| x.as_str()),
+            Ticker.into() => &["VOD", "VOD", "VOD", "VOD", "VOD", "GOOGL", "GOOGL", "VOD", "VOD"],
+            Qty.into() => &[1000.0, 500.0, 700.0, 100.0, 200.0, 10.0, 10.0, 2.0, 600.0],
+            Amount.into() => &[3990.0, 2495.0, 4210.0, 515.0, 900.0, 1000.0, 15000.0, 0.0, 1010.0],
+            Commission.into() => &[10.0, 5.0, 10.0, 5.0, 0.0, 0.0, 0.0, 0.0, 10.0],
+            Tax.into() => &[0.0; 9],
+            Currency.into() => &["GBP", "GBP", "GBP", "GBP", "GBP", "USD", "USD", "USD", "GBP"],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Date.into()).alias(Date.into()))
+        .collect()
+        .unwrap()
+    }
+
+    fn scraper() -> utils::test::mock::Scraper {
+        utils::test::mock::Scraper::new()
+            .with_history("USD/GBP", &[("2023-03-01", 0.8), ("2023-09-01", 0.75)])
+    }
+
+    #[test]
+    fn tax_year_boundaries() {
+        assert_eq!(tax_year("2024-04-05".parse().unwrap()), "2023/24");
+        assert_eq!(tax_year("2024-04-06".parse().unwrap()), "2024/25");
+        assert_eq!(tax_year("1999-12-31".parse().unwrap()), "1999/00");
+    }
+
+    #[test]
+    fn disposals_matching_rules() {
+        let result = CapitalGains::try_from_orders(orders(), &mut scraper())
+            .unwrap()
+            .collect()
+            .unwrap()
+            .lazy()
+            .with_column(dtype_col(&DataType::Float64).round(2))
+            .collect()
+            .unwrap();
+
+        let expected = df! (
+            Date.into() => &["2023-06-15", "2023-06-15", "2023-06-15", "2023-09-01", "2024-05-01"],
+            Ticker.into() => &["VOD", "VOD", "VOD", "GOOGL", "VOD"],
+            MatchingRule.into() => &["SameDay", "BedAndBreakfast", "Section104", "Section104", "Section104"],
+            Qty.into() => &[100.0, 200.0, 400.0, 10.0, 600.0],
+            Proceeds.into() => &[601.43, 1202.86, 2405.71, 11250.0, 1010.0],
+            AllowableCost.into() => &[521.43, 902.86, 1739.05, 800.0, 1310.0],
+            Gain.into() => &[80.0, 300.0, 666.67, 10450.0, -300.0],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Date.into()).alias(Date.into()))
+        .collect()
+        .unwrap();
+
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn report_by_tax_year() {
+        let result = CapitalGains::try_from_orders(orders(), &mut scraper())
+            .unwrap()
+            .by_tax_year()
+            .unwrap();
+
+        let expected = df! (
+            TaxYear.into() => &["2023/24", "2024/25"],
+            Proceeds.into() => &[15460.0, 1010.0],
+            AllowableCost.into() => &[3963.33, 1310.0],
+            Gain.into() => &[11496.67, 0.0],
+            Loss.into() => &[0.0, 300.0],
+            NetGain.into() => &[11496.67, -300.0],
+            AnnualExemptAmount.into() => &[6000.0, 3000.0],
+            TaxableGain.into() => &[5496.67, 0.0],
+        )
+        .unwrap();
+
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn corporate_actions_carry_the_pool() {
+        let sell = df! (
+            Date.into() => &["2023-07-03"],
+            Action.into() => &[Sell.as_str()],
+            Ticker.into() => &["META"],
+            NewTicker.into() => &[None::<&str>],
+            Qty.into() => &[20.0],
+            Price.into() => &[280.0],
+            Amount.into() => &[5600.0],
+            Country.into() => &["Usa"],
+            Currency.into() => &["USD"],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Date.into()).alias(Date.into()));
+        let orders = concat(
+            [utils::test::generate_corporate_action_orders().lazy(), sell],
+            Default::default(),
+        )
+        .unwrap()
+        .with_columns([
+            lit(0.0).alias(Tax.into()),
+            lit(0.0).alias(Commission.into()),
+        ]);
+        let mut scraper =
+            utils::test::mock::Scraper::new().with_history("USD/GBP", &[("2022-01-01", 0.8)]);
+
+        let result = CapitalGains::try_from_orders(orders, &mut scraper)
+            .unwrap()
+            .collect()
+            .unwrap()
+            .lazy()
+            .with_column(dtype_col(&DataType::Float64).round(2))
+            .collect()
+            .unwrap();
+
+        let expected = df! (
+            Date.into() => &["2023-03-01", "2023-06-01", "2023-07-03"],
+            Ticker.into() => &["SPIN", "SPIN", "META"],
+            MatchingRule.into() => &["Section104", "Section104", "Section104"],
+            Qty.into() => &[2.0, 8.0, 20.0],
+            Proceeds.into() => &[160.0, 320.0, 4480.0],
+            AllowableCost.into() => &[147.2, 147.2, 2944.0],
+            Gain.into() => &[12.8, 172.8, 1536.0],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Date.into()).alias(Date.into()))
+        .collect()
+        .unwrap();
+
+        assert_eq!(expected, result);
+    }
+}
@@ -0,0 +1,277 @@
+use crate::schema::Column;
+use crate::utils;
+use anyhow::{ensure, Result};
+use chrono::NaiveDate;
+use polars::prelude::*;
+
+/// Shares bought up to 30 days before or after a loss replace the shares sold.
+const WASH_SALE_DAYS: i64 = 30;
+const TOLERANCE: f64 = 1e-9;
+
+#[derive(Debug)]
+struct Buy {
+    date: NaiveDate,
+    ticker: String,
+    price: f64,
+    /// Shares still held and not used yet to replace a loss.
+    available: f64,
+}
+
+#[derive(Default)]
+struct Flagged {
+    date: Vec<NaiveDate>,
+    ticker: Vec<String>,
+    acquired: Vec<Option<NaiveDate>>,
+    qty: Vec<f64>,
+    profit: Vec<f64>,
+    disallowed: Vec<f64>,
+    replacement: Vec<NaiveDate>,
+    adjusted_price: Vec<f64>,
+}
+
+/// Disallows the losses of lots replaced by substantially identical shares, the disallowed
+/// loss is added to the cost of the replacement lot as reported in the 1099-B.
+pub struct WashSales {
+    profit: DataFrame,
+    flagged: DataFrame,
+}
+
+impl WashSales {
+    /// `profit` are the sold lots of `liquidated::Profit` with a lot cost basis, in the
+    /// currency of the orders.
+    pub fn try_from_profit(profit: &DataFrame, orders: impl IntoLazy) -> Result<Self> {
+        ensure!(
+            profit
+                .get_column_names()
+                .contains(&Column::AcquisitionDate.as_str()),
+            "Wash sales need the sold lots, use a lot cost basis"
+        );
+
+        let buys = orders
+            .lazy()
+            .filter(utils::polars::filter::buy())
+            .sort(
+                [Column::Date.as_str()],
+                SortMultipleOptions::default().with_maintain_order(true),
+            )
+            .collect()?;
+        let mut buys: Vec<Buy> = utils::polars::column_date(&buys, Column::Date.as_str())?
+            .into_iter()
+            .zip(utils::polars::column_str(&buys, Column::Ticker.as_str())?)
+            .zip(utils::polars::column_f64(&buys, Column::Price.as_str())?)
+            .zip(utils::polars::column_f64(&buys, Column::Qty.as_str())?)
+            .map(|(((date, ticker), price), qty)| Buy {
+                date,
+                ticker: ticker.to_owned(),
+                price,
+                available: qty,
+            })
+            .collect();
+
+        let profit = profit.sort(
+            [Column::Date.as_str()],
+            SortMultipleOptions::default().with_maintain_order(true),
+        )?;
+        let dates = utils::polars::column_date(&profit, Column::Date.as_str())?;
+        let tickers = utils::polars::column_str(&profit, Column::Ticker.as_str())?;
+        let qty = utils::polars::column_f64(&profit, Column::Qty.as_str())?;
+        let acquired: Vec<Option<NaiveDate>> = profit
+            .column(Column::AcquisitionDate.as_str())?
+            .date()?
+            .as_date_iter()
+            .collect();
+        let mut profits = utils::polars::column_f64(&profit, Column::Profit.as_str())?;
+        // The disallowed loss per share still to add to the cost of later sells of each lot.
+        let mut adjustments: Vec<(String, NaiveDate, f64, f64)> = Vec::new();
+
+        let mut flagged = Flagged::default();
+        let mut sold = 0;
+        for i in 0..profit.height() {
+            // The shares sold up to the date of the loss don't replace it.
+            while sold < profit.height() && dates[sold] <= dates[i] {
+                let mut left = qty[sold];
+                for buy in buys
+                    .iter_mut()
+                    .filter(|buy| buy.ticker == tickers[sold] && Some(buy.date) == acquired[sold])
+                {
+                    let taken = left.min(buy.available);
+                    buy.available -= taken;
+                    left -= taken;
+                }
+                sold += 1;
+            }
+
+            // Sells of a replacement lot carry its adjusted cost.
+            let mut left = qty[i];
+            for (ticker, date, per_share, qty) in adjustments.iter_mut() {
+                if *ticker == tickers[i] && Some(*date) == acquired[i] && left > TOLERANCE {
+                    let adjusted = left.min(*qty);
+                    profits[i] -= *per_share * adjusted;
+                    *qty -= adjusted;
+                    left -= adjusted;
+                }
+            }
+
+            if profits[i] >= 0.0 {
+                continue;
+            }
+            let loss_per_share = -profits[i] / qty[i];
+            let mut washed = qty[i];
+            for buy in buys.iter_mut().filter(|buy| {
+                buy.ticker == tickers[i]
+                    && (buy.date - dates[i]).num_days().abs() <= WASH_SALE_DAYS
+                    // The rest of the lot sold doesn't replace it.
+                    && Some(buy.date) != acquired[i]
+            }) {
+                let replaced = washed.min(buy.available);
+                if replaced <= TOLERANCE {
+                    continue;
+                }
+                buy.available -= replaced;
+                washed -= replaced;
+
+                let disallowed = loss_per_share * replaced;
+                profits[i] += disallowed;
+                adjustments.push((buy.ticker.clone(), buy.date, loss_per_share, replaced));
+
+                flagged.date.push(dates[i]);
+                flagged.ticker.push(tickers[i].to_owned());
+                flagged.acquired.push(acquired[i]);
+                flagged.qty.push(replaced);
+                flagged.profit.push(-loss_per_share * replaced);
+                flagged.disallowed.push(disallowed);
+                flagged.replacement.push(buy.date);
+                flagged.adjusted_price.push(buy.price + loss_per_share);
+            }
+        }
+
+        let mut profit = profit;
+        profit.with_column(Series::new(Column::Profit.as_str(), profits))?;
+        let flagged = df!(
+            Column::Date.as_str() => flagged.date,
+            Column::Ticker.as_str() => flagged.ticker,
+            Column::AcquisitionDate.as_str() => flagged.acquired,
+            Column::Qty.as_str() => flagged.qty,
+            Column::Profit.as_str() => flagged.profit,
+            Column::DisallowedLoss.as_str() => flagged.disallowed,
+            Column::ReplacementDate.as_str() => flagged.replacement,
+            Column::AdjustedPrice.as_str() => flagged.adjusted_price,
+        )?;
+
+        Ok(Self { profit, flagged })
+    }
+
+    /// The sold lots with the disallowed losses and the adjusted cost of the replacements.
+    pub fn adjusted_profit(&self) -> DataFrame {
+        self.profit.clone()
+    }
+
+    /// The replaced sells, with the disallowed loss and the adjusted price of the replacement.
+    pub fn collect(self) -> Result<DataFrame> {
+        Ok(self.flagged)
+    }
+}
+
+#[cfg(test)]
+mod unittest {
+    use super::*;
+    use crate::liquidated::Profit;
+    use crate::lots::{CostBasis, LotSelection};
+
+    #[test]
+    fn wash_sales_success() {
+        let orders = df!(
+            Column::Date.into() => &[
+                "2023-01-03", "2023-06-01", "2023-06-15", "2023-09-01",
+                "2023-02-01", "2023-03-01", "2023-03-10",
+            ],
+            Column::Action.into() => &["Buy", "Sell", "Buy", "Sell", "Buy", "Sell", "Buy"],
+            Column::Ticker.into() => &["AAPL", "AAPL", "AAPL", "AAPL", "MSFT", "MSFT", "MSFT"],
+            Column::Qty.into() => &[10.0, 10.0, 10.0, 10.0, 10.0, 10.0, 4.0],
+            Column::Price.into() => &[100.0, 80.0, 85.0, 90.0, 50.0, 40.0, 45.0],
+            Column::Amount.into() => &[1000.0, 800.0, 850.0, 900.0, 500.0, 400.0, 180.0],
+            Column::Currency.into() => &["USD"; 7],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Column::Date.into()))
+        .collect()
+        .unwrap();
+
+        let profit = Profit::from_orders_with(orders.clone(), CostBasis::Fifo, LotSelection::new())
+            .unwrap()
+            .collect()
+            .unwrap();
+        let wash_sales = WashSales::try_from_profit(&profit, orders).unwrap();
+
+        // The AAPL loss is fully replaced and moves to the sell of the replacement, which is a
+        // loss without replacement. Only 4 of the 10 MSFT shares are replaced.
+        let adjusted = wash_sales
+            .adjusted_profit()
+            .lazy()
+            .select([
+                col(Column::Ticker.as_str()),
+                col(Column::Profit.as_str()).round(4),
+            ])
+            .collect()
+            .unwrap();
+        let expected = df!(
+            Column::Ticker.into() => &["MSFT", "AAPL", "AAPL"],
+            Column::Profit.into() => &[-60.0, 0.0, -150.0],
+        )
+        .unwrap();
+        assert_eq!(expected, adjusted);
+
+        let flagged = wash_sales
+            .collect()
+            .unwrap()
+            .lazy()
+            .select([
+                col(Column::Date.as_str()).cast(DataType::String),
+                col(Column::Ticker.as_str()),
+                col(Column::Qty.as_str()),
+                col(Column::DisallowedLoss.as_str()).round(4),
+                col(Column::ReplacementDate.as_str()).cast(DataType::String),
+                col(Column::AdjustedPrice.as_str()).round(4),
+            ])
+            .collect()
+            .unwrap();
+        let expected = df!(
+            Column::Date.into() => &["2023-03-01", "2023-06-01"],
+            Column::Ticker.into() => &["MSFT", "AAPL"],
+            Column::Qty.into() => &[4.0, 10.0],
+            Column::DisallowedLoss.into() => &[40.0, 200.0],
+            Column::ReplacementDate.into() => &["2023-03-10", "2023-06-15"],
+            Column::AdjustedPrice.into() => &[55.0, 105.0],
+        )
+        .unwrap();
+        assert_eq!(expected, flagged);
+    }
+
+    #[test]
+    fn wash_sales_ignore_the_shares_sold() {
+        let orders = df!(
+            Column::Date.into() => &["2023-01-01", "2023-01-15", "2023-01-20"],
+            Column::Action.into() => &["Buy", "Buy", "Sell"],
+            Column::Ticker.into() => &["AAPL"; 3],
+            Column::Qty.into() => &[10.0, 10.0, 20.0],
+            Column::Price.into() => &[100.0, 90.0, 80.0],
+            Column::Amount.into() => &[1000.0, 900.0, 1600.0],
+            Column::Currency.into() => &["USD"; 3],
+        )
+        .unwrap()
+        .lazy()
+        .with_column(utils::polars::str_to_date(Column::Date.into()))
+        .collect()
+        .unwrap();
+
+        let profit = Profit::from_orders_with(orders.clone(), CostBasis::Fifo, LotSelection::new())
+            .unwrap()
+            .collect()
+            .unwrap();
+        let wash_sales = WashSales::try_from_profit(&profit, orders).unwrap();
+
+        // Both lots are sold at a loss, no shares are left to replace them.
+        assert_eq!(0, wash_sales.collect().unwrap().height());
+    }
+}
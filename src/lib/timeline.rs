@@ -1,17 +1,19 @@
-use crate::dividends::Dividends;
-use crate::liquidated;
-use crate::portfolio::Portfolio;
+use crate::currency::{self, ExchangeRate};
+use crate::perpetual_inventory::carried_fraction;
 use crate::schema::{self, Action, Column};
-use crate::scraper::{IScraper, ScraperData};
-use crate::summary::Summary;
-use crate::uninvested;
+use crate::scraper::{IScraper, ScraperData, SearchPeriod};
 use crate::utils;
-use anyhow::Result;
+use anyhow::{Context, Result};
+use chrono::NaiveDate;
 use polars::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
 
 pub struct Timeline {
     orders: LazyFrame,
     currency: schema::Currency,
+    historical_rates: bool,
+    benchmark: Option<(String, schema::Country)>,
 }
 
 impl Timeline {
@@ -19,9 +21,34 @@ impl Timeline {
         Timeline {
             orders: orders.lazy(),
             currency,
+            historical_rates: false,
+            benchmark: None,
         }
     }
 
+    /// Convert capital, dividends and profit with the exchange rate of each transaction date.
+    pub fn with_historical_rates(mut self, enabled: bool) -> Self {
+        self.historical_rates = enabled;
+        self
+    }
+
+    /// Compare the portfolio with buying `ticker` on each deposit date.
+    pub fn with_benchmark(mut self, ticker: &str, country: schema::Country) -> Self {
+        self.benchmark = Some((ticker.to_owned(), country));
+        self
+    }
+
+    /// Dividends and the liquidated profit are converted with the rate of their own date or with
+    /// the rate of the last day.
+    fn dividend_rate(&self, date: NaiveDate) -> ExchangeRate {
+        if self.historical_rates {
+            ExchangeRate::Historical
+        } else {
+            ExchangeRate::Present(Some(date))
+        }
+    }
+
+    /// Snapshots of the summary every `interval_days` until `date`, walking the orders once.
     pub fn summary<T: IScraper>(
         self,
         scraper: &mut T,
@@ -35,69 +62,54 @@ impl Timeline {
         } else {
             today
         };
-        let df = self.orders.clone().collect().unwrap();
-        let mut current_date = utils::polars::first_date(&df);
+        let orders = Order::from_frame(
+            &self
+                .orders
+                .clone()
+                .sort(
+                    [Column::Date.as_str()],
+                    SortMultipleOptions::default().with_maintain_order(true),
+                )
+                .collect()?,
+        )?;
+        let Some(mut current_date) = orders.iter().map(|order| order.date).min() else {
+            return Ok(DataFrame::default());
+        };
+        let quotes = Quotes::from_frame(&scraped_data.quotes)?;
 
-        let mut result = LazyFrame::default();
-        loop {
-            let orders = self.orders.clone().filter(
-                col(Column::Action.as_str())
-                    .eq(lit(Action::Split.as_str()))
-                    .or(col(Column::Date.as_str()).lt_eq(lit(current_date))),
-            );
+        let mut currencies: Vec<_> = orders
+            .iter()
+            .map(|order| order.currency.as_str())
+            .chain(quotes.currencies())
+            .collect();
+        currencies.sort();
+        currencies.dedup();
+        let rates = ExchangeRates::load(scraper, self.currency, &currencies, current_date, date)?;
+
+        // Quotes are adjusted by splits, so the quantities are adjusted by the future splits too.
+        let splits: Vec<_> = orders
+            .iter()
+            .filter(|order| order.action == Action::Split)
+            .collect();
 
-            let dividends = if let Ok(dividends) = Dividends::try_from_orders(orders.clone()) {
-                dividends
-                    .normalize_currency(scraper, self.currency, Some(date))?
-                    .by_ticker()?
-            } else {
-                DataFrame::default()
-            };
-
-            let cash = uninvested::Cash::from_orders(orders.clone()).collect()?;
-            if let Ok(portfolio) = Portfolio::try_from_orders(orders.clone(), Some(current_date)) {
-                let portfolio = portfolio
-                    .with_quotes(&scraped_data.quotes)?
-                    .with_average_price()?
-                    .with_uninvested_cash(cash.clone())
-                    .normalize_currency(scraper, self.currency)?
-                    .paper_profit()
-                    .with_dividends(dividends.clone())
-                    .with_profit()
-                    .with_allocation()
-                    .collect()?;
-
-                let profit = if let Ok(profit) = liquidated::Profit::from_orders(orders.clone()) {
-                    profit
-                        .normalize_currency(scraper, self.currency, Some(date))?
-                        .collect()?
-                } else {
-                    DataFrame::default()
-                };
-
-                let summary = Summary::from_portfolio(portfolio)?
-                    .with_dividends(dividends)?
-                    .with_capital_invested(
-                        orders.clone(),
-                        self.currency,
-                        scraper,
-                        Some(current_date),
-                    )?
-                    .with_liquidated_profit(profit)?
-                    .finish();
-
-                result = concat(
-                    [
-                        result,
-                        summary.with_column(
-                            lit(current_date)
-                                .cast(DataType::Date)
-                                .alias(schema::Column::Date.as_str()),
-                        ),
-                    ],
-                    Default::default(),
-                )?;
+        let dividend_rate = self.dividend_rate(date);
+        let mut state = State::default();
+        let mut pending = orders.iter().peekable();
+        let mut snapshots = Snapshots::default();
+        loop {
+            while let Some(order) = pending.next_if(|order| order.date <= current_date) {
+                state.apply(order, &rates, dividend_rate);
             }
+
+            let applied_splits = splits.partition_point(|split| split.date <= current_date);
+            state.snapshot(
+                current_date,
+                &splits[applied_splits..],
+                &quotes,
+                &rates,
+                self.historical_rates,
+                &mut snapshots,
+            );
             if current_date == date {
                 break;
             }
@@ -105,17 +117,564 @@ impl Timeline {
             current_date = current_date.min(date);
         }
 
-        Ok(result
+        let result = snapshots.finish()?.lazy().with_column(
+            (col(Column::PaperProfit.into())
+                + col(Column::Dividends.into())
+                + col(Column::LiquidatedProfit.into()))
+            .alias(Column::NetProfit.into()),
+        );
+        let result = result.select(
+            [
+                Column::PrimaryCapital,
+                Column::PortfolioCost,
+                Column::MarketValue,
+                Column::PaperProfit,
+                Column::Dividends,
+                Column::LiquidatedProfit,
+                Column::NetProfit,
+                Column::UninvestedCash,
+                Column::Date,
+            ]
+            .map(|column| col(column.as_str())),
+        );
+
+        let mut result = result
             .sort([schema::Column::Date.as_str()], Default::default())
+            .collect()?;
+        if result.shape().0 == 0 {
+            return Ok(result);
+        }
+
+        let dates = utils::polars::column_date(&result, Column::Date.as_str())?;
+        let cash_flows = self.cash_flows(scraper, date)?;
+        let flows = Self::flows_by_interval(&dates, &cash_flows);
+
+        let values: Vec<_> = utils::polars::column_f64(&result, Column::MarketValue.as_str())?
+            .into_iter()
+            .zip(utils::polars::column_f64(
+                &result,
+                Column::UninvestedCash.as_str(),
+            )?)
+            .map(|(market_value, cash)| market_value + cash)
+            .collect();
+        result.with_column(Series::new(
+            Column::TimeWeightedReturn.as_str(),
+            Self::time_weighted_return(&values, &flows),
+        ))?;
+
+        if let Some((ticker, country)) = &self.benchmark {
+            let prices = self.benchmark_prices(scraper, ticker, *country, &cash_flows, date)?;
+            let mut units = 0.0;
+            let mut cash_flows = cash_flows.iter().peekable();
+            let values: Vec<_> = dates
+                .iter()
+                .map(|date| {
+                    while let Some((day, amount)) = cash_flows.next_if(|(x, _)| x <= date) {
+                        units += amount / Self::price_at(&prices, day);
+                    }
+                    units * Self::price_at(&prices, date)
+                })
+                .collect();
+            result.with_column(Series::new(
+                Column::BenchmarkReturn.as_str(),
+                Self::time_weighted_return(&values, &flows),
+            ))?;
+            result.with_column(Series::new(Column::BenchmarkValue.as_str(), values))?;
+        }
+
+        Ok(result
+            .lazy()
+            .with_column(dtype_col(&DataType::Float64).round(2))
             .collect()?)
     }
+
+    /// Deposits (positive) and withdraws (negative) up to `date`, converted with the exchange
+    /// rate of their own date.
+    fn cash_flows(
+        &self,
+        scraper: &mut impl IScraper,
+        date: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, f64)>> {
+        let orders = self
+            .orders
+            .clone()
+            .filter(utils::polars::filter::deposit_and_withdraw())
+            .filter(col(Column::Date.as_str()).lt_eq(lit(date)))
+            .with_column(utils::polars::compute::negative_amount_on_withdraw())
+            .collect()?;
+        if orders.shape().0 == 0 {
+            return Ok(Vec::new());
+        }
+
+        let orders = currency::normalize_historical(
+            orders,
+            Column::Currency.as_str(),
+            &[col(Column::Amount.as_str())],
+            self.currency,
+            scraper,
+        )?
+        .sort([Column::Date.as_str()], Default::default())
+        .collect()?;
+
+        Ok(utils::polars::column_date(&orders, Column::Date.as_str())?
+            .into_iter()
+            .zip(utils::polars::column_f64(&orders, Column::Amount.as_str())?)
+            .collect())
+    }
+
+    /// Sums the cash flows since the previous snapshot, the first one takes all the earlier flows.
+    fn flows_by_interval(dates: &[NaiveDate], cash_flows: &[(NaiveDate, f64)]) -> Vec<f64> {
+        let mut previous: Option<NaiveDate> = None;
+        dates
+            .iter()
+            .map(|date| {
+                let flow = cash_flows
+                    .iter()
+                    .filter(|(x, _)| x <= date && previous.is_none_or(|previous| *x > previous))
+                    .map(|(_, amount)| amount)
+                    .sum();
+                previous = Some(*date);
+                flow
+            })
+            .collect()
+    }
+
+    /// Chains the return of each interval in percent. The flows are assumed to happen at the
+    /// start of the interval, so deposits and withdraws don't count as gains or losses.
+    fn time_weighted_return(values: &[f64], flows: &[f64]) -> Vec<f64> {
+        let mut index = 1.0;
+        let mut previous = 0.0;
+        values
+            .iter()
+            .zip(flows)
+            .map(|(value, flow)| {
+                let invested = previous + flow;
+                if invested > 0.0 {
+                    index *= value / invested;
+                }
+                previous = *value;
+                (index - 1.0) * 100.0
+            })
+            .collect()
+    }
+
+    fn benchmark_prices(
+        &self,
+        scraper: &mut impl IScraper,
+        ticker: &str,
+        country: schema::Country,
+        cash_flows: &[(NaiveDate, f64)],
+        date: NaiveDate,
+    ) -> Result<Vec<(NaiveDate, f64)>> {
+        let start = cash_flows.first().map_or(date, |(date, _)| *date);
+        let quotes = scraper
+            .with_ticker(&[ticker.to_owned()], Some(&[country]))
+            .load_blocking(SearchPeriod::new(
+                Some(start - chrono::Duration::days(7)),
+                Some(date),
+                None,
+            ))?
+            .quotes
+            .lazy()
+            .filter(col(Column::Ticker.as_str()).eq(lit(ticker)));
+        anyhow::ensure!(
+            quotes.clone().collect()?.shape().0 > 0,
+            "No quotes for the benchmark {ticker}"
+        );
+
+        let quotes = currency::normalize_historical(
+            quotes,
+            Column::Currency.as_str(),
+            &[col(Column::Price.as_str())],
+            self.currency,
+            scraper,
+        )?
+        .sort([Column::Date.as_str()], Default::default())
+        .collect()?;
+
+        Ok(utils::polars::column_date(&quotes, Column::Date.as_str())?
+            .into_iter()
+            .zip(utils::polars::column_f64(&quotes, Column::Price.as_str())?)
+            .collect())
+    }
+
+    /// The latest price until `date`, or the first one when the benchmark starts later.
+    fn price_at(prices: &[(NaiveDate, f64)], date: &NaiveDate) -> f64 {
+        let index = prices.partition_point(|(x, _)| x <= date);
+        prices[index.saturating_sub(1)].1
+    }
+}
+
+/// A row of the orders, the timeline walks them once in date order.
+struct Order {
+    date: NaiveDate,
+    action: Action,
+    ticker: String,
+    new_ticker: Option<String>,
+    qty: f64,
+    price: f64,
+    amount: f64,
+    currency: String,
+}
+
+impl Order {
+    fn from_frame(orders: &DataFrame) -> Result<Vec<Self>> {
+        let dates = utils::polars::column_date(orders, Column::Date.as_str())?;
+        let actions = utils::polars::column_str(orders, Column::Action.as_str())?;
+        let tickers = utils::polars::column_str(orders, Column::Ticker.as_str())?;
+        let currencies = utils::polars::column_str(orders, Column::Currency.as_str())?;
+        let new_tickers = utils::polars::column_opt_str(orders, Column::NewTicker.as_str())?;
+        let float = |column: Column| -> Result<Vec<f64>> {
+            let series = orders.column(column.as_str())?.cast(&DataType::Float64)?;
+            let values = series.f64()?.into_iter().map(Option::unwrap_or_default);
+            Ok(values.collect())
+        };
+        let (qty, price, amount) = (
+            float(Column::Qty)?,
+            float(Column::Price)?,
+            float(Column::Amount)?,
+        );
+
+        (0..orders.height())
+            .map(|i| {
+                Ok(Order {
+                    date: dates[i],
+                    action: Action::from_str(actions[i])
+                        .with_context(|| format!("Unknown action {}", actions[i]))?,
+                    ticker: tickers[i].to_owned(),
+                    new_ticker: new_tickers[i].map(str::to_owned),
+                    qty: qty[i],
+                    price: price[i],
+                    amount: amount[i],
+                    currency: currencies[i].to_owned(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Prices of each ticker sorted by date.
+struct Quotes(HashMap<String, Vec<(NaiveDate, f64, String)>>);
+
+impl Quotes {
+    fn from_frame(quotes: &DataFrame) -> Result<Self> {
+        let mut series: HashMap<_, Vec<_>> = HashMap::new();
+        if quotes.height() > 0 {
+            let quotes = quotes.sort([Column::Date.as_str()], Default::default())?;
+            let dates = utils::polars::column_date(&quotes, Column::Date.as_str())?;
+            let tickers = utils::polars::column_str(&quotes, Column::Ticker.as_str())?;
+            let prices = utils::polars::column_f64(&quotes, Column::Price.as_str())?;
+            let currencies = utils::polars::column_str(&quotes, Column::Currency.as_str())?;
+            for i in 0..quotes.height() {
+                series.entry(tickers[i].to_owned()).or_default().push((
+                    dates[i],
+                    prices[i],
+                    currencies[i].to_owned(),
+                ));
+            }
+        }
+        Ok(Self(series))
+    }
+
+    fn currencies(&self) -> impl Iterator<Item = &str> {
+        self.0
+            .values()
+            .flatten()
+            .map(|(_, _, currency)| currency.as_str())
+    }
+
+    /// The latest price until `date` and its currency.
+    fn at(&self, ticker: &str, date: NaiveDate) -> Option<(f64, &str)> {
+        let series = self.0.get(ticker)?;
+        let index = series.partition_point(|(x, _, _)| *x <= date);
+        let (_, price, currency) = series.get(index.checked_sub(1)?)?;
+        Some((*price, currency.as_str()))
+    }
+}
+
+/// Exchange rates to the timeline currency, loaded once for the whole period.
+struct ExchangeRates(HashMap<String, Vec<(NaiveDate, f64)>>);
+
+impl ExchangeRates {
+    fn load(
+        scraper: &mut impl IScraper,
+        currency: schema::Currency,
+        currencies: &[&str],
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<Self> {
+        let mut requested = false;
+        for from in currencies {
+            let from: schema::Currency = from
+                .parse()
+                .with_context(|| format!("Can't parse {from}"))?;
+            if from != currency {
+                scraper.with_currency(from, currency);
+                requested = true;
+            }
+        }
+
+        let mut series: HashMap<_, Vec<_>> = HashMap::new();
+        if requested {
+            let quotes = scraper
+                .load_blocking(SearchPeriod::new(
+                    Some(start - chrono::Duration::days(7)),
+                    Some(end + chrono::Duration::days(1)),
+                    None,
+                ))?
+                .quotes
+                .sort([Column::Date.as_str()], Default::default())?;
+            let dates = utils::polars::column_date(&quotes, Column::Date.as_str())?;
+            let tickers = utils::polars::column_str(&quotes, Column::Ticker.as_str())?;
+            let rates = utils::polars::column_f64(&quotes, Column::Price.as_str())?;
+            for i in 0..quotes.height() {
+                // Find the origin currency, i.e "USD/GBP" -> "USD"
+                let (from, _) = tickers[i]
+                    .split_once('/')
+                    .with_context(|| format!("Failed to split {}", tickers[i]))?;
+                series
+                    .entry(from.to_owned())
+                    .or_default()
+                    .push((dates[i], rates[i]));
+            }
+        }
+        Ok(Self(series))
+    }
+
+    /// The rate of `date` or of the previous trading day, older dates use the first rate of the
+    /// series and currencies without a series, like the timeline currency itself, use 1.
+    fn at(&self, from: &str, date: NaiveDate) -> f64 {
+        let Some(series) = self.0.get(from) else {
+            return 1.0;
+        };
+        let index = series.partition_point(|(x, _)| *x <= date);
+        series
+            .get(index.saturating_sub(1))
+            .map_or(1.0, |(_, rate)| *rate)
+    }
+
+    fn convert(&self, from: &str, amount: f64, rate: ExchangeRate, date: NaiveDate) -> f64 {
+        let date = match rate {
+            ExchangeRate::Present(present_date) => {
+                present_date.unwrap_or(chrono::Local::now().date_naive())
+            }
+            ExchangeRate::Historical => date,
+        };
+        amount * self.at(from, date)
+    }
+}
+
+#[derive(Default)]
+struct Position {
+    /// Bought minus sold, without the splits.
+    qty: f64,
+    accrued_qty: f64,
+    average_price: f64,
+    currency: String,
+}
+
+/// The running totals of the orders until the current date, in the currency of each order.
+#[derive(Default)]
+struct State {
+    positions: BTreeMap<String, Position>,
+    cash: HashMap<String, f64>,
+    capital: HashMap<String, f64>,
+    historical_capital: f64,
+    dividends: f64,
+    liquidated_profit: f64,
+}
+
+impl State {
+    fn apply(&mut self, order: &Order, rates: &ExchangeRates, dividend_rate: ExchangeRate) {
+        let sign = match order.action {
+            Action::Buy | Action::Withdraw | Action::Tax | Action::Fee => -1.0,
+            Action::Ignore => 0.0,
+            _ => 1.0,
+        };
+        *self.cash.entry(order.currency.clone()).or_default() += sign * order.amount;
+
+        match order.action {
+            Action::Buy | Action::Sell | Action::CashInLieu | Action::Split => {
+                let position = self.positions.entry(order.ticker.clone()).or_default();
+                match order.action {
+                    Action::Buy => {
+                        let accrued_qty = position.accrued_qty + order.qty;
+                        position.average_price = (position.average_price * position.accrued_qty
+                            + order.price * order.qty)
+                            / accrued_qty;
+                        position.accrued_qty = accrued_qty;
+                        position.qty += order.qty;
+                        position.currency.clone_from(&order.currency);
+                    }
+                    Action::Sell | Action::CashInLieu => {
+                        self.liquidated_profit += rates.convert(
+                            &order.currency,
+                            (order.price - position.average_price) * order.qty,
+                            dividend_rate,
+                            order.date,
+                        );
+                        position.accrued_qty -= order.qty;
+                        position.qty -= order.qty;
+                        position.currency.clone_from(&order.currency);
+                    }
+                    _ => {
+                        position.average_price /= order.qty;
+                        position.accrued_qty *= order.qty;
+                    }
+                }
+            }
+            Action::Rename | Action::Merger | Action::SpinOff => {
+                self.carry(order, rates, dividend_rate);
+            }
+            Action::Dividend | Action::Interest | Action::Tax => {
+                self.dividends +=
+                    sign * rates.convert(&order.currency, order.amount, dividend_rate, order.date);
+            }
+            Action::Deposit | Action::Withdraw => {
+                *self.capital.entry(order.currency.clone()).or_default() += sign * order.amount;
+                self.historical_capital +=
+                    sign * order.amount * rates.at(&order.currency, order.date);
+            }
+            _ => (),
+        }
+    }
+
+    /// Carries the cost basis of a rename, merger or spin-off to the `NewTicker` like the
+    /// `AverageCost`, the cash of a merger is liquidated.
+    fn carry(&mut self, order: &Order, rates: &ExchangeRates, dividend_rate: ExchangeRate) {
+        let position = self.positions.entry(order.ticker.clone()).or_default();
+        let (avg, held) = (position.average_price, position.accrued_qty);
+        let carried = avg * held * carried_fraction(order.action, order.price);
+        match order.action {
+            Action::SpinOff if held > 0.0 => position.average_price -= carried / held,
+            Action::SpinOff => (),
+            _ => {
+                position.accrued_qty = 0.0;
+                position.qty = 0.0;
+            }
+        }
+        if order.action == Action::Merger && held > 0.0 && order.amount > 0.0 {
+            self.liquidated_profit += rates.convert(
+                &order.currency,
+                order.amount - (avg * held - carried),
+                dividend_rate,
+                order.date,
+            );
+        }
+
+        let received = held * order.qty;
+        if let (Some(new_ticker), true) = (&order.new_ticker, received > 0.0) {
+            let position = self.positions.entry(new_ticker.clone()).or_default();
+            let accrued_qty = position.accrued_qty + received;
+            position.average_price =
+                (position.average_price * position.accrued_qty + carried) / accrued_qty;
+            position.accrued_qty = accrued_qty;
+            position.qty += received;
+            position.currency.clone_from(&order.currency);
+        }
+    }
+
+    /// Values the positions at `date`, nothing is recorded before the first buy.
+    fn snapshot(
+        &self,
+        date: NaiveDate,
+        future_splits: &[&Order],
+        quotes: &Quotes,
+        rates: &ExchangeRates,
+        historical_rates: bool,
+        snapshots: &mut Snapshots,
+    ) {
+        let mut held = self
+            .positions
+            .iter()
+            .filter(|(_, position)| position.qty > 0.0)
+            .peekable();
+        if held.peek().is_none() {
+            return;
+        }
+
+        let (mut cost, mut market_value) = (0.0, 0.0);
+        for (ticker, position) in held {
+            let ratio: f64 = future_splits
+                .iter()
+                .filter(|split| split.ticker == *ticker)
+                .map(|split| split.qty)
+                .product();
+            let accrued_qty = position.accrued_qty * ratio;
+            if accrued_qty <= 0.0 {
+                continue;
+            }
+            cost +=
+                position.average_price * position.accrued_qty * rates.at(&position.currency, date);
+            if let Some((price, currency)) = quotes.at(ticker, date) {
+                market_value += price * accrued_qty * rates.at(currency, date);
+            }
+        }
+
+        let in_currency = |totals: &HashMap<String, f64>| -> f64 {
+            totals
+                .iter()
+                .map(|(currency, amount)| amount * rates.at(currency, date))
+                .sum()
+        };
+
+        snapshots.date.push(date);
+        snapshots.primary_capital.push(if historical_rates {
+            self.historical_capital
+        } else {
+            in_currency(&self.capital)
+        });
+        snapshots.portfolio_cost.push(cost);
+        snapshots.market_value.push(market_value);
+        snapshots.dividends.push(self.dividends);
+        snapshots.liquidated_profit.push(self.liquidated_profit);
+        snapshots.uninvested_cash.push(in_currency(&self.cash));
+    }
+}
+
+#[derive(Default)]
+struct Snapshots {
+    date: Vec<NaiveDate>,
+    primary_capital: Vec<f64>,
+    portfolio_cost: Vec<f64>,
+    market_value: Vec<f64>,
+    dividends: Vec<f64>,
+    liquidated_profit: Vec<f64>,
+    uninvested_cash: Vec<f64>,
+}
+
+impl Snapshots {
+    fn finish(self) -> Result<DataFrame> {
+        let paper_profit: Vec<_> = self
+            .market_value
+            .iter()
+            .zip(&self.portfolio_cost)
+            .map(|(market_value, cost)| market_value - cost)
+            .collect();
+
+        Ok(df!(
+            Column::Date.as_str() => self.date,
+            Column::PrimaryCapital.as_str() => self.primary_capital,
+            Column::PortfolioCost.as_str() => self.portfolio_cost,
+            Column::MarketValue.as_str() => self.market_value,
+            Column::PaperProfit.as_str() => paper_profit,
+            Column::Dividends.as_str() => self.dividends,
+            Column::LiquidatedProfit.as_str() => self.liquidated_profit,
+            Column::UninvestedCash.as_str() => self.uninvested_cash,
+        )?)
+    }
 }
 
 #[cfg(test)]
 mod unittest {
     use super::*;
+    use crate::dividends::Dividends;
+    use crate::liquidated;
+    use crate::portfolio::Portfolio;
     use crate::schema::Column;
     use crate::scraper::SearchPeriod;
+    use crate::summary::Summary;
+    use crate::uninvested;
     use crate::utils;
     use std::fs::File;
     use std::path::Path;
@@ -156,4 +715,145 @@ mod unittest {
             output.as_os_str().to_str().unwrap()
         );
     }
+
+    #[test]
+    fn timeline_benchmark_success() {
+        let orders = utils::test::generate_mocking_orders();
+        let mut scraper = utils::test::mock::Scraper::new().with_history(
+            "SPY",
+            &[
+                ("2024-03-01", 100.0),
+                ("2024-06-01", 110.0),
+                ("2024-09-01", 121.0),
+            ],
+        );
+
+        let data = scraper
+            .with_ticker(&["GOOGL".to_owned(), "APPL".to_owned()], None)
+            .load_blocking(SearchPeriod::new(None, None, None))
+            .unwrap();
+
+        let result = Timeline::from_orders(orders.clone(), schema::Currency::USD)
+            .with_benchmark("SPY", schema::Country::Usa)
+            .summary(&mut scraper, &data, 30, Some("2024-09-27"))
+            .unwrap()
+            .lazy()
+            .select([
+                col(Column::Date.as_str()).cast(DataType::String),
+                col(Column::BenchmarkValue.as_str()),
+                col(Column::BenchmarkReturn.as_str()),
+            ])
+            .collect()
+            .unwrap();
+
+        // The single deposit of 1000 buys 10 shares at 100.
+        let expected = df!(
+            Column::Date.as_str() => &[
+                "2024-04-13", "2024-05-13", "2024-06-12", "2024-07-12",
+                "2024-08-11", "2024-09-10", "2024-09-27",
+            ],
+            Column::BenchmarkValue.as_str() => &[1000.0, 1000.0, 1100.0, 1100.0, 1100.0, 1210.0, 1210.0],
+            Column::BenchmarkReturn.as_str() => &[0.0, 0.0, 10.0, 10.0, 10.0, 21.0, 21.0],
+        )
+        .unwrap();
+
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn timeline_with_corporate_actions() {
+        let orders = utils::test::generate_corporate_action_orders();
+        let mut scraper = utils::test::mock::Scraper::new()
+            .with_history("META", &[("2023-06-30", 280.0)])
+            .with_history("ACQ", &[("2023-06-30", 50.0)]);
+
+        let data = scraper
+            .with_ticker(&["META".to_owned(), "ACQ".to_owned()], None)
+            .load_blocking(SearchPeriod::new(None, None, None))
+            .unwrap();
+
+        let result = Timeline::from_orders(orders, schema::Currency::USD)
+            .summary(&mut scraper, &data, 30, Some("2023-07-01"))
+            .unwrap()
+            .tail(Some(1))
+            .select([
+                Column::PortfolioCost.as_str(),
+                Column::MarketValue.as_str(),
+                Column::LiquidatedProfit.as_str(),
+            ])
+            .unwrap();
+
+        // META and ACQ keep the cost basis of FB and SPIN, the merger cash is liquidated.
+        let expected = df!(
+            Column::PortfolioCost.as_str() => &[4232.0],
+            Column::MarketValue.as_str() => &[6200.0],
+            Column::LiquidatedProfit.as_str() => &[232.0],
+        )
+        .unwrap();
+
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn timeline_matches_summary_in_other_currency() {
+        let orders = utils::test::generate_mocking_orders();
+        let date = "2024-09-27".parse().unwrap();
+        let rate = ExchangeRate::Present(Some(date));
+        let mut scraper = utils::test::mock::Scraper::new();
+
+        let data = scraper
+            .with_ticker(&["GOOGL".to_owned(), "APPL".to_owned()], None)
+            .load_blocking(SearchPeriod::new(None, None, None))
+            .unwrap();
+
+        let dividends = Dividends::try_from_orders(orders.clone())
+            .unwrap()
+            .normalize_currency(&mut scraper, schema::Currency::GBP, rate)
+            .unwrap()
+            .by_ticker()
+            .unwrap();
+        let cash = uninvested::Cash::from_orders(orders.clone())
+            .collect()
+            .unwrap();
+        let portfolio = Portfolio::try_from_orders(orders.clone(), Some(date))
+            .unwrap()
+            .with_quotes(&data.quotes)
+            .unwrap()
+            .with_average_price()
+            .unwrap()
+            .with_uninvested_cash(cash)
+            .normalize_currency(&mut scraper, schema::Currency::GBP)
+            .unwrap()
+            .paper_profit()
+            .with_dividends(dividends.clone())
+            .with_profit()
+            .collect()
+            .unwrap();
+        let profit = liquidated::Profit::from_orders(orders.clone())
+            .unwrap()
+            .normalize_currency(&mut scraper, schema::Currency::GBP, rate)
+            .unwrap()
+            .collect()
+            .unwrap();
+        let expected = Summary::from_portfolio(portfolio)
+            .unwrap()
+            .with_dividends(dividends)
+            .unwrap()
+            .with_capital_invested(orders.clone(), schema::Currency::GBP, &mut scraper, rate)
+            .unwrap()
+            .with_liquidated_profit(profit)
+            .unwrap()
+            .finish()
+            .collect()
+            .unwrap();
+
+        let result = Timeline::from_orders(orders, schema::Currency::GBP)
+            .summary(&mut scraper, &data, 30, Some("2024-09-27"))
+            .unwrap()
+            .tail(Some(1))
+            .select(expected.get_column_names())
+            .unwrap();
+
+        assert_eq!(expected, result);
+    }
 }
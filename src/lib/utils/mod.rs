@@ -2,3 +2,4 @@
 pub mod test;
 
 pub mod polars;
+pub mod tar;
@@ -10,6 +10,15 @@ pub fn epoc_to_date(column: &str) -> Expr {
 }
 
 pub fn str_to_date(column: &str) -> Expr {
+    str_to_datetime(column, true).cast(DataType::Date)
+}
+
+/// As `str_to_date`, but the dates it can't parse are null instead of failing.
+pub fn str_to_date_or_null(column: &str) -> Expr {
+    str_to_datetime(column, false).cast(DataType::Date)
+}
+
+fn str_to_datetime(column: &str, strict: bool) -> Expr {
     col(column)
         .str()
         .replace(
@@ -18,8 +27,15 @@ pub fn str_to_date(column: &str) -> Expr {
             false,
         )
         .str()
-        .to_datetime(None, None, StrptimeOptions::default(), lit("raise"))
-        .cast(DataType::Date)
+        .to_datetime(
+            None,
+            None,
+            StrptimeOptions {
+                strict,
+                ..StrptimeOptions::default()
+            },
+            lit("raise"),
+        )
 }
 
 pub fn map_str_column<F>(name: &str, func: F) -> Expr
@@ -32,7 +48,7 @@ where
                 series
                     .str()?
                     .into_iter()
-                    .map(|row| func(row))
+                    .map(&func)
                     .collect::<ChunkedArray<_>>()
                     .into_series(),
             ))
@@ -85,6 +101,26 @@ pub fn column_str<'a>(df: &'a DataFrame, name: &str) -> Result<Vec<&'a str>> {
         .collect())
 }
 
+/// The values of an optional column, all `None` when the frame doesn't have it.
+pub fn column_opt_str<'a>(df: &'a DataFrame, name: &str) -> Result<Vec<Option<&'a str>>> {
+    match df.column(name) {
+        Ok(column) => Ok(column.str()?.into_iter().collect()),
+        Err(_) => Ok(vec![None; df.height()]),
+    }
+}
+
+/// The values of an optional numeric column, all `None` when the frame doesn't have it.
+pub fn column_opt_f64(df: &DataFrame, name: &str) -> Result<Vec<Option<f64>>> {
+    match df.column(name) {
+        Ok(column) => Ok(column
+            .cast(&DataType::Float64)?
+            .f64()?
+            .into_iter()
+            .collect()),
+        Err(_) => Ok(vec![None; df.height()]),
+    }
+}
+
 pub fn column_date(df: &DataFrame, name: &str) -> Result<Vec<chrono::NaiveDate>> {
     Ok(df
         .column(name)?
@@ -212,11 +248,35 @@ pub mod filter {
         buy().or(sell()).or(split())
     }
 
+    pub fn corporate_action() -> Expr {
+        col(Action.into())
+            .eq(lit(Rename.as_str()))
+            .or(col(Action.into()).eq(lit(Merger.as_str())))
+            .or(col(Action.into()).eq(lit(SpinOff.as_str())))
+            .or(col(Action.into()).eq(lit(CashInLieu.as_str())))
+    }
+
+    /// Sells and the corporate actions paid in cash.
+    pub fn disposal() -> Expr {
+        sell()
+            .or(col(Action.into()).eq(lit(CashInLieu.as_str())))
+            .or(col(Action.into())
+                .eq(lit(Merger.as_str()))
+                .and(col(Amount.into()).gt(lit(0))))
+    }
+
     pub fn deposit_and_withdraw() -> Expr {
         col(Action.into())
             .eq(lit(Deposit.as_str()))
             .or(col(Action.into()).eq(lit(Withdraw.as_str())))
     }
+
+    /// The orders of the account, and the ones without account, like the scraped splits.
+    pub fn account(name: &str) -> Expr {
+        col(Account.into())
+            .eq(lit(name.to_owned()))
+            .or(col(Account.into()).is_null())
+    }
 }
 
 pub mod transform {
@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use std::io::{Read, Write};
+
+/// Writes the files as a gzip compressed tar archive, the names can have folders.
+pub fn write(files: &[(String, Vec<u8>)], writer: impl Write) -> Result<()> {
+    let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+        writer,
+        flate2::Compression::default(),
+    ));
+    for (name, content) in files {
+        let mut header = tar::Header::new_ustar();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, name, content.as_slice())
+            .with_context(|| format!("Failed to archive {name}"))?;
+    }
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Reads the regular files of a gzip compressed tar archive.
+pub fn read(reader: impl Read) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(reader));
+    let mut files = Vec::new();
+    for entry in archive.entries().context("Not a tar archive")? {
+        let mut entry = entry.context("Corrupted archive")?;
+        // Folders and links aren't needed.
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().into_owned();
+        let mut content = Vec::new();
+        entry
+            .read_to_end(&mut content)
+            .with_context(|| format!("Failed to read {name} of the archive"))?;
+        files.push((name, content));
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod unittest {
+    use super::*;
+
+    #[test]
+    fn write_and_read_success() {
+        let files = vec![
+            ("quotes.parquet".to_owned(), vec![1; 700]),
+            ("ranges.parquet".to_owned(), Vec::new()),
+        ];
+        let mut archive = Vec::new();
+        write(&files, &mut archive).unwrap();
+
+        assert_eq!(read(archive.as_slice()).unwrap(), files);
+    }
+}
@@ -11,19 +11,13 @@ pub mod fs {
     pub fn compare_files(file_path1: &Path, file_path2: &Path) -> Result<bool> {
         // Read the contents of the first file into a vector
         let contents1: Vec<_> = fs::read(file_path1)
-            .expect(&format!(
-                "Cant't read file {}",
-                file_path1.to_str().unwrap()
-            ))
+            .unwrap_or_else(|_| panic!("Cant't read file {}", file_path1.to_str().unwrap()))
             .into_iter()
             .filter(|x| *x != b'\r' && *x != b'\n')
             .collect();
         // Read the contents of the second file into a vector
         let contents2: Vec<_> = fs::read(file_path2)
-            .expect(&format!(
-                "Cant't read file {}",
-                file_path2.to_str().unwrap()
-            ))
+            .unwrap_or_else(|_| panic!("Cant't read file {}", file_path2.to_str().unwrap()))
             .into_iter()
             .filter(|x| *x != b'\r' && *x != b'\n')
             .collect();
@@ -32,6 +26,42 @@ pub mod fs {
     }
 }
 
+pub mod http {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+
+    /// Serves the file `<dir>/<s>.csv` for the requests with the query `?s=<s>` until the test
+    /// ends, the missing files are answered with "No data". Returns the base url.
+    pub fn serve_dir(dir: &str) -> String {
+        let dir = PathBuf::from(dir);
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind the test server");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+                let mut request = String::new();
+                BufReader::new(&stream).read_line(&mut request).unwrap();
+                let symbol = request
+                    .split(['?', '&', ' '])
+                    .find_map(|x| x.strip_prefix("s="))
+                    .unwrap_or_default();
+                let body = std::fs::read_to_string(dir.join(format!("{symbol}.csv")))
+                    .unwrap_or_else(|_| "No data".to_owned());
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+            }
+        });
+        url
+    }
+}
+
 pub mod mock {
 
     use crate::schema::Column;
@@ -46,6 +76,13 @@ pub mod mock {
         tickers: Vec<String>,
         countries: Vec<schema::Country>,
         map: HashMap<String, f64>,
+        history: HashMap<String, Vec<(&'static str, f64)>>,
+    }
+
+    impl Default for Scraper {
+        fn default() -> Self {
+            Self::new()
+        }
     }
 
     impl Scraper {
@@ -61,11 +98,22 @@ pub mod mock {
                     ("BRL/USD".into(), 0.21),
                     ("BRL/GBP".into(), 0.18),
                 ]),
+                history: HashMap::new(),
             }
         }
+
+        /// Replaces the single mocked price of `ticker` by a dated series.
+        pub fn with_history(mut self, ticker: &str, history: &[(&'static str, f64)]) -> Self {
+            self.history.insert(ticker.into(), history.to_vec());
+            self
+        }
     }
 
     impl IScraper for Scraper {
+        fn name(&self) -> &'static str {
+            "Mock"
+        }
+
         fn with_ticker(
             &mut self,
             tickers: &[String],
@@ -89,31 +137,31 @@ pub mod mock {
         }
 
         async fn load(&mut self, _: SearchPeriod) -> Result<ScraperData> {
-            let len = self.tickers.len();
-            let prices: Vec<_> = self
-                .tickers
-                .iter()
-                .map(|x| {
-                    *self
-                        .map
-                        .get(x)
-                        .expect(&format!("{x} not found in {:?}", self.map))
-                })
-                .collect();
-
-            let currencies: Vec<_> = self
-                .countries
-                .iter()
-                .map(|x| {
-                    let currency: Currency = x.clone().into();
-                    currency.as_str()
-                })
-                .collect();
+            let (mut dates, mut tickers, mut prices, mut currencies) =
+                (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+            for (ticker, country) in self.tickers.iter().zip(self.countries.iter()) {
+                let currency: Currency = (*country).into();
+                let history = self.history.get(ticker).cloned().unwrap_or_else(|| {
+                    vec![(
+                        "2022-10-01",
+                        *self
+                            .map
+                            .get(ticker)
+                            .unwrap_or_else(|| panic!("{ticker} not found in {:?}", self.map)),
+                    )]
+                });
+                for (date, price) in history {
+                    dates.push(date);
+                    tickers.push(ticker.clone());
+                    prices.push(price);
+                    currencies.push(currency.as_str());
+                }
+            }
 
             let data = ScraperData::new(
                 df!(
-                    Column::Date.into() => vec!["2022-10-01"; len],
-                    Column::Ticker.into() => &self.tickers,
+                    Column::Date.into() => dates,
+                    Column::Ticker.into() => tickers,
                     Column::Price.into() => prices,
                     Column::Currency.into() => currencies,
                 )
@@ -174,3 +222,24 @@ pub fn generate_mocking_orders() -> DataFrame {
         .collect()
         .unwrap()
 }
+
+/// A position renamed, spun off, partially paid in cash and acquired.
+pub fn generate_corporate_action_orders() -> DataFrame {
+    let actions: &[&str] = &[Buy, Rename, Buy, SpinOff, CashInLieu, Merger].map(|x| x.into());
+    df! (
+        Date.into() => &["2022-01-03", "2022-06-09", "2022-07-01", "2023-01-02", "2023-03-01", "2023-06-01"],
+        Action.into() => actions,
+        Ticker.into() => &["FB", "FB", "META", "META", "SPIN", "SPIN"],
+        NewTicker.into() => &[None, Some("META"), None, Some("SPIN"), None, Some("ACQ")],
+        Qty.into() => &[10.0, 1.0, 10.0, 0.5, 2.0, 1.5],
+        Price.into() => &[300.0, 0.0, 160.0, 0.2, 100.0, 0.25],
+        Amount.into() => &[3000.0, 0.0, 1600.0, 0.0, 200.0, 400.0],
+        Country.into() => vec![Usa.as_str(); actions.len()],
+        Currency.into() => vec![schema::Currency::USD.as_str(); actions.len()],
+    )
+    .unwrap()
+    .lazy()
+    .with_column(super::polars::str_to_date(Date.into()).alias(Date.into()))
+    .collect()
+    .unwrap()
+}
@@ -0,0 +1,141 @@
+use crate::schema::Column;
+use crate::utils;
+use anyhow::Result;
+use chrono::NaiveDate;
+use polars::prelude::*;
+use std::collections::BTreeMap;
+
+const MAX_ITERATIONS: usize = 100;
+const TOLERANCE: f64 = 1e-9;
+
+fn npv(flows: &[(NaiveDate, f64)], rate: f64) -> f64 {
+    let start = flows[0].0;
+    flows
+        .iter()
+        .map(|(date, amount)| {
+            let years = (*date - start).num_days() as f64 / 365.0;
+            amount / (1.0 + rate).powf(years)
+        })
+        .sum()
+}
+
+fn npv_derivative(flows: &[(NaiveDate, f64)], rate: f64) -> f64 {
+    let start = flows[0].0;
+    flows
+        .iter()
+        .map(|(date, amount)| {
+            let years = (*date - start).num_days() as f64 / 365.0;
+            -years * amount / (1.0 + rate).powf(years + 1.0)
+        })
+        .sum()
+}
+
+/// The annualized internal rate of return of irregular cash flows, where money paid in is
+/// negative and money received is positive. Returns None when the flows don't change sign.
+pub fn xirr(flows: &[(NaiveDate, f64)]) -> Option<f64> {
+    let mut flows = flows.to_vec();
+    flows.sort_by_key(|(date, _)| *date);
+    if !flows.iter().any(|(_, x)| *x > 0.0) || !flows.iter().any(|(_, x)| *x < 0.0) {
+        return None;
+    }
+
+    // Newton converges fast from a sensible guess, bisection is the fallback.
+    let mut rate = 0.1;
+    for _ in 0..MAX_ITERATIONS {
+        let derivative = npv_derivative(&flows, rate);
+        if derivative == 0.0 || !derivative.is_finite() {
+            break;
+        }
+        let next = rate - npv(&flows, rate) / derivative;
+        if !next.is_finite() || next <= -1.0 {
+            break;
+        }
+        if (next - rate).abs() < TOLERANCE {
+            return Some(next);
+        }
+        rate = next;
+    }
+
+    let (mut low, mut high) = (-1.0 + TOLERANCE, 1.0);
+    while npv(&flows, low).signum() == npv(&flows, high).signum() {
+        high *= 2.0;
+        if high > 1e9 {
+            return None;
+        }
+    }
+    for _ in 0..MAX_ITERATIONS * 10 {
+        let middle = (low + high) / 2.0;
+        if npv(&flows, middle).signum() == npv(&flows, low).signum() {
+            low = middle;
+        } else {
+            high = middle;
+        }
+        if high - low < TOLERANCE {
+            break;
+        }
+    }
+    Some((low + high) / 2.0)
+}
+
+/// Computes the XIRR in percent of each ticker from a frame of dated cash flows
+/// with the columns Date, Ticker and Amount.
+pub fn by_ticker(flows: &DataFrame) -> Result<DataFrame> {
+    let dates = utils::polars::column_date(flows, Column::Date.as_str())?;
+    let tickers = utils::polars::column_str(flows, Column::Ticker.as_str())?;
+    let amounts = utils::polars::column_f64(flows, Column::Amount.as_str())?;
+
+    let mut grouped: BTreeMap<&str, Vec<(NaiveDate, f64)>> = BTreeMap::new();
+    for ((date, ticker), amount) in dates.into_iter().zip(tickers).zip(amounts) {
+        grouped.entry(ticker).or_default().push((date, amount));
+    }
+
+    let (tickers, rates): (Vec<_>, Vec<_>) = grouped
+        .into_iter()
+        .map(|(ticker, flows)| (ticker, xirr(&flows).map(|rate| rate * 100.0)))
+        .unzip();
+
+    Ok(df!(
+        Column::Ticker.as_str() => tickers,
+        Column::Xirr.as_str() => rates,
+    )?)
+}
+
+#[cfg(test)]
+mod unittest {
+    use super::*;
+
+    fn date(date: &str) -> NaiveDate {
+        date.parse().unwrap()
+    }
+
+    #[test]
+    fn xirr_one_year() {
+        let rate = xirr(&[(date("2023-01-01"), -1000.0), (date("2024-01-01"), 1100.0)]).unwrap();
+        assert!((rate - 0.1).abs() < 1e-6, "{rate}");
+    }
+
+    #[test]
+    fn xirr_irregular_flows() {
+        // Reference value computed with the spreadsheet XIRR function.
+        let rate = xirr(&[
+            (date("2008-01-01"), -10000.0),
+            (date("2008-03-01"), 2750.0),
+            (date("2008-10-30"), 4250.0),
+            (date("2009-02-15"), 3250.0),
+            (date("2009-04-01"), 2750.0),
+        ])
+        .unwrap();
+        assert!((rate - 0.373362535).abs() < 1e-6, "{rate}");
+    }
+
+    #[test]
+    fn xirr_loss() {
+        let rate = xirr(&[(date("2023-01-01"), -1000.0), (date("2023-07-02"), 500.0)]).unwrap();
+        assert!((rate - -0.75).abs() < 1e-3, "{rate}");
+    }
+
+    #[test]
+    fn xirr_without_sign_change() {
+        assert_eq!(xirr(&[(date("2023-01-01"), -1000.0)]), None);
+    }
+}
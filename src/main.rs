@@ -4,17 +4,27 @@ use anyhow::Result;
 
 use polars::prelude::*;
 
-use reis_finance_lib::broker::{self, IBroker, Schwab, Trading212};
+use reis_finance_lib::broker::{
+    self, Degiro, Generic, IBroker, InteractiveBrokers, Schwab, Trading212, B3,
+};
+use reis_finance_lib::corporate_actions::CorporateActions;
+use reis_finance_lib::currency::ExchangeRate;
 use reis_finance_lib::dividends::Dividends;
 use reis_finance_lib::global_conf;
 use reis_finance_lib::googlesheet::GoogleSheet;
+use reis_finance_lib::history::History;
 use reis_finance_lib::liquidated;
+use reis_finance_lib::lots::{CostBasis, LotSelection};
 use reis_finance_lib::portfolio::Portfolio;
 use reis_finance_lib::schema;
-use reis_finance_lib::scraper::{self, Cache, Yahoo};
+use reis_finance_lib::scraper::{
+    self, Cache, Fallback, Offline, Overrides, Provider, Stooq, Yahoo,
+};
 use reis_finance_lib::summary::Summary;
+use reis_finance_lib::tax;
 use reis_finance_lib::timeline::Timeline;
 use reis_finance_lib::uninvested;
+use reis_finance_lib::utils;
 
 use clap::Parser;
 use std::path::PathBuf;
@@ -28,14 +38,70 @@ struct Args {
     #[arg(long, value_parser =  PathBuf::from_str)]
     trading212_orders: Option<PathBuf>,
 
+    /// The account name of the Trading 212 orders, i.e. ISA
+    #[arg(long)]
+    trading212_account: Option<String>,
+
     /// A folder with Schwab orders
     #[arg(long, value_parser =  PathBuf::from_str)]
     schwab_orders: Option<PathBuf>,
 
+    /// The account name of the Schwab orders
+    #[arg(long)]
+    schwab_account: Option<String>,
+
+    /// A folder with Degiro Transactions.csv and Account.csv exports
+    #[arg(long, value_parser =  PathBuf::from_str)]
+    degiro_orders: Option<PathBuf>,
+
+    /// The account name of the Degiro orders
+    #[arg(long)]
+    degiro_account: Option<String>,
+
+    /// A folder with orders of any broker, described by the `--mapping` file
+    #[arg(long, value_parser =  PathBuf::from_str, requires = "mapping")]
+    generic_orders: Option<PathBuf>,
+
+    /// A json file mapping the columns of the `--generic-orders` to the orders schema
+    #[arg(long, value_parser =  PathBuf::from_str)]
+    mapping: Option<PathBuf>,
+
+    /// The account name of the generic orders
+    #[arg(long)]
+    generic_account: Option<String>,
+
+    /// A folder with Interactive Brokers Flex Query statements (xml)
+    #[arg(long, value_parser =  PathBuf::from_str)]
+    ibkr_orders: Option<PathBuf>,
+
+    /// The account name of the Interactive Brokers orders
+    #[arg(long)]
+    ibkr_account: Option<String>,
+
+    /// A folder with the B3 investor portal "Negociação" and "Movimentação" exports (csv)
+    #[arg(long, value_parser =  PathBuf::from_str)]
+    b3_orders: Option<PathBuf>,
+
+    /// The account name of the B3 orders
+    #[arg(long)]
+    b3_account: Option<String>,
+
+    /// Produce the reports of each account, instead of the consolidated ones.
+    #[arg(long, default_value = "false")]
+    by_account: bool,
+
     /// A folder with Schwab orders
     #[arg(short, long)]
     timeline: Option<usize>,
 
+    /// A ticker to compare the timeline with, bought on each deposit date
+    #[arg(long)]
+    benchmark: Option<String>,
+
+    /// The country where the benchmark is listed
+    #[arg(long, value_parser = schema::Country::from_str, default_value = "Usa")]
+    benchmark_country: schema::Country,
+
     /// The currency to be used
     #[arg(short, long, value_parser =  schema::Currency::from_str, default_value = "USD")]
     currency: schema::Currency,
@@ -52,9 +118,92 @@ struct Args {
     #[arg(short, long, default_value = "false")]
     update: bool,
 
+    /// The price providers tried in order for each ticker: Yahoo, Stooq or Offline.
+    #[arg(long, value_parser = Provider::from_str, value_delimiter = ',')]
+    provider: Vec<Provider>,
+
+    /// Limit of the requests per second to Yahoo, the failed ones are retried with backoff.
+    #[arg(long, value_parser = positive_rate, default_value = "5")]
+    requests_per_second: f64,
+
+    /// Read the prices from a folder with quotes, splits and dividends files instead of the
+    /// network, the layout of the cache.
+    #[arg(long, value_parser = PathBuf::from_str)]
+    prices_dir: Option<PathBuf>,
+
     /// Filter-out transactions after the date.
     #[arg(short, long, value_parser = chrono::NaiveDate::from_str)]
     date: Option<chrono::NaiveDate>,
+
+    /// Convert capital, dividends and profit with the exchange rate of each transaction date.
+    #[arg(long, default_value = "false")]
+    historical_fx: bool,
+
+    /// How the cost of the sold shares is computed: AverageCost, Fifo, Lifo or HighestCost.
+    #[arg(long, value_parser = CostBasis::from_str, default_value = "AverageCost")]
+    cost_basis: CostBasis,
+
+    /// A csv file with the lots sold by specific sells (Date, Ticker, AcquisitionDate, Qty).
+    #[arg(long, value_parser = PathBuf::from_str)]
+    lot_selection: Option<PathBuf>,
+
+    /// Ignore the orders with unknown actions and report them at the end, instead of failing.
+    #[arg(long, default_value = "false")]
+    lenient: bool,
+
+    /// Compute the daily valuation of each position.
+    #[arg(long, default_value = "false")]
+    history: bool,
+
+    /// Compute the UK capital gains tax report.
+    #[arg(long, default_value = "false")]
+    uk_cgt: bool,
+
+    /// Compute the Brazilian monthly taxes (DARF) and the "Bens e Direitos" of the orders in BRL.
+    #[arg(long, default_value = "false")]
+    br_tax: bool,
+
+    /// Compute the US wash sales of the sold lots, requires a lot cost basis.
+    #[arg(long, default_value = "false")]
+    us_wash_sales: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Maintenance of the price cache.
+    #[command(subcommand)]
+    Cache(CacheCommand),
+}
+
+#[derive(clap::Subcommand, Debug, PartialEq)]
+enum CacheCommand {
+    /// List the cached tickers with the dates held and the rows of each kind of data.
+    List,
+    /// Remove the prices of a ticker, between the dates if given, to load them again.
+    Invalidate {
+        ticker: String,
+        #[arg(long, value_parser = chrono::NaiveDate::from_str)]
+        start: Option<chrono::NaiveDate>,
+        #[arg(long, value_parser = chrono::NaiveDate::from_str)]
+        end: Option<chrono::NaiveDate>,
+    },
+    /// Remove the tickers not in the orders.
+    Prune,
+    /// Write the cache to a tar.gz archive.
+    Export { file: PathBuf },
+    /// Replace the cache with an archive written by `cache export`.
+    Import { file: PathBuf },
+}
+
+/// A rate above zero, the interval between the requests is its inverse.
+fn positive_rate(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate.is_finite() => Ok(rate),
+        _ => Err(format!("{s:?} isn't a number above 0")),
+    }
 }
 
 fn main() -> Result<()> {
@@ -68,12 +217,68 @@ fn main() -> Result<()> {
         .start()?;
 
     let args: Args = Args::parse();
+    if let Some(Command::Cache(command)) = &args.command {
+        if *command != CacheCommand::Prune {
+            return cache_command(command, &[]);
+        }
+    }
     let mut orders: Vec<DataFrame> = Vec::new();
+    let mut unknown_actions = Vec::new();
 
     if let Some(schwab_orders) = &args.schwab_orders {
         println!("Loading schwab orders...");
-        let broker = Schwab::default();
+        let mut broker = Schwab::default().with_lenient(args.lenient);
+        if let Some(account) = &args.schwab_account {
+            broker = broker.with_account(account);
+        }
         orders.push(broker.load_from_dir(schwab_orders.as_path())?);
+        unknown_actions.extend(broker.unknown_actions());
+    }
+
+    if let Some(degiro_orders) = &args.degiro_orders {
+        println!("Loading degiro orders...");
+        let tickers_file = global_conf::get_config_dir().join("degiro_tickers.json");
+        let mut broker = if tickers_file.exists() {
+            Degiro::new(Degiro::tickers_from_file(&tickers_file)?)
+        } else {
+            Degiro::default()
+        }
+        .with_lenient(args.lenient);
+        if let Some(account) = &args.degiro_account {
+            broker = broker.with_account(account);
+        }
+        orders.push(broker.load_from_dir(degiro_orders.as_path())?);
+        unknown_actions.extend(broker.unknown_actions());
+    }
+
+    if let (Some(generic_orders), Some(mapping)) = (&args.generic_orders, &args.mapping) {
+        println!("Loading generic orders...");
+        let mut broker = Generic::from_file(mapping.as_path())?.with_lenient(args.lenient);
+        if let Some(account) = &args.generic_account {
+            broker = broker.with_account(account);
+        }
+        orders.push(broker.load_from_dir(generic_orders.as_path())?);
+        unknown_actions.extend(broker.unknown_actions());
+    }
+
+    if let Some(ibkr_orders) = &args.ibkr_orders {
+        println!("Loading interactive brokers orders...");
+        let mut broker = InteractiveBrokers::default().with_lenient(args.lenient);
+        if let Some(account) = &args.ibkr_account {
+            broker = broker.with_account(account);
+        }
+        orders.push(broker.load_from_dir(ibkr_orders.as_path())?);
+        unknown_actions.extend(broker.unknown_actions());
+    }
+
+    if let Some(b3_orders) = &args.b3_orders {
+        println!("Loading B3 orders...");
+        let mut broker = B3::default().with_lenient(args.lenient);
+        if let Some(account) = &args.b3_account {
+            broker = broker.with_account(account);
+        }
+        orders.push(broker.load_from_dir(b3_orders.as_path())?);
+        unknown_actions.extend(broker.unknown_actions());
     }
 
     if let Some(trading212_orders) = &args.trading212_orders {
@@ -83,33 +288,136 @@ fn main() -> Result<()> {
             &global_conf::get_config_dir().join("trading212_config.json"),
         );
 
-        let broker = Trading212::new(schema::Currency::GBP, Some(config));
+        let mut broker =
+            Trading212::new(schema::Currency::GBP, Some(config)).with_lenient(args.lenient);
+        if let Some(account) = &args.trading212_account {
+            broker = broker.with_account(account);
+        }
 
         orders.push(if args.update {
             broker.load_from_api(Some(trading212_orders.as_path()))?
         } else {
             broker.load_from_dir(trading212_orders.as_path())?
         });
+        unknown_actions.extend(broker.unknown_actions());
     }
 
-    if !orders.is_empty() {
-        execute(orders, &args)
-    } else {
+    if orders.is_empty() {
         anyhow::bail!("You must provide orders.")
     }
+    if let Some(Command::Cache(command)) = &args.command {
+        return cache_command(command, &orders);
+    }
+    if args.by_account {
+        let mut accounts: Vec<&str> = Vec::new();
+        for frame in &orders {
+            for account in utils::polars::column_str(frame, schema::Column::Account.as_str())? {
+                if !accounts.contains(&account) {
+                    accounts.push(account);
+                }
+            }
+        }
+        for account in accounts {
+            println!("Account {account}:");
+            execute(&orders, &args, Some(account))?;
+        }
+    } else {
+        execute(&orders, &args, None)?;
+    }
+
+    if !unknown_actions.is_empty() {
+        println!(
+            "Ignored {} orders with unknown actions:",
+            unknown_actions.len()
+        );
+        for unknown in unknown_actions {
+            println!("\t{unknown}");
+        }
+    }
+    Ok(())
 }
 
-fn execute(orders: Vec<impl IntoLazy>, args: &Args) -> Result<()> {
-    let mut scraper = if args.cache {
-        either::Right(Cache::new(Yahoo::new(), global_conf::get_cache_dir()))
+fn cache_command(command: &CacheCommand, orders: &[DataFrame]) -> Result<()> {
+    let cache = Cache::new((), global_conf::get_cache_dir());
+    match command {
+        CacheCommand::List => println!("{}", cache.tickers()?),
+        CacheCommand::Invalidate { ticker, start, end } => {
+            let removed = cache.invalidate(ticker, *start, *end)?;
+            println!("Removed {removed} rows of {ticker}");
+        }
+        CacheCommand::Prune => {
+            let mut tickers = Vec::new();
+            for frame in orders {
+                for ticker in utils::polars::column_str(frame, schema::Column::Ticker.as_str())? {
+                    tickers.push(ticker.to_owned());
+                }
+            }
+            let pruned = cache.prune(&tickers)?;
+            println!("Removed {} tickers: {}", pruned.len(), pruned.join(", "));
+        }
+        CacheCommand::Export { file } => cache.export(file)?,
+        CacheCommand::Import { file } => cache.import(file)?,
+    }
+    Ok(())
+}
+
+fn execute(orders: &[DataFrame], args: &Args, account: Option<&str>) -> Result<()> {
+    let mut kinds = args.provider.clone();
+    if kinds.is_empty() && args.prices_dir.is_none() {
+        kinds.push(Provider::Yahoo);
+    }
+    if args.prices_dir.is_some() && !kinds.contains(&Provider::Offline) {
+        kinds.push(Provider::Offline);
+    }
+    // The tickers missing in the prices dir are left to the other providers and the overrides.
+    let prices_file = global_conf::get_config_dir().join("price_overrides.csv");
+    let lenient = kinds.len() > 1 || prices_file.exists();
+    let mut providers = Vec::new();
+    for kind in kinds {
+        providers.push(match (kind, &args.prices_dir) {
+            (Provider::Yahoo, _) => either::Left(either::Left(
+                Yahoo::new().with_requests_per_second(args.requests_per_second)?,
+            )),
+            (Provider::Stooq, _) => either::Left(either::Right(Stooq::new())),
+            (Provider::Offline, Some(dir)) => {
+                either::Right(Offline::new(dir).with_lenient(lenient))
+            }
+            (Provider::Offline, None) => anyhow::bail!("The Offline provider needs --prices-dir"),
+        });
+    }
+    let scraper = if args.cache {
+        either::Left(Cache::new(
+            Fallback::new(providers),
+            global_conf::get_cache_dir(),
+        ))
     } else {
-        either::Left(Yahoo::new())
+        either::Right(Fallback::new(providers))
     };
+    // Outside of the cache, the changes to the overrides files apply in the next run.
+    let mut scraper = Overrides::new(scraper);
+    if prices_file.exists() {
+        scraper = scraper.with_prices(&prices_file)?;
+    }
+    let rates_file = global_conf::get_config_dir().join("fx_overrides.csv");
+    if rates_file.exists() {
+        scraper = scraper.with_rates(&rates_file)?;
+    }
 
     let mut df = LazyFrame::default();
     for lf in orders {
-        df = concat([df, lf.lazy()], Default::default())?;
+        df = concat([df, lf.clone().lazy()], Default::default())?;
     }
+    if let Some(account) = account {
+        df = df.filter(utils::polars::filter::account(account));
+    }
+
+    let corporate_actions_file = global_conf::get_config_dir().join("corporate_actions.csv");
+    let corporate_actions = if corporate_actions_file.exists() {
+        CorporateActions::from_file(&corporate_actions_file)?
+    } else {
+        CorporateActions::default()
+    };
+    df = corporate_actions.merge_into(df)?;
 
     let current_date = args.date.unwrap_or(chrono::Local::now().date_naive());
     let mut orders = df
@@ -126,8 +434,18 @@ fn execute(orders: Vec<impl IntoLazy>, args: &Args) -> Result<()> {
     println!("Loading market data...");
     let scraped_data =
         tokio_test::block_on(scraper::load_data(orders.clone(), &mut scraper, args.date))?;
-    if scraped_data.splits.shape().0 > 0 {
-        let splits = scraped_data.splits.clone().lazy().select([
+    if !scraped_data.failures.is_empty() {
+        println!(
+            "No prices for {} tickers, their market price is 0:",
+            scraped_data.failures.len()
+        );
+        for (ticker, reason) in &scraped_data.failures {
+            println!("\t{ticker}: {reason}");
+        }
+    }
+    let splits = CorporateActions::filter_splits(orders.clone(), scraped_data.splits.clone())?;
+    if splits.shape().0 > 0 {
+        let splits = splits.lazy().select([
             col(schema::Column::Date.as_str()),
             lit(schema::Action::Split.as_str()).alias(schema::Column::Action.as_str()),
             col(schema::Column::Ticker.as_str()),
@@ -139,15 +457,30 @@ fn execute(orders: Vec<impl IntoLazy>, args: &Args) -> Result<()> {
             lit(schema::Country::NA.as_str()).alias(schema::Column::Country.as_str()),
             lit(schema::Currency::USD.as_str()).alias(schema::Column::Currency.as_str()),
             lit(schema::Type::Stock.as_str()).alias(schema::Column::Type.as_str()),
+            lit(NULL)
+                .cast(DataType::String)
+                .alias(schema::Column::Account.as_str()),
+            lit(NULL)
+                .cast(DataType::String)
+                .alias(schema::Column::Broker.as_str()),
+            lit(NULL)
+                .cast(DataType::String)
+                .alias(schema::Column::NewTicker.as_str()),
         ]);
         orders = concat([orders, splits], Default::default())?
             .sort([schema::Column::Date.as_str()], Default::default());
     }
 
+    let rate = if args.historical_fx {
+        ExchangeRate::Historical
+    } else {
+        ExchangeRate::Present(args.date)
+    };
+
     // TODO: This code is repeated in timeline.
     println!("Computing dividends...");
     let dividends = Dividends::try_from_orders(orders.clone())?
-        .normalize_currency(&mut scraper, args.currency, args.date)?
+        .normalize_currency(&mut scraper, args.currency, rate)?
         .by_ticker()?;
 
     println!("Computing uninvested cash...");
@@ -162,6 +495,7 @@ fn execute(orders: Vec<impl IntoLazy>, args: &Args) -> Result<()> {
         .with_uninvested_cash(cash.clone())
         .normalize_currency(&mut scraper, args.currency)?
         .paper_profit()
+        .with_xirr(&mut scraper, args.currency, rate)?
         .with_dividends(dividends.clone())
         .with_profit()
         .with_allocation()
@@ -169,37 +503,108 @@ fn execute(orders: Vec<impl IntoLazy>, args: &Args) -> Result<()> {
         .collect()?;
 
     println!("Computing profit...");
-    let profit = liquidated::Profit::from_orders(orders.clone())?
-        .normalize_currency(&mut scraper, args.currency, args.date)?
+    let selection = match &args.lot_selection {
+        Some(file) => LotSelection::from_file(file)?,
+        None => LotSelection::default(),
+    };
+    let wash_sales = if args.us_wash_sales {
+        println!("Computing US wash sales...");
+        let sold_lots = liquidated::Profit::from_orders_with(
+            orders.clone(),
+            args.cost_basis,
+            selection.clone(),
+        )?
+        .collect()?;
+        let wash_sales = tax::us::WashSales::try_from_profit(&sold_lots, orders.clone())?;
+        Some((wash_sales.adjusted_profit(), wash_sales.collect()?))
+    } else {
+        None
+    };
+    let profit = liquidated::Profit::from_orders_with(orders.clone(), args.cost_basis, selection)?
+        .normalize_currency(&mut scraper, args.currency, rate)?
         .collect()?;
 
     println!("Computing summary...");
     let summary = Summary::from_portfolio(portfolio.clone())?
         .with_dividends(dividends.clone())?
-        .with_capital_invested(orders.clone(), args.currency, &mut scraper, args.date)?
+        .with_capital_invested(orders.clone(), args.currency, &mut scraper, rate)?
         .with_liquidated_profit(profit.clone())?
+        .with_xirr(orders.clone(), args.currency, &mut scraper, rate, args.date)?
         .collect()?;
 
+    let history = if args.history {
+        println!("Computing history...");
+        Some(History::from_orders(orders.clone()).collect(&scraped_data.quotes)?)
+    } else {
+        None
+    };
+
+    let has_sells = || -> Result<bool> {
+        Ok(orders
+            .clone()
+            .filter(utils::polars::filter::sell())
+            .collect()?
+            .height()
+            > 0)
+    };
+    let capital_gains = if args.uk_cgt && !has_sells()? {
+        println!("No sells, skipping the UK capital gains.");
+        None
+    } else if args.uk_cgt {
+        println!("Computing UK capital gains...");
+        let capital_gains = tax::uk::CapitalGains::try_from_orders(orders.clone(), &mut scraper)?;
+        Some((capital_gains.by_tax_year()?, capital_gains.collect()?))
+    } else {
+        None
+    };
+
+    let brazil_tax = if args.br_tax {
+        println!("Computing Brazilian taxes...");
+        let capital_gains = tax::brazil::CapitalGains::try_from_orders(orders.clone())?;
+        Some((
+            capital_gains.by_month()?,
+            capital_gains.darf()?,
+            capital_gains.bens_e_direitos()?,
+        ))
+    } else {
+        None
+    };
+
     if args.show {
         dbg!(&summary);
         dbg!(&portfolio);
         dbg!(&profit);
         dbg!(&dividends);
+        if let Some(history) = &history {
+            dbg!(history);
+        }
+        if let Some(capital_gains) = &capital_gains {
+            dbg!(capital_gains);
+        }
+        if let Some(wash_sales) = &wash_sales {
+            dbg!(wash_sales);
+        }
+        if let Some(brazil_tax) = &brazil_tax {
+            dbg!(brazil_tax);
+        }
     } else {
         let mut sheet = GoogleSheet::new()?;
+        if let Some(account) = account {
+            sheet = sheet.with_tab(account);
+        }
         println!("Uploading summary...");
         sheet.update_sheets(&summary)?;
         println!("Uploading portfolio...");
         sheet.update_sheets(&portfolio)?;
 
-        if let Some(timeline) = args.timeline {
+        if let Some(interval) = args.timeline {
             println!("Computing timeline...");
-            let timeline = Timeline::from_orders(orders.clone(), args.currency).summary(
-                &mut scraper,
-                &scraped_data,
-                timeline,
-                None,
-            )?;
+            let mut timeline = Timeline::from_orders(orders.clone(), args.currency)
+                .with_historical_rates(args.historical_fx);
+            if let Some(benchmark) = &args.benchmark {
+                timeline = timeline.with_benchmark(benchmark, args.benchmark_country);
+            }
+            let timeline = timeline.summary(&mut scraper, &scraped_data, interval, None)?;
             println!("Uploading timeline...");
             sheet.update_sheets(&timeline)?;
         }
@@ -208,9 +613,33 @@ fn execute(orders: Vec<impl IntoLazy>, args: &Args) -> Result<()> {
         println!("Uploading dividends...");
         sheet.update_sheets(&dividends)?;
         let dividends = Dividends::try_from_orders(orders.clone())?
-            .normalize_currency(&mut scraper, args.currency, args.date)?
+            .normalize_currency(&mut scraper, args.currency, rate)?
             .collect()?;
         sheet.update_sheets(&dividends)?;
+
+        if let Some(history) = &history {
+            println!("Uploading history...");
+            sheet.update_sheets(history)?;
+        }
+
+        if let Some((report, disposals)) = &capital_gains {
+            println!("Uploading UK capital gains...");
+            sheet.update_sheets(report)?;
+            sheet.update_sheets(disposals)?;
+        }
+
+        if let Some((adjusted_profit, flagged)) = &wash_sales {
+            println!("Uploading US wash sales...");
+            sheet.update_sheets(adjusted_profit)?;
+            sheet.update_sheets(flagged)?;
+        }
+
+        if let Some((by_month, darf, bens_e_direitos)) = &brazil_tax {
+            println!("Uploading Brazilian taxes...");
+            sheet.update_sheets(by_month)?;
+            sheet.update_sheets(darf)?;
+            sheet.update_sheets(bens_e_direitos)?;
+        }
     }
 
     Ok(())